@@ -13,29 +13,503 @@
 //
 
 use std::collections::HashMap;
+use std::convert::Infallible;
 use std::fmt;
+use std::future::Future;
+use std::net::SocketAddr;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+use std::pin::Pin;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
 
+use aws_config::retry::RetryConfig as SdkRetryConfig;
+use aws_sdk_s3::error::HeadObjectError;
 use aws_sdk_s3::model::{
-    BucketLocationConstraint, CreateBucketConfiguration, Delete, Object, ObjectIdentifier,
+    BucketLocationConstraint, ChecksumMode, CompletedMultipartUpload, CompletedPart,
+    CreateBucketConfiguration, CsvInput, CsvOutput, Delete, ExpressionType, FileHeaderInfo,
+    GlacierJobParameters, InputSerialization, JsonInput, JsonOutput, JsonType, MetadataDirective,
+    Object, ObjectAttributes, ObjectIdentifier, ObjectVersion, OutputSerialization, RestoreRequest,
+    SelectObjectContentEventStream, ServerSideEncryption, StorageClass, Tag, Tagging,
 };
 use aws_sdk_s3::output::{
-    CreateBucketOutput, DeleteObjectOutput, DeleteObjectsOutput, GetObjectOutput, HeadObjectOutput,
+    CopyObjectOutput, CreateBucketOutput, DeleteObjectOutput, DeleteObjectsOutput,
+    GetObjectAttributesOutput, GetObjectOutput, HeadObjectOutput, ListObjectsV2Output,
 };
-use aws_sdk_s3::{output::PutObjectOutput, types::ByteStream, Client};
-use aws_sdk_s3::{Credentials, Endpoint, Region};
+use aws_sdk_s3::types::{ByteStream, DateTime, SdkError};
+use aws_sdk_s3::{Client, Credentials};
+use aws_sdk_s3::{Endpoint, Region};
+use aws_sigv4::http_request::SignableBody;
 use aws_smithy_client::hyper_ext;
+use aws_types::credentials::ProvideCredentials;
+use aws_types::endpoint::{AwsEndpoint, BoxError, CredentialScope, ResolveAwsEndpoint};
+use aws_types::SigningService;
+use futures::future::{join_all, BoxFuture, Shared};
+use futures::stream::{StreamExt, TryStreamExt};
+use futures::FutureExt;
+use http::Uri;
+use hyper::client::HttpConnector;
+use hyper_rustls::HttpsConnector;
+use tokio::sync::Semaphore;
+use zenoh::time::Timestamp;
 use zenoh::value::Value;
 use zenoh::Result as ZResult;
 use zenoh_buffers::buffer::SplitBuffer;
 use zenoh_core::zerror;
 
-use crate::config::TlsClientConfig;
+use crate::config::{
+    ConnectionConfig, ContentLengthMismatch, HeadMetadataFallback, HttpVersion, LogKeys,
+    MetadataEncoding, MultipartConfig, PayloadSigning, RegionMismatch, RestoreConfig,
+    RetryClassification, RetryConfig, S3Config, SseCustomerKey, SseKmsConfig, TlsClientConfig,
+    UploadSpillConfig, PROP_S3_ACCESS_KEY, PROP_S3_SECRET_KEY,
+};
+use crate::utils::{
+    content_checksum, content_checksum_of, decode_metadata_value, encode_metadata_value,
+    endpoint_authority, percent_encode_key, redact_key, retry_with_backoff, DeletedKeys,
+    EndpointFailover, GetCache, OperationMetrics, RateLimiter, RetryBudget, TimestampSynthesizer,
+    UploadBody, UploadBudget,
+};
+use crate::{
+    CONTENT_CHECKSUM_METADATA_KEY, FORMAT_VERSION_METADATA_KEY, FULL_KEY_METADATA_KEY,
+    ORIGINAL_LENGTH_METADATA_KEY, SPILLED_METADATA_KEY,
+};
+
+// Maximum amount of objects that can be deleted with a single DeleteObjects request.
+const MAX_DELETE_OBJECTS_BATCH: usize = 1000;
+
+// Key and version id markers from which the listing of the versions of the objects resumes.
+type VersionMarkers = (Option<String>, Option<String>);
+
+// Suffixes of the sidecar file holding the metadata of an exported object, and of the files being
+// written by an export.
+const EXPORT_SIDECAR_SUFFIX: &str = ".metadata.json";
+const EXPORT_PARTIAL_SUFFIX: &str = ".part";
+
+// Maximum size in bytes of the user-defined metadata of an object, measured as the sum of the
+// sizes of its keys and values.
+const MAX_METADATA_SIZE: usize = 2048;
+
+// Headers of the response carrying the ids of a request, to be provided to the AWS support.
+const REQUEST_ID_HEADER: &str = "x-amz-request-id";
+const EXTENDED_REQUEST_ID_HEADER: &str = "x-amz-id-2";
+
+// Header of a redirection response carrying the region where the bucket is located.
+const BUCKET_REGION_HEADER: &str = "x-amz-bucket-region";
+
+// Maximum time waited for the TCP connection to the endpoint by the health check.
+const HEALTH_CHECK_CONNECT_TIMEOUT: Duration = Duration::from_secs(5);
+
+// Algorithm of the server-side encryption with customer-provided keys (SSE-C).
+const SSE_CUSTOMER_ALGORITHM: &str = "AES256";
+
+/// Sets the SSE-C headers on the [builder] of a request when a customer-provided key is
+/// configured.
+macro_rules! with_sse_customer_key {
+    ($builder:expr, $sse_customer_key:expr) => {
+        match $sse_customer_key {
+            Some(sse_customer_key) => $builder
+                .sse_customer_algorithm(SSE_CUSTOMER_ALGORITHM)
+                .sse_customer_key(&sse_customer_key.key)
+                .sse_customer_key_md5(&sse_customer_key.key_md5),
+            None => $builder,
+        }
+    };
+}
+
+/// Sets the SSE-KMS headers on the [builder] of a request writing an object when the encryption
+/// with a KMS key is configured.
+macro_rules! with_sse_kms {
+    ($builder:expr, $sse_kms:expr) => {
+        match $sse_kms {
+            Some(sse_kms) => $builder
+                .server_side_encryption(ServerSideEncryption::AwsKms)
+                .set_ssekms_key_id(sse_kms.key_id.to_owned())
+                .set_ssekms_encryption_context(sse_kms.encryption_context.to_owned()),
+            None => $builder,
+        }
+    };
+}
+
+/// Sends the request built by [builder], with an unsigned payload if so configured by
+/// [payload_signing].
+macro_rules! send_with_payload_signing {
+    ($builder:expr, $payload_signing:expr) => {
+        async move {
+            match $payload_signing {
+                PayloadSigning::Signed => $builder.send().await,
+                PayloadSigning::Unsigned => {
+                    // The signer uses the payload found in the properties of the operation instead
+                    // of hashing the body.
+                    let operation = $builder.customize().await?.map_operation(|mut operation| {
+                        operation
+                            .properties_mut()
+                            .insert(SignableBody::UnsignedPayload);
+                        Ok::<_, Infallible>(operation)
+                    });
+                    match operation {
+                        Ok(operation) => operation.send().await,
+                        Err(never) => match never {},
+                    }
+                }
+            }
+        }
+    };
+}
+
+/// Resolver of a custom [endpoint] whose requests are signed with the service name
+/// [signing_name] instead of `s3`, which the SDK takes from the credential scope of the endpoint.
+#[derive(Debug)]
+struct SigningNameEndpoint {
+    endpoint: Endpoint,
+    signing_name: SigningService,
+}
+
+impl ResolveAwsEndpoint for SigningNameEndpoint {
+    fn resolve_endpoint(&self, _region: &Region) -> Result<AwsEndpoint, BoxError> {
+        // The signing region remains the region of the requests.
+        Ok(AwsEndpoint::new(
+            self.endpoint.to_owned(),
+            CredentialScope::builder()
+                .service(self.signing_name.to_owned())
+                .build(),
+        ))
+    }
+}
+
+/// HTTP connector reporting the outcome of its connections to the [EndpointFailover] of the
+/// client, so that the requests fail over to the next endpoint upon a connection failure.
+#[derive(Clone)]
+struct FailoverConnector {
+    inner: HttpsConnector<HttpConnector>,
+    failover: Arc<EndpointFailover>,
+    // Index of the endpoint of the connector.
+    index: usize,
+}
+
+impl hyper::service::Service<Uri> for FailoverConnector {
+    type Response = <HttpsConnector<HttpConnector> as hyper::service::Service<Uri>>::Response;
+    type Error = <HttpsConnector<HttpConnector> as hyper::service::Service<Uri>>::Error;
+    type Future =
+        Pin<Box<dyn Future<Output = Result<Self::Response, Self::Error>> + Send + 'static>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, uri: Uri) -> Self::Future {
+        let failover = self.failover.clone();
+        let index = self.index;
+        let connecting = self.inner.call(uri);
+        Box::pin(async move {
+            let result = connecting.await;
+            match &result {
+                Ok(_) => failover.on_connected(index),
+                Err(e) => failover.on_connection_failure(index, e),
+            }
+            result
+        })
+    }
+}
+
+/// Options of a server-side copy of an object, overriding the properties of the source object.
+#[derive(Default, Clone)]
+pub(crate) struct CopyObjectOptions {
+    /// Metadata replacing the one of the source object, which is preserved if None.
+    pub metadata: Option<HashMap<String, String>>,
+    /// Storage class of the copy (e.g. `STANDARD_IA`).
+    pub storage_class: Option<String>,
+    /// Server-side encryption of the copy, either `AES256` or `aws:kms`.
+    pub server_side_encryption: Option<String>,
+    /// Id of the KMS key used when the server-side encryption is `aws:kms`.
+    pub sse_kms_key_id: Option<String>,
+    /// Content-Type of the copy, only replaced along with the metadata.
+    pub content_type: Option<String>,
+    /// Content-Encoding of the copy, only replaced along with the metadata.
+    pub content_encoding: Option<String>,
+    /// Content-Disposition of the copy, only replaced along with the metadata.
+    pub content_disposition: Option<String>,
+    /// Content-Language of the copy, only replaced along with the metadata.
+    pub content_language: Option<String>,
+    /// Cache-Control of the copy, only replaced along with the metadata, by the one configured on
+    /// the storage if None.
+    pub cache_control: Option<String>,
+    /// Expiration date of the cached copy, only replaced along with the metadata.
+    pub expires: Option<DateTime>,
+}
+
+/// Serialization of the objects queried with [S3Client::select_object_content]: the records
+/// matching the expression are returned in the same format as the object.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub(crate) enum SelectFormat {
+    /// CSV object whose first line is a header naming the columns.
+    Csv,
+    /// JSON lines object, i.e. one JSON document per line.
+    Json,
+}
+
+impl std::str::FromStr for SelectFormat {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "csv" => Ok(SelectFormat::Csv),
+            "json" => Ok(SelectFormat::Json),
+            _ => {
+                Err(zerror!(r#"Unsupported select format '{s}', expected "csv" or "json"."#).into())
+            }
+        }
+    }
+}
+
+/// Object retrieved with [S3Client::get_object], with its payload fully read so that it can be
+/// shared among concurrent gets.
+pub(crate) struct ObjectContent {
+    pub metadata: Option<HashMap<String, String>>,
+    pub content_encoding: Option<String>,
+    pub content_type: Option<String>,
+    /// Last modification date of the object, in nanoseconds since the UNIX epoch.
+    pub last_modified: Option<i128>,
+    pub body: Vec<u8>,
+}
+
+// Get request shared among the concurrent gets of a same key. Its error is stringified to be
+// cloned for each of them.
+type SharedGet = Shared<BoxFuture<'static, Result<Option<Arc<ObjectContent>>, FetchError>>>;
+
+// Interval at which the restoration of an archived object is checked while a get waits for it.
+const RESTORE_POLL_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Error of the retrieval of an object, which may be shared by coalesced gets.
+#[derive(Clone)]
+enum FetchError {
+    /// The object is archived by its storage class and must be restored to be retrieved.
+    Archived,
+    /// The request was redirected to the region where the bucket is located.
+    Redirected {
+        region: String,
+        error: String,
+    },
+    Failed(String),
+}
+
+impl From<String> for FetchError {
+    fn from(e: String) -> Self {
+        FetchError::Failed(e)
+    }
+}
+
+impl fmt::Display for FetchError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FetchError::Archived => write!(f, "Object is archived and must be restored"),
+            FetchError::Redirected { error: e, .. } | FetchError::Failed(e) => write!(f, "{e}"),
+        }
+    }
+}
+
+/// Guard of an in-progress multipart upload, which aborts the upload when dropped before being
+/// disarmed, e.g. when the future of the put is cancelled or panics, so that the uploaded parts
+/// aren't left behind.
+struct MultipartUploadGuard {
+    client: Client,
+    bucket: String,
+    key: String,
+    upload_id: String,
+    log_keys: LogKeys,
+    armed: bool,
+}
+
+impl MultipartUploadGuard {
+    /// Aborts the upload upon its failure, waiting for the abort to be sent.
+    async fn abort(mut self) {
+        self.armed = false;
+        abort_multipart_upload(
+            &self.client,
+            &self.bucket,
+            &self.key,
+            &self.upload_id,
+            self.log_keys,
+        )
+        .await;
+    }
+
+    /// Disarms the guard once the upload is completed.
+    fn disarm(mut self) {
+        self.armed = false;
+    }
+}
+
+impl Drop for MultipartUploadGuard {
+    fn drop(&mut self) {
+        if !self.armed {
+            return;
+        }
+        let key = redact_key(&self.key, self.log_keys).into_owned();
+        let handle = match tokio::runtime::Handle::try_current() {
+            Ok(handle) => handle,
+            Err(_) => {
+                tracing::warn!(
+                    "Couldn't abort interrupted multipart upload '{}' of '{key}' without a \
+                    runtime, its parts are left behind.",
+                    self.upload_id
+                );
+                return;
+            }
+        };
+        tracing::debug!(
+            "Aborting interrupted multipart upload '{}' of '{key}'.",
+            self.upload_id
+        );
+        let client = self.client.clone();
+        let bucket = std::mem::take(&mut self.bucket);
+        let key = std::mem::take(&mut self.key);
+        let upload_id = std::mem::take(&mut self.upload_id);
+        let log_keys = self.log_keys;
+        handle.spawn(async move {
+            abort_multipart_upload(&client, &bucket, &key, &upload_id, log_keys).await
+        });
+    }
+}
+
+/// Clients of the endpoints communicating with a given region.
+struct RegionClients {
+    region: Option<String>,
+    // Clients of the endpoints, in order of preference, of which the active one is used.
+    clients: Vec<Client>,
+    // Clients of the endpoints sending the read requests with distinct credentials, if any.
+    read_clients: Vec<Client>,
+    // Client sending the HEAD requests of the listings to a distinct endpoint, if specified.
+    metadata_client: Option<Client>,
+}
+
+/// Settings from which the clients of the endpoints are built, kept to rebuild them for the
+/// region of the bucket upon a redirection.
+struct ClientParams {
+    credentials: Option<Credentials>,
+    read_credentials: Option<Credentials>,
+    sdk_retry: Option<SdkRetryConfig>,
+    // Endpoints in order of preference, None standing for the default AWS endpoint resolver.
+    endpoints: Vec<Option<String>>,
+    metadata_endpoint: Option<String>,
+    signing_name: Option<String>,
+    tls_config: Option<TlsClientConfig>,
+    connection_config: ConnectionConfig,
+    failover: Option<Arc<EndpointFailover>>,
+}
+
+impl ClientParams {
+    /// Builds the clients of the endpoints communicating with the [region].
+    async fn build(&self, region: Option<String>) -> ZResult<RegionClients> {
+        let mut clients = vec![];
+        let mut read_clients = vec![];
+        for (index, endpoint) in self.endpoints.iter().enumerate() {
+            let failover = self
+                .failover
+                .as_ref()
+                .map(|failover| (failover.clone(), index));
+            clients.push(
+                S3Client::build_client(
+                    self.sdk_retry.as_ref(),
+                    self.credentials.as_ref(),
+                    region.as_ref(),
+                    endpoint.to_owned(),
+                    self.signing_name.as_ref(),
+                    self.tls_config.to_owned(),
+                    &self.connection_config,
+                    failover.to_owned(),
+                )
+                .await?,
+            );
+            if let Some(read_credentials) = &self.read_credentials {
+                read_clients.push(
+                    S3Client::build_client(
+                        self.sdk_retry.as_ref(),
+                        Some(read_credentials),
+                        region.as_ref(),
+                        endpoint.to_owned(),
+                        self.signing_name.as_ref(),
+                        self.tls_config.to_owned(),
+                        &self.connection_config,
+                        failover,
+                    )
+                    .await?,
+                );
+            }
+        }
+        let metadata_client = match &self.metadata_endpoint {
+            Some(metadata_endpoint) => Some(
+                S3Client::build_client(
+                    self.sdk_retry.as_ref(),
+                    self.read_credentials.as_ref().or(self.credentials.as_ref()),
+                    region.as_ref(),
+                    Some(metadata_endpoint.to_owned()),
+                    self.signing_name.as_ref(),
+                    self.tls_config.to_owned(),
+                    &self.connection_config,
+                    None,
+                )
+                .await?,
+            ),
+            None => None,
+        };
+        Ok(RegionClients {
+            region,
+            clients,
+            read_clients,
+            metadata_client,
+        })
+    }
+}
 
 /// Client to communicate with the S3 storage.
 pub(crate) struct S3Client {
-    client: Client,
+    // Clients of the endpoints, replaced by clients of the region of the bucket when following a
+    // redirection to it.
+    region_clients: Mutex<Arc<RegionClients>>,
+    client_params: ClientParams,
+    failover: Option<Arc<EndpointFailover>>,
+    region_mismatch: RegionMismatch,
     bucket: String,
+    // Prefix of the shared bucket under which all the object keys are placed, if specified.
+    bucket_prefix: Option<String>,
     region: Option<String>,
+    multipart: Option<MultipartConfig>,
+    upload_spill: Option<UploadSpillConfig>,
+    // Bounds of the multipart uploads and of their parts in progress at once.
+    multipart_uploads: Option<Arc<Semaphore>>,
+    multipart_parts: Option<Arc<Semaphore>>,
+    bucket_retry: RetryConfig,
+    retry_budget: Option<RetryBudget>,
+    retry_classification: RetryClassification,
+    upload_budget: Option<UploadBudget>,
+    sse_customer_key: Option<SseCustomerKey>,
+    sse_kms: Option<SseKmsConfig>,
+    cache_control: Option<String>,
+    content_types: HashMap<String, String>,
+    spill_oversized_metadata: bool,
+    metadata_encoding: MetadataEncoding,
+    timestamp_metadata_key: String,
+    head_metadata_fallback: HeadMetadataFallback,
+    payload_signing: PayloadSigning,
+    verify_after_write: bool,
+    skip_unchanged_puts: bool,
+    in_flight_gets: Mutex<HashMap<String, SharedGet>>,
+    get_cache: Option<GetCache>,
+    maintenance_concurrency: usize,
+    deleted_keys: Option<DeletedKeys>,
+    rate_limiter: Option<Arc<RateLimiter>>,
+    content_length_mismatch: ContentLengthMismatch,
+    validate_response_checksum: bool,
+    restore_archived: Option<RestoreConfig>,
+    operation_deadline: Option<Duration>,
+    metrics: Option<Arc<OperationMetrics>>,
+    probe_bucket_on_denied: bool,
+    empty_bucket_concurrency: usize,
+    bucket_tags: HashMap<String, String>,
+    manage_bucket_tags: bool,
+    log_keys: LogKeys,
 }
 
 impl S3Client {
@@ -43,23 +517,155 @@ impl S3Client {
     ///
     /// # Arguments
     ///
-    /// * `credentials`: credentials to communicate with the storage
-    /// * `bucket`: name of the bucket/storage
+    /// * `config`: the S3Config of the storage, providing the credentials to communicate with the
+    ///     storage, the name of the bucket and the settings of the operations
     /// * `region`: region where the bucket/storage ought to be located
-    /// * `endpoint`: the endpoint where the storage is located, either an AWS endpoint
-    ///     (see https://docs.aws.amazon.com/general/latest/gr/s3.html) or a custom one if you are
-    ///     setting a MinIO instance. If None then the default AWS endpoint resolver will attempt
-    ///     to retrieve the endpoint based on the specified region.
+    /// * `endpoints`: the endpoints where the storage is located, either an AWS endpoint
+    ///     (see https://docs.aws.amazon.com/general/latest/gr/s3.html) or custom ones if you are
+    ///     setting a MinIO instance. If empty then the default AWS endpoint resolver will attempt
+    ///     to retrieve the endpoint based on the specified region. When several endpoints are
+    ///     specified, the requests are sent to the first one and fail over to the next ones upon
+    ///     connection failures (see [EndpointFailover]).
+    /// * `metadata_endpoint`: optional endpoint to which the HEAD requests retrieving the metadata
+    ///     of the listed objects are sent instead of `endpoint`, e.g. a caching gateway only
+    ///     serving the metadata while the payloads are fetched from the origin.
+    /// * `signing_name`: optional service name with which the requests are signed (SigV4) instead
+    ///     of `s3`, for the S3-compatible gateways expecting another one. Requires `endpoint`.
     /// * `tls_config`: optional TlsClientConfig to enable TLS security.
+    /// * `connection_config`: settings of the connections (keep-alive, idle timeout), which are
+    ///     otherwise left to the defaults of the SDK.
+    /// * `upload_budget`: optional UploadBudget shared among the storages of the volume to bound
+    ///     the amount of bytes being uploaded at once.
+    ///
+    /// When read credentials are specified in the configuration, the read requests (gets, HEAD
+    /// requests and listings) are sent with them while the other requests are sent with the
+    /// credentials of the storage.
+    ///
+    /// Fails if no credentials are specified in the configuration and the default provider chain
+    /// of the AWS SDK can't resolve any.
+    #[allow(clippy::too_many_arguments)]
     pub async fn new(
-        credentials: Credentials,
-        bucket: String,
+        config: &S3Config,
         region: Option<String>,
+        endpoints: Vec<String>,
+        metadata_endpoint: Option<String>,
+        signing_name: Option<String>,
+        tls_config: Option<TlsClientConfig>,
+        connection_config: &ConnectionConfig,
+        upload_budget: Option<UploadBudget>,
+    ) -> ZResult<Self> {
+        if config.payload_signing == PayloadSigning::Unsigned {
+            if let Some(endpoint) = endpoints.iter().find(|x| !x.starts_with("https://")) {
+                return Err(zerror!(
+                    "Unsigned payloads are only allowed over TLS, but the endpoint is \
+                    '{endpoint}'."
+                )
+                .into());
+            }
+        }
+        let failover = match endpoints.len() {
+            0 | 1 => None,
+            _ => Some(Arc::new(EndpointFailover::new(
+                endpoints.to_owned(),
+                connection_config
+                    .max_failovers
+                    .unwrap_or(endpoints.len() - 1),
+            ))),
+        };
+        // The default AWS endpoint resolver is used when no endpoint is specified.
+        let endpoints = match endpoints.is_empty() {
+            true => vec![None],
+            false => endpoints.into_iter().map(Some).collect(),
+        };
+        let client_params = ClientParams {
+            credentials: config.credentials.to_owned(),
+            read_credentials: config.read_credentials.to_owned(),
+            sdk_retry: config.sdk_retry.to_owned(),
+            endpoints,
+            metadata_endpoint,
+            signing_name,
+            tls_config,
+            connection_config: connection_config.to_owned(),
+            failover: failover.to_owned(),
+        };
+        let region_clients = client_params.build(region.to_owned()).await?;
+
+        Ok(S3Client {
+            region_clients: Mutex::new(Arc::new(region_clients)),
+            client_params,
+            failover,
+            region_mismatch: config.region_mismatch,
+            bucket: config.bucket.to_owned(),
+            bucket_prefix: config.bucket_prefix.to_owned(),
+            region,
+            multipart: config.multipart.to_owned(),
+            upload_spill: config.upload_spill.to_owned(),
+            multipart_uploads: config
+                .multipart
+                .as_ref()
+                .and_then(|multipart| multipart.max_concurrent_uploads)
+                .map(|permits| Arc::new(Semaphore::new(permits))),
+            multipart_parts: config
+                .multipart
+                .as_ref()
+                .and_then(|multipart| multipart.max_concurrent_parts)
+                .map(|permits| Arc::new(Semaphore::new(permits))),
+            bucket_retry: config.bucket_retry.to_owned(),
+            retry_budget: config.retry_budget.map(RetryBudget::new),
+            retry_classification: config.retry_classification.to_owned(),
+            upload_budget,
+            sse_customer_key: config.sse_customer_key.to_owned(),
+            sse_kms: config.sse_kms.to_owned(),
+            cache_control: config.cache_control.to_owned(),
+            content_types: config.content_types.to_owned(),
+            spill_oversized_metadata: config.spill_oversized_metadata,
+            metadata_encoding: config.metadata_encoding,
+            timestamp_metadata_key: config.timestamp_metadata_key.to_owned(),
+            head_metadata_fallback: config.head_metadata_fallback,
+            payload_signing: config.payload_signing,
+            verify_after_write: config.verify_after_write,
+            skip_unchanged_puts: config.skip_unchanged_puts,
+            in_flight_gets: Mutex::new(HashMap::new()),
+            get_cache: config.get_cache.to_owned().map(GetCache::new),
+            maintenance_concurrency: config.maintenance_concurrency,
+            deleted_keys: config.delete_grace_period.map(DeletedKeys::new),
+            rate_limiter: config
+                .rate_limit
+                .to_owned()
+                .map(|rate_limit| Arc::new(RateLimiter::new(rate_limit))),
+            restore_archived: config.restore_archived.to_owned(),
+            content_length_mismatch: config.content_length_mismatch,
+            validate_response_checksum: config.validate_response_checksum,
+            operation_deadline: config.operation_deadline,
+            metrics: config
+                .metrics_interval
+                .map(|_| Arc::new(OperationMetrics::default())),
+            probe_bucket_on_denied: config.probe_bucket_on_denied,
+            empty_bucket_concurrency: config.empty_bucket_concurrency,
+            bucket_tags: config.bucket_tags.to_owned(),
+            manage_bucket_tags: config.manage_bucket_tags,
+            log_keys: config.log_keys,
+        })
+    }
+
+    /// Builds the SDK client communicating with the [endpoint] with the [credentials], or those
+    /// of the default provider chain if None, whose connections are reported to the [failover]
+    /// between the endpoints along with the index of the endpoint, if any.
+    #[allow(clippy::too_many_arguments)]
+    async fn build_client(
+        sdk_retry: Option<&SdkRetryConfig>,
+        credentials: Option<&Credentials>,
+        region: Option<&String>,
         endpoint: Option<String>,
+        signing_name: Option<&String>,
         tls_config: Option<TlsClientConfig>,
-    ) -> Self {
-        let mut config_loader =
-            aws_config::ConfigLoader::default().credentials_provider(credentials);
+        connection_config: &ConnectionConfig,
+        failover: Option<(Arc<EndpointFailover>, usize)>,
+    ) -> ZResult<Client> {
+        let mut config_loader = aws_config::ConfigLoader::default();
+        if let Some(credentials) = credentials {
+            config_loader = config_loader.credentials_provider(credentials.to_owned());
+        }
 
         config_loader = match region {
             Some(ref region) => config_loader.region(Region::new(region.to_owned())),
@@ -73,190 +679,3471 @@ impl S3Client {
             }
         };
 
-        config_loader = match endpoint {
-            Some(endpoint) => config_loader.endpoint_resolver(Endpoint::immutable(
-                endpoint.parse().expect("Invalid endpoint: "),
-            )),
-            None => {
+        if let Some(sdk_retry) = sdk_retry {
+            config_loader = config_loader.retry_config(sdk_retry.to_owned());
+        }
+
+        let endpoint = endpoint
+            .map(|endpoint| {
+                endpoint
+                    .parse::<Uri>()
+                    .map_err(|e| zerror!("Invalid endpoint '{endpoint}': {e}"))
+            })
+            .transpose()?;
+        config_loader = match (endpoint, signing_name) {
+            (Some(endpoint), Some(signing_name)) => {
+                config_loader.endpoint_resolver(SigningNameEndpoint {
+                    endpoint: Endpoint::immutable(endpoint),
+                    signing_name: SigningService::from(signing_name.to_owned()),
+                })
+            }
+            (Some(endpoint), None) => {
+                config_loader.endpoint_resolver(Endpoint::immutable(endpoint))
+            }
+            (None, _) => {
                 tracing::debug!("Endpoint not specified.");
                 config_loader
             }
         };
 
-        let config = &config_loader.load().await;
+        let sdk_config = &config_loader.load().await;
 
-        let client = if let Some(tls_config) = tls_config {
-            Client::from_conf_conn(
-                config.into(),
-                hyper_ext::Adapter::builder().build(tls_config.https_connector),
-            )
-        } else {
-            Client::new(config)
+        // The credentials resolved by the default provider chain are checked upfront, as a
+        // failure would otherwise only surface on the first operation, with an opaque error.
+        if credentials.is_none() {
+            let provider = sdk_config
+                .credentials_provider()
+                .ok_or_else(|| zerror!("No credentials provider available."))?;
+            provider.provide_credentials().await.map_err(|e| {
+                zerror!(
+                    "Couldn't resolve credentials: '{}' and '{}' are not specified and the default \
+                    provider chain of the AWS SDK, which attempted the environment variables \
+                    (AWS_ACCESS_KEY_ID, AWS_SECRET_ACCESS_KEY), the profile files (~/.aws/config, \
+                    ~/.aws/credentials), the web identity token, the ECS container metadata and \
+                    the EC2 instance metadata (IMDS), failed: {}",
+                    PROP_S3_ACCESS_KEY,
+                    PROP_S3_SECRET_KEY,
+                    e
+                )
+            })?;
+        }
+
+        let https_connector = match tls_config {
+            Some(tls_config) => {
+                if tls_config.http_version == HttpVersion::Http2 {
+                    tracing::debug!("Preferring HTTP/2, falling back to HTTP/1.1.");
+                }
+                tls_config.https_connector
+            }
+            None if !connection_config.is_default() || failover.is_some() => {
+                connection_config.default_connector()
+            }
+            None => return Ok(Client::new(sdk_config)),
         };
+        let mut hyper_builder = hyper::Client::builder();
+        if let Some(idle_timeout) = connection_config.idle_timeout {
+            hyper_builder.pool_idle_timeout(idle_timeout);
+        }
+        let adapter_builder = hyper_ext::Adapter::builder().hyper_builder(hyper_builder);
+        Ok(match failover {
+            Some((failover, index)) => Client::from_conf_conn(
+                sdk_config.into(),
+                adapter_builder.build(FailoverConnector {
+                    inner: https_connector,
+                    failover,
+                    index,
+                }),
+            ),
+            None => {
+                Client::from_conf_conn(sdk_config.into(), adapter_builder.build(https_connector))
+            }
+        })
+    }
 
-        S3Client {
-            client,
-            bucket,
-            region,
+    /// Returns the client of the active endpoint.
+    fn client(&self) -> Client {
+        let region_clients = self.region_clients();
+        match &self.failover {
+            Some(failover) => region_clients.clients[failover.active()].clone(),
+            None => region_clients.clients[0].clone(),
         }
     }
 
-    /// Retrieves the object associated to the [key] specified.
-    pub async fn get_object(&self, key: &str) -> ZResult<GetObjectOutput> {
-        Ok(self
-            .client
-            .get_object()
-            .bucket(&self.bucket)
-            .key(key.to_string())
-            .send()
-            .await?)
+    /// Returns the client of the active endpoint sending the read requests, which is the client
+    /// of the other requests unless read credentials are specified.
+    fn read_client(&self) -> Client {
+        let region_clients = self.region_clients();
+        match (&self.failover, region_clients.read_clients.is_empty()) {
+            (_, true) => self.client(),
+            (Some(failover), false) => region_clients.read_clients[failover.active()].clone(),
+            (None, false) => region_clients.read_clients[0].clone(),
+        }
     }
 
-    /// Retrieves the head object (the header of the object without its actual payload) associated
-    /// to the [key] specified.
-    pub async fn get_head_object(&self, key: &str) -> ZResult<HeadObjectOutput> {
-        Ok(self
-            .client
-            .head_object()
-            .bucket(&self.bucket)
-            .key(key.to_string())
-            .send()
-            .await?)
+    /// Returns the client sending the HEAD requests of the listings, which is the client of the
+    /// read requests unless a metadata endpoint is specified.
+    fn metadata_client(&self) -> Client {
+        match &self.region_clients().metadata_client {
+            Some(metadata_client) => metadata_client.clone(),
+            None => self.read_client(),
+        }
     }
 
-    /// Performs a put operation on the storage on the key specified (which corresponds to the
-    /// name of the file to be created) with the [Sample] provided.
-    pub async fn put_object(
-        &self,
-        key: String,
-        value: Value,
-        metadata: Option<HashMap<String, String>>,
-    ) -> ZResult<PutObjectOutput> {
-        let body = ByteStream::from(value.payload.contiguous().to_vec());
-        Ok(self
-            .client
-            .put_object()
-            .bucket(self.bucket.to_owned())
-            .key(key)
-            .body(body)
-            .set_content_encoding(Some(value.encoding.to_string()))
-            .set_metadata(metadata)
-            .send()
-            .await?)
+    fn region_clients(&self) -> Arc<RegionClients> {
+        self.region_clients
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .clone()
     }
 
-    /// Performs a DELETE operation on the key specified.
-    pub async fn delete_object(&self, key: String) -> ZResult<DeleteObjectOutput> {
-        Ok(self
-            .client
-            .delete_object()
-            .bucket(self.bucket.to_owned())
-            .key(key)
-            .send()
-            .await?)
+    /// Rebuilds the clients for the [region] where a request was redirected to, if the
+    /// redirections are followed and the clients don't already communicate with that region.
+    /// Returns whether the failed request should be sent again.
+    async fn follow_region_redirect(&self, region: String) -> bool {
+        if self.region_mismatch != RegionMismatch::Follow {
+            return false;
+        }
+        if self.region_clients().region.as_ref() == Some(&region) {
+            // The clients were already rebuilt by a concurrent request.
+            return true;
+        }
+        match self.client_params.build(Some(region.to_owned())).await {
+            Ok(region_clients) => {
+                tracing::info!(
+                    "Requests to bucket '{self}' are redirected to region '{region}', using it."
+                );
+                *self
+                    .region_clients
+                    .lock()
+                    .unwrap_or_else(|e| e.into_inner()) = Arc::new(region_clients);
+                true
+            }
+            Err(e) => {
+                tracing::warn!("Couldn't follow redirection of bucket '{self}' to '{region}': {e}");
+                false
+            }
+        }
     }
 
-    /// Deletes the specified objects from the bucket.
-    pub async fn delete_objects_in_bucket(
-        &self,
-        objects: Vec<Object>,
-    ) -> ZResult<DeleteObjectsOutput> {
-        if objects.is_empty() {
-            return Ok(DeleteObjectsOutput::builder()
-                .set_deleted(Some(vec![]))
-                .build());
+    /// Sends the request built by [send] with the clients of the current region, then, upon a
+    /// redirection to the region of the bucket, follows it (see [follow_region_redirect]) and
+    /// sends the request once more.
+    async fn send_following_redirect<T, E, F, Fut>(&self, send: F) -> Result<T, SdkError<E>>
+    where
+        F: Fn() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E>>>,
+    {
+        let result = send().await;
+        if let Some(region) = result.as_ref().err().and_then(redirect_region) {
+            if self.follow_region_redirect(region).await {
+                return send().await;
+            }
         }
+        result
+    }
 
-        let mut object_identifiers: Vec<ObjectIdentifier> = vec![];
+    /// Returns the key of the object sent to S3 for the [key], placed under the bucket prefix if
+    /// specified. Fails for the keys with `.` or `..` segments, which could escape the bucket
+    /// prefix on the gateways normalizing the paths of the requests.
+    fn scoped_key(&self, key: &str) -> ZResult<String> {
+        match &self.bucket_prefix {
+            Some(bucket_prefix) => {
+                if key
+                    .split('/')
+                    .any(|segment| segment == "." || segment == "..")
+                {
+                    return Err(zerror!(
+                        "Key '{}' is out of the scope of bucket prefix '{bucket_prefix}'.",
+                        redact_key(key, self.log_keys)
+                    )
+                    .into());
+                }
+                Ok(format!("{bucket_prefix}{key}"))
+            }
+            None => Ok(key.to_string()),
+        }
+    }
 
-        for object in objects {
-            let identifier = ObjectIdentifier::builder()
-                .set_key(object.key().map(|x| x.to_string()))
-                .build();
-            object_identifiers.push(identifier);
+    /// Returns the prefix of the listings of the objects whose key starts with the [prefix],
+    /// placed under the bucket prefix if specified.
+    fn scoped_prefix(&self, prefix: Option<&str>) -> ZResult<Option<String>> {
+        match (prefix, &self.bucket_prefix) {
+            (Some(prefix), _) => self.scoped_key(prefix).map(Some),
+            (None, bucket_prefix) => Ok(bucket_prefix.to_owned()),
         }
+    }
 
-        let delete = Delete::builder()
-            .set_objects(Some(object_identifiers))
-            .build();
+    /// Returns the [key] of a listed object relative to the bucket prefix, if specified, or None
+    /// if the object is out of its scope.
+    fn unscoped_key<'a>(&self, key: &'a str) -> Option<&'a str> {
+        match &self.bucket_prefix {
+            Some(bucket_prefix) => key.strip_prefix(bucket_prefix.as_str()),
+            None => Some(key),
+        }
+    }
+
+    /// Returns the listed [objects] whose key is relative to the bucket prefix, if specified,
+    /// leaving out the objects out of its scope.
+    fn unscoped_objects(&self, objects: &[Object]) -> Vec<Object> {
+        objects
+            .iter()
+            .filter_map(|object| {
+                let key = self.unscoped_key(object.key()?)?.to_string();
+                let mut object = object.to_owned();
+                object.key = Some(key);
+                Some(object)
+            })
+            .collect()
+    }
 
-        Ok(self
-            .client
-            .delete_objects()
+    /// Checks that the read credentials, if specified, grant access to the bucket by listing a
+    /// single object.
+    pub async fn check_read_access(&self) -> ZResult<()> {
+        if self.region_clients().read_clients.is_empty() {
+            return Ok(());
+        }
+        self.read_client()
+            .list_objects_v2()
             .bucket(self.bucket.to_owned())
-            .delete(delete)
+            .set_prefix(self.bucket_prefix.to_owned())
+            .max_keys(1)
             .send()
-            .await?)
+            .await
+            .map_err(|e| {
+                let e = with_request_ids(e);
+                zerror!("Read credentials can't list bucket '{}': {e}", self.bucket)
+            })?;
+        Ok(())
     }
 
-    /// Asyncronically creates the bucket associated to this client upon construction on a new
-    /// tokio runtime.
-    /// Returns:
-    /// - Ok(Some(CreateBucketOutput)) in case the bucket was successfully created
-    /// - Ok(Some(None)) in case the `reuse_bucket` parameter is true and the bucket already exists
-    ///     and is owned by you
-    /// - Error in any other case
-    pub async fn create_bucket(&self, reuse_bucket: bool) -> ZResult<Option<CreateBucketOutput>> {
-        let constraint = self
-            .region
+    /// Returns the endpoint the requests are currently sent to, when failing over between several
+    /// endpoints.
+    pub fn active_endpoint(&self) -> Option<&str> {
+        self.failover
             .as_ref()
-            .map(|region| BucketLocationConstraint::from(region.as_str()));
-        let cfg = CreateBucketConfiguration::builder()
-            .set_location_constraint(constraint)
-            .build();
-        let result = self
-            .client
-            .create_bucket()
-            .create_bucket_configuration(cfg)
-            .bucket(self.bucket.to_owned())
-            .send()
-            .await;
+            .map(|failover| failover.active_endpoint())
+    }
 
-        match result {
-            Ok(output) => Ok(Some(output)),
-            Err(aws_sdk_s3::types::SdkError::ServiceError { err, raw }) => {
-                if err.is_bucket_already_owned_by_you() && reuse_bucket {
-                    return Ok(None);
-                };
-                Err(zerror!("Couldn't associate bucket '{self}': {raw:?}").into())
+    /// Retrieves the object associated to the [key] specified, returning None when there is no
+    /// object stored under that key.
+    ///
+    /// Concurrent gets of a same key are coalesced: while a GetObject request is in flight for
+    /// the key, the other gets of that key await its result instead of issuing their own request.
+    /// When the get cache is enabled, the object is first looked up in the cache. The whole get is
+    /// bounded by the operation deadline, if configured.
+    pub async fn get_object(&self, key: &str) -> ZResult<Option<Arc<ObjectContent>>> {
+        self.within_deadline("Get", key, self.get_object_unbounded(key))
+            .await
+    }
+
+    async fn get_object_unbounded(&self, key: &str) -> ZResult<Option<Arc<ObjectContent>>> {
+        let object_key = self.scoped_key(key)?;
+        if self.is_recently_deleted(key) {
+            return Ok(None);
+        }
+        let generation = match &self.get_cache {
+            Some(get_cache) => match get_cache.get(key) {
+                (Some(object), _) => return Ok(Some(object)),
+                (None, generation) => Some(generation),
+            },
+            None => None,
+        };
+        let get = {
+            let mut in_flight_gets = self
+                .in_flight_gets
+                .lock()
+                .map_err(|e| zerror!("Get operation failed for key '{key}': {e}"))?;
+            in_flight_gets
+                .entry(key.to_string())
+                .or_insert_with(|| {
+                    let rate_limiter = self.rate_limiter.clone();
+                    let fetch = Self::fetch_object(
+                        self.read_client(),
+                        self.bucket.clone(),
+                        object_key.to_owned(),
+                        self.sse_customer_key.clone(),
+                        self.content_length_mismatch,
+                        self.validate_response_checksum,
+                    );
+                    // Only the get issuing the request waits for its turn within the rate limit,
+                    // the coalesced gets don't send any request.
+                    async move {
+                        if let Some(rate_limiter) = rate_limiter {
+                            rate_limiter
+                                .acquire()
+                                .await
+                                .map_err(|e| FetchError::Failed(e.to_string()))?;
+                        }
+                        fetch.await
+                    }
+                    .boxed()
+                    .shared()
+                })
+                .clone()
+        };
+        let result = get.clone().await;
+
+        // The first of the coalesced gets to complete removes the request, so that the gets
+        // issued from now on see the latest state of the object.
+        if let Ok(mut in_flight_gets) = self.in_flight_gets.lock() {
+            if in_flight_gets
+                .get(key)
+                .map_or(false, |in_flight_get| in_flight_get.ptr_eq(&get))
+            {
+                in_flight_gets.remove(key);
             }
-            Err(err) => {
-                Err(zerror!("Couldn't create or associate bucket '{self}': {err:?}.").into())
+        }
+        // The redirected get is sent once more, on its own, to the region of the bucket.
+        let result = match result {
+            Err(FetchError::Redirected { region, error }) => {
+                match self.follow_region_redirect(region).await {
+                    true => {
+                        Self::fetch_object(
+                            self.read_client(),
+                            self.bucket.clone(),
+                            object_key,
+                            self.sse_customer_key.clone(),
+                            self.content_length_mismatch,
+                            self.validate_response_checksum,
+                        )
+                        .await
+                    }
+                    false => Err(FetchError::Failed(error)),
+                }
             }
+            result => result,
+        };
+        if let (Some(get_cache), Some(generation), Ok(Some(object))) =
+            (&self.get_cache, generation, &result)
+        {
+            get_cache.insert(key, object.clone(), generation);
+        }
+        match result {
+            Err(FetchError::Archived) => self.restore_archived_object(key).await,
+            result => result.map_err(|e| zerror!("{e}").into()),
         }
     }
 
-    /// Deletes the bucket associated to this storage.
+    /// Initiates the restoration of the archived object associated to the [key], if configured,
+    /// and waits up to the configured time for the object to be restored to retrieve it.
     ///
-    /// In order to fulfill this operation, all the contained files in the bucket are deleted.
-    pub async fn delete_bucket(&self) -> ZResult<()> {
-        let objects = self.list_objects_in_bucket().await?;
-        self.delete_objects_in_bucket(objects).await?;
-        self.client
-            .delete_bucket()
+    /// Restorations are asynchronous and take from minutes to hours depending on the retrieval
+    /// tier: when the object isn't restored in time, a distinct "restore in progress" error is
+    /// returned, and a later get retrieves the object once restored.
+    async fn restore_archived_object(&self, key: &str) -> ZResult<Option<Arc<ObjectContent>>> {
+        let restore = match &self.restore_archived {
+            Some(restore) => restore,
+            None => {
+                return Err(zerror!(
+                    "Object '{key}' is archived and must be restored before being retrieved."
+                )
+                .into())
+            }
+        };
+        let restore_request = RestoreRequest::builder()
+            .days(restore.days)
+            .glacier_job_parameters(
+                GlacierJobParameters::builder()
+                    .tier(restore.tier.to_owned())
+                    .build(),
+            )
+            .build();
+        self.limit_rate().await?;
+        match self
+            .client()
+            .restore_object()
             .bucket(&self.bucket)
+            .key(self.scoped_key(key)?)
+            .restore_request(restore_request)
             .send()
-            .await?;
-        tracing::debug!("Deleted bucket '{}'.", self.bucket.to_owned());
-        Ok(())
+            .await
+        {
+            Ok(_) => tracing::debug!(
+                "Initiated the restoration of archived object '{}'.",
+                redact_key(key, self.log_keys)
+            ),
+            Err(SdkError::ServiceError { err, .. })
+                if err.code() == Some("RestoreAlreadyInProgress") =>
+            {
+                tracing::debug!(
+                    "Restoration of archived object '{}' already in progress.",
+                    redact_key(key, self.log_keys)
+                )
+            }
+            Err(err) => {
+                return Err(zerror!("Couldn't restore archived object '{key}': {err}").into())
+            }
+        }
+
+        let deadline = Instant::now() + restore.max_wait;
+        loop {
+            let remaining = deadline.saturating_duration_since(Instant::now());
+            if remaining.is_zero() {
+                break;
+            }
+            tokio::time::sleep(remaining.min(RESTORE_POLL_INTERVAL)).await;
+            if self.is_restored(key).await? {
+                self.limit_rate().await?;
+                return Self::fetch_object(
+                    self.read_client(),
+                    self.bucket.clone(),
+                    self.scoped_key(key)?,
+                    self.sse_customer_key.clone(),
+                    self.content_length_mismatch,
+                    self.validate_response_checksum,
+                )
+                .await
+                .map_err(|e| zerror!("{e}").into());
+            }
+        }
+        Err(
+            zerror!("Object '{key}' is archived and its restoration is in progress, retry later.")
+                .into(),
+        )
     }
 
-    /// Lists all the objects contained in the bucket.
-    pub async fn list_objects_in_bucket(&self) -> ZResult<Vec<Object>> {
-        let response = self
-            .client
-            .list_objects_v2()
-            .bucket(self.bucket.to_owned())
-            .send()
-            .await?;
-        Ok(response.contents().unwrap_or_default().to_vec())
+    /// Checks whether the restoration of the archived object associated to the [key] completed,
+    /// as reported by the `x-amz-restore` header of the object.
+    async fn is_restored(&self, key: &str) -> ZResult<bool> {
+        let output = self.get_head_object(key).await?;
+        Ok(output.restore().map_or(false, |restore| {
+            restore.contains("ongoing-request=\"false\"")
+        }))
     }
-}
 
-impl std::fmt::Display for S3Client {
-    // It's sufficient to display the bucket name as we only have a single
-    // bucket and a single storage for each client.
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        write!(f, "{}", self.bucket)
+    /// Awaits the [future] of the [operation] on the [key], aborting it with an error once the
+    /// operation deadline expires, if configured. The cached object of the key is then
+    /// invalidated, as the aborted operation may have modified it. The operation is recorded in
+    /// the metrics of the client, if enabled.
+    async fn within_deadline<T>(
+        &self,
+        operation: &str,
+        key: &str,
+        future: impl Future<Output = ZResult<T>>,
+    ) -> ZResult<T> {
+        let started_at = Instant::now();
+        let result = match self.operation_deadline {
+            Some(deadline) => match tokio::time::timeout(deadline, future).await {
+                Ok(result) => result,
+                Err(_) => {
+                    self.invalidate_cached_object(key);
+                    Err(zerror!(
+                        "{operation} operation on '{key}' exceeded its deadline of {deadline:?}."
+                    )
+                    .into())
+                }
+            },
+            None => future.await,
+        };
+        if let Some(metrics) = &self.metrics {
+            metrics.record(operation, started_at.elapsed(), result.is_ok());
+        }
+        result
+    }
+
+    /// Returns the metrics of the operations of the client, if enabled.
+    pub fn metrics(&self) -> Option<&Arc<OperationMetrics>> {
+        self.metrics.as_ref()
+    }
+
+    /// Waits until the request about to be sent fits in the rate limit, if configured.
+    async fn limit_rate(&self) -> ZResult<()> {
+        match &self.rate_limiter {
+            Some(rate_limiter) => rate_limiter.acquire().await,
+            None => Ok(()),
+        }
+    }
+
+    /// Returns the amount of requests sent during the last second, if the rate limit is
+    /// configured.
+    pub fn current_rate(&self) -> Option<usize> {
+        self.rate_limiter
+            .as_ref()
+            .map(|rate_limiter| rate_limiter.current_rate())
+    }
+
+    /// Returns the amount of tokens currently available in the retry budget, if configured.
+    pub fn retry_budget_level(&self) -> Option<usize> {
+        self.retry_budget.as_ref().map(RetryBudget::level)
+    }
+
+    /// Checks whether the failed request may succeed if retried according to the overrides of the
+    /// retry classification matching the HTTP status code or the error of a service error, or else
+    /// according to the [default] classification.
+    fn is_retryable<E: fmt::Display>(&self, err: &SdkError<E>, default: bool) -> bool {
+        let overridden = match err {
+            SdkError::ServiceError { err, raw } => self
+                .retry_classification
+                .classify(&[raw.http().status().as_str(), &err.to_string()]),
+            _ => None,
+        };
+        overridden.unwrap_or(default)
+    }
+
+    /// Checks whether the [key] was deleted through this client during the delete grace period,
+    /// in which case its object is considered absent.
+    fn is_recently_deleted(&self, key: &str) -> bool {
+        self.deleted_keys
+            .as_ref()
+            .map_or(false, |deleted_keys| deleted_keys.contains(key))
+    }
+
+    /// Tracks the [key] as deleted for the delete grace period, if configured.
+    fn track_deleted_key(&self, key: &str) {
+        if let Some(deleted_keys) = &self.deleted_keys {
+            deleted_keys.insert(key);
+        }
+    }
+
+    /// Removes the object associated to the [key] from the get cache, if enabled, after a write.
+    fn invalidate_cached_object(&self, key: &str) {
+        if let Some(get_cache) = &self.get_cache {
+            get_cache.invalidate(key);
+        }
+    }
+
+    async fn fetch_object(
+        client: Client,
+        bucket: String,
+        key: String,
+        sse_customer_key: Option<SseCustomerKey>,
+        content_length_mismatch: ContentLengthMismatch,
+        validate_response_checksum: bool,
+    ) -> Result<Option<Arc<ObjectContent>>, FetchError> {
+        // The SDK validates the body against the checksum headers of the response, if any.
+        let request = client
+            .get_object()
+            .bucket(bucket)
+            .key(&key)
+            .set_checksum_mode(validate_response_checksum.then_some(ChecksumMode::Enabled));
+        let output: GetObjectOutput = match with_sse_customer_key!(request, &sse_customer_key)
+            .send()
+            .await
+        {
+            Ok(output) => output,
+            Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => return Ok(None),
+            Err(SdkError::ServiceError { err, .. }) if err.is_invalid_object_state() => {
+                return Err(FetchError::Archived)
+            }
+            Err(e) => {
+                return Err(match redirect_region(&e) {
+                    Some(region) => FetchError::Redirected {
+                        region,
+                        error: with_request_ids(e).to_string(),
+                    },
+                    None => FetchError::Failed(with_request_ids(e).to_string()),
+                })
+            }
+        };
+        let body = output
+            .body
+            .collect()
+            .await
+            .map_err(|e| format!("Couldn't process retrieved contents: {e}"))?;
+        let mut metadata = output.metadata;
+        let mut body = Vec::from(body.into_bytes());
+        // The Content-Length is reported as 0 when absent (e.g. with chunked responses).
+        if output.content_length > 0 && body.len() as i64 != output.content_length {
+            let mismatch = format!(
+                "Retrieved {} bytes for '{key}' while its Content-Length is {}",
+                body.len(),
+                output.content_length
+            );
+            match content_length_mismatch {
+                ContentLengthMismatch::Error => return Err(mismatch.into()),
+                ContentLengthMismatch::Warn => tracing::warn!("{mismatch}."),
+            }
+        }
+        // The checksum stored by `skip_unchanged_puts` covers the body including the spilled
+        // metadata, see [S3Client::put_object].
+        let stored_checksum = match metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(CONTENT_CHECKSUM_METADATA_KEY))
+        {
+            Some(checksum) if validate_response_checksum => Some(
+                decode_metadata_value(checksum.to_owned())
+                    .map_err(|e| format!("Couldn't decode the checksum of '{key}': {e}"))?,
+            ),
+            _ => None,
+        };
+        if let Some(stored_checksum) = stored_checksum {
+            if content_checksum(&body) != stored_checksum {
+                return Err(
+                    format!("Checksum of the body retrieved for '{key}' doesn't match").into(),
+                );
+            }
+        }
+        // Restores the metadata which didn't fit in the headers, see [S3Client::fit_metadata].
+        if let Some(metadata) = metadata.as_mut() {
+            if let Some(spilled_size) = metadata.remove(SPILLED_METADATA_KEY) {
+                let spilled_size = spilled_size
+                    .parse::<usize>()
+                    .ok()
+                    .filter(|size| *size <= body.len())
+                    .ok_or_else(|| format!("Invalid size of spilled metadata: {spilled_size}"))?;
+                let spilled: HashMap<String, String> =
+                    serde_json::from_slice(&body[..spilled_size])
+                        .map_err(|e| format!("Couldn't parse spilled metadata: {e}"))?;
+                metadata.extend(spilled);
+                body.drain(..spilled_size);
+            }
+            // The values are decoded whatever the configured encoding, as the objects may have
+            // been stored by another storage.
+            for value in metadata.values_mut() {
+                *value = decode_metadata_value(std::mem::take(value))
+                    .map_err(|e| format!("Couldn't decode the metadata of '{key}': {e}"))?;
+            }
+            if let Some(original_length) = metadata.get(ORIGINAL_LENGTH_METADATA_KEY) {
+                if original_length.parse::<usize>() != Ok(body.len()) {
+                    return Err(format!(
+                        "Retrieved {} bytes instead of the original length of {original_length}",
+                        body.len()
+                    )
+                    .into());
+                }
+            }
+        }
+        Ok(Some(Arc::new(ObjectContent {
+            metadata,
+            content_encoding: output.content_encoding,
+            content_type: output.content_type,
+            last_modified: output.last_modified.map(|date| date.as_nanos()),
+            body,
+        })))
+    }
+
+    /// Retrieves the head object (the header of the object without its actual payload) associated
+    /// to the [key] specified, from the metadata endpoint if specified.
+    pub async fn get_head_object(&self, key: &str) -> ZResult<HeadObjectOutput> {
+        let object_key = self.scoped_key(key)?;
+        self.limit_rate().await?;
+        self.send_head_object(&object_key)
+            .await
+            .map_err(with_request_ids)
+    }
+
+    /// Sends the HEAD request of the object stored under [object_key], to the metadata endpoint
+    /// if specified.
+    async fn send_head_object(
+        &self,
+        object_key: &str,
+    ) -> Result<HeadObjectOutput, SdkError<HeadObjectError>> {
+        self.send_following_redirect(|| {
+            let request = self
+                .metadata_client()
+                .head_object()
+                .bucket(&self.bucket)
+                .key(object_key.to_string());
+            with_sse_customer_key!(request, &self.sse_customer_key).send()
+        })
+        .await
+    }
+
+    /// Retrieves the user metadata of the object associated to the [key] with a HEAD request,
+    /// returning None when there is no object stored under that key (e.g. when it was deleted
+    /// since it was listed).
+    ///
+    /// When the HEAD response lacks the timestamp and the `ranged_get` fallback is configured, the
+    /// metadata is instead retrieved with a GET of the first byte of the object.
+    pub async fn get_object_metadata(&self, key: &str) -> ZResult<Option<HashMap<String, String>>> {
+        let object_key = self.scoped_key(key)?;
+        self.limit_rate().await?;
+        let metadata = match self.send_head_object(&object_key).await {
+            Ok(output) => output.metadata.unwrap_or_default(),
+            Err(SdkError::ServiceError { err, .. }) if err.is_not_found() => return Ok(None),
+            Err(err) => return Err(with_request_ids(err)),
+        };
+        let has_timestamp = metadata.contains_key(&self.timestamp_metadata_key);
+        if has_timestamp || self.head_metadata_fallback == HeadMetadataFallback::Disabled {
+            return Ok(Some(metadata));
+        }
+        tracing::debug!(
+            "No timestamp in the HEAD response of '{}', retrying with a GET.",
+            redact_key(key, self.log_keys)
+        );
+        self.limit_rate().await?;
+        let request = self
+            .read_client()
+            .get_object()
+            .bucket(&self.bucket)
+            .key(object_key);
+        let ranged_request = request.clone().range("bytes=0-0");
+        match with_sse_customer_key!(ranged_request, &self.sse_customer_key)
+            .send()
+            .await
+        {
+            Ok(output) => Ok(Some(output.metadata.unwrap_or_default())),
+            // An empty object has no first byte, hence is retrieved entirely.
+            Err(SdkError::ServiceError { err, .. }) if err.code() == Some("InvalidRange") => {
+                self.limit_rate().await?;
+                match with_sse_customer_key!(request, &self.sse_customer_key)
+                    .send()
+                    .await
+                {
+                    Ok(output) => Ok(Some(output.metadata.unwrap_or_default())),
+                    Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => Ok(None),
+                    Err(e) => Err(with_request_ids(e)),
+                }
+            }
+            Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => Ok(None),
+            Err(e) => Err(with_request_ids(e)),
+        }
+    }
+
+    /// Filters the content of the object associated to the [key] on the server side with the
+    /// given SQL [expression] (e.g. `SELECT * FROM s3object s WHERE s.city = 'Paris'`), returning
+    /// only the matching records.
+    pub async fn select_object_content(
+        &self,
+        key: &str,
+        expression: String,
+        format: SelectFormat,
+    ) -> ZResult<Vec<u8>> {
+        let (input, output) = match format {
+            SelectFormat::Csv => (
+                InputSerialization::builder()
+                    .csv(
+                        CsvInput::builder()
+                            .file_header_info(FileHeaderInfo::Use)
+                            .build(),
+                    )
+                    .build(),
+                OutputSerialization::builder()
+                    .csv(CsvOutput::builder().build())
+                    .build(),
+            ),
+            SelectFormat::Json => (
+                InputSerialization::builder()
+                    .json(JsonInput::builder().r#type(JsonType::Lines).build())
+                    .build(),
+                OutputSerialization::builder()
+                    .json(JsonOutput::builder().build())
+                    .build(),
+            ),
+        };
+        let request = self
+            .read_client()
+            .select_object_content()
+            .bucket(&self.bucket)
+            .key(self.scoped_key(key)?)
+            .expression(expression)
+            .expression_type(ExpressionType::Sql)
+            .input_serialization(input)
+            .output_serialization(output);
+        let mut output = with_sse_customer_key!(request, &self.sse_customer_key)
+            .send()
+            .await
+            .map_err(with_request_ids)?;
+
+        let mut records = vec![];
+        while let Some(event) = output.payload.recv().await? {
+            if let SelectObjectContentEventStream::Records(event) = event {
+                if let Some(payload) = event.payload() {
+                    records.extend_from_slice(payload.as_ref());
+                }
+            }
+        }
+        Ok(records)
+    }
+
+    /// Retrieves the attributes of the object associated to the [key] specified: its ETag,
+    /// checksum, parts, storage class and size, in a single request and without its payload.
+    /// Returns None when there is no object stored under that key.
+    pub async fn get_object_attributes(
+        &self,
+        key: &str,
+    ) -> ZResult<Option<GetObjectAttributesOutput>> {
+        let object_key = self.scoped_key(key)?;
+        self.limit_rate().await?;
+        let request = self
+            .read_client()
+            .get_object_attributes()
+            .bucket(&self.bucket)
+            .key(object_key)
+            .set_object_attributes(Some(vec![
+                ObjectAttributes::Etag,
+                ObjectAttributes::Checksum,
+                ObjectAttributes::ObjectParts,
+                ObjectAttributes::StorageClass,
+                ObjectAttributes::ObjectSize,
+            ]));
+        match with_sse_customer_key!(request, &self.sse_customer_key)
+            .send()
+            .await
+        {
+            Ok(output) => Ok(Some(output)),
+            Err(SdkError::ServiceError { err, .. }) if err.is_no_such_key() => Ok(None),
+            Err(err) => Err(with_request_ids(err)),
+        }
+    }
+
+    /// Retrieves the head object associated to the [key] specified, returning None when there is
+    /// no object stored under that key.
+    pub async fn get_head_object_if_exists(&self, key: &str) -> ZResult<Option<HeadObjectOutput>> {
+        let object_key = self.scoped_key(key)?;
+        if self.is_recently_deleted(key) {
+            return Ok(None);
+        }
+        self.limit_rate().await?;
+        let response = self
+            .send_following_redirect(|| {
+                let request = self
+                    .read_client()
+                    .head_object()
+                    .bucket(&self.bucket)
+                    .key(object_key.to_owned());
+                with_sse_customer_key!(request, &self.sse_customer_key).send()
+            })
+            .await;
+        match response {
+            Ok(output) => Ok(Some(output)),
+            Err(SdkError::ServiceError { err, .. }) if err.is_not_found() => Ok(None),
+            Err(err) => Err(with_request_ids(err)),
+        }
+    }
+
+    /// Performs a put operation on the storage on the key specified (which corresponds to the
+    /// name of the file to be created) with the [Sample] provided.
+    ///
+    /// The zenoh encoding of the value is not sent as the HTTP Content-Encoding header; it's up to
+    /// the caller to store it in the [metadata].
+    ///
+    /// When multipart uploads are configured and the payload exceeds the configured threshold,
+    /// the value is uploaded in several parts (see [S3Client::put_object_multipart]).
+    ///
+    /// When `verify_after_write` is enabled, the stored object is checked once uploaded (see
+    /// [S3Client::verify_written_object]).
+    ///
+    /// When `skip_unchanged_puts` is enabled and the stored object has the same body, only its
+    /// metadata is refreshed (see [S3Client::is_stored_unchanged]).
+    ///
+    /// The whole put is bounded by the operation deadline, if configured.
+    pub async fn put_object(
+        &self,
+        key: String,
+        value: Value,
+        metadata: Option<HashMap<String, String>>,
+    ) -> ZResult<()> {
+        let deadline_key = key.to_owned();
+        let put = self.put_object_unbounded(key, value, metadata);
+        self.within_deadline("Put", &deadline_key, put).await
+    }
+
+    async fn put_object_unbounded(
+        &self,
+        key: String,
+        value: Value,
+        metadata: Option<HashMap<String, String>>,
+    ) -> ZResult<()> {
+        self.limit_rate().await?;
+        // Holds the share of the upload budget until the upload is done.
+        let _permit = match &self.upload_budget {
+            Some(upload_budget) => Some(upload_budget.acquire(value.payload.len()).await?),
+            None => None,
+        };
+        let content_type = self.content_types.get(&value.encoding.to_string()).cloned();
+        let spill = self
+            .upload_spill
+            .as_ref()
+            .filter(|spill| value.payload.len() > spill.threshold);
+        let (metadata, body, checksum) = match spill {
+            Some(spill) => {
+                // The spilled metadata, if any, is the whole body of an empty payload.
+                let (metadata, prefix) = self.fit_metadata(&key, metadata, vec![])?;
+                let chunks = || std::iter::once(prefix.as_slice()).chain(value.payload.slices());
+                let checksum = self
+                    .skip_unchanged_puts
+                    .then(|| content_checksum_of(chunks()));
+                (metadata, UploadBody::spill(chunks(), spill)?, checksum)
+            }
+            None => {
+                let (metadata, payload) =
+                    self.fit_metadata(&key, metadata, value.payload.contiguous().to_vec())?;
+                let checksum = self.skip_unchanged_puts.then(|| content_checksum(&payload));
+                (metadata, UploadBody::Memory(payload), checksum)
+            }
+        };
+        let metadata = match checksum {
+            Some(checksum) => Some(with_content_checksum(metadata, checksum)),
+            None => metadata,
+        };
+        let timestamp = metadata
+            .as_ref()
+            .and_then(|metadata| metadata.get(&self.timestamp_metadata_key))
+            .cloned();
+        let size = body.size();
+        let unchanged =
+            self.skip_unchanged_puts && self.is_stored_unchanged(&key, metadata.as_ref()).await;
+        let result = match &self.multipart {
+            // The object is copied onto itself, replacing its metadata.
+            _ if unchanged => self
+                .copy_object(
+                    &key,
+                    &key,
+                    CopyObjectOptions {
+                        metadata: metadata.to_owned(),
+                        content_type,
+                        ..Default::default()
+                    },
+                )
+                .await
+                .map(|_| ()),
+            Some(multipart) if size > multipart.threshold => {
+                self.put_object_multipart(key.to_owned(), body, metadata, content_type, multipart)
+                    .await
+            }
+            _ => {
+                // The body is streamed anew if the request is sent once more, from the temporary
+                // file of a spilled body, which is kept until the upload is done.
+                let object_key = self.scoped_key(&key)?;
+                let (body, key, object_key) = (&body, &key, &object_key);
+                let (metadata, content_type) = (&metadata, &content_type);
+                self.send_following_redirect(move || async move {
+                    let stream = match body {
+                        UploadBody::Memory(payload) => ByteStream::from(payload.to_owned()),
+                        UploadBody::File { file, .. } => {
+                            match ByteStream::from_path(file.path()).await {
+                                Ok(stream) => stream,
+                                Err(e) => {
+                                    return Err(SdkError::ConstructionFailure(
+                                        zerror!("Couldn't read the spilled body of '{key}': {e}")
+                                            .into(),
+                                    ))
+                                }
+                            }
+                        }
+                    };
+                    let request = self
+                        .client()
+                        .put_object()
+                        .bucket(self.bucket.to_owned())
+                        .key(object_key.to_owned())
+                        .body(stream)
+                        .set_metadata(metadata.to_owned())
+                        .set_content_type(content_type.to_owned())
+                        .set_cache_control(self.cache_control.to_owned());
+                    let request = with_sse_customer_key!(request, &self.sse_customer_key);
+                    let request = with_sse_kms!(request, &self.sse_kms);
+                    send_with_payload_signing!(request, self.payload_signing).await
+                })
+                .await
+                .map(|_| ())
+                .map_err(with_request_ids)
+            }
+        };
+        // The object may have been overwritten even if the request failed.
+        self.invalidate_cached_object(&key);
+        result?;
+        if let Some(deleted_keys) = &self.deleted_keys {
+            deleted_keys.remove(&key);
+        }
+        if self.verify_after_write {
+            self.verify_written_object(&key, size, timestamp.as_deref())
+                .await?;
+        }
+        Ok(())
+    }
+
+    /// Checks whether the object stored under [key] has the same body as the one about to be
+    /// written, by comparing the checksums found in their metadata. Any failure to retrieve the
+    /// stored object is considered as a change, so that the value is uploaded.
+    async fn is_stored_unchanged(
+        &self,
+        key: &str,
+        metadata: Option<&HashMap<String, String>>,
+    ) -> bool {
+        let checksum = match metadata.and_then(|x| x.get(CONTENT_CHECKSUM_METADATA_KEY)) {
+            Some(checksum) => checksum,
+            None => return false,
+        };
+        match self.get_head_object_if_exists(key).await {
+            Ok(Some(head)) => {
+                let stored_checksum = head
+                    .metadata()
+                    .and_then(|metadata| metadata.get(CONTENT_CHECKSUM_METADATA_KEY));
+                if stored_checksum == Some(checksum) {
+                    tracing::debug!(
+                        "Body of '{}' is unchanged, only refreshing its metadata.",
+                        redact_key(key, self.log_keys)
+                    );
+                    return true;
+                }
+                false
+            }
+            Ok(None) => false,
+            Err(e) => {
+                tracing::debug!(
+                    "Couldn't compare the body of '{}' to the stored one: {e}",
+                    redact_key(key, self.log_keys)
+                );
+                false
+            }
+        }
+    }
+
+    /// Checks that the object written under [key] was actually stored, with the expected [size],
+    /// in order to detect the writes silently dropped (e.g. by a gateway).
+    ///
+    /// The size is read from the attributes of the object, without its payload nor metadata. Only
+    /// upon a mismatch is the object's timestamp retrieved: if it carries another timestamp than
+    /// the written one, it was overwritten by a concurrent write in the meantime.
+    async fn verify_written_object(
+        &self,
+        key: &str,
+        size: usize,
+        timestamp: Option<&str>,
+    ) -> ZResult<()> {
+        let attributes = self
+            .get_object_attributes(key)
+            .await
+            .map_err(|e| zerror!("Couldn't verify the write of '{key}': {e}"))?;
+        let stored_size = attributes.as_ref().map(|x| x.object_size());
+        if stored_size == Some(size as i64) {
+            return Ok(());
+        }
+        let head = self
+            .get_head_object_if_exists(key)
+            .await
+            .map_err(|e| zerror!("Couldn't verify the write of '{key}': {e}"))?
+            .ok_or_else(|| zerror!("Write of '{key}' was not stored: the object is missing."))?;
+        let stored_timestamp = head
+            .metadata()
+            .and_then(|metadata| metadata.get(&self.timestamp_metadata_key))
+            .map(|x| x.as_str());
+        if stored_timestamp != timestamp {
+            tracing::debug!(
+                "Object '{}' was overwritten before the verification of its write.",
+                redact_key(key, self.log_keys)
+            );
+            return Ok(());
+        }
+        Err(zerror!(
+            "Write of '{key}' was not stored correctly: the object is {} bytes long instead of \
+            {size}.",
+            stored_size.unwrap_or_else(|| head.content_length())
+        )
+        .into())
+    }
+
+    /// Checks that the [metadata] of the object to be stored under [key] fits within the size
+    /// allowed by S3, so that the put fails with a clear error rather than upon the request.
+    ///
+    /// When `spill_oversized_metadata` is enabled, oversized metadata is instead stored as a JSON
+    /// prefix of the [payload], except for the timestamp and the complete key of the object which
+    /// are needed by the listings, and the original length of the value. The size of that prefix
+    /// is stored under the [SPILLED_METADATA_KEY] metadata so that it can be restored by
+    /// [S3Client::fetch_object].
+    ///
+    /// With the `base64` metadata encoding, the values which can't be sent in a header are first
+    /// encoded (see [encode_metadata_value]), and are decoded by [S3Client::fetch_object].
+    fn fit_metadata(
+        &self,
+        key: &str,
+        metadata: Option<HashMap<String, String>>,
+        payload: Vec<u8>,
+    ) -> ZResult<(Option<HashMap<String, String>>, Vec<u8>)> {
+        let mut metadata = match metadata {
+            Some(metadata) => match self.metadata_encoding {
+                MetadataEncoding::Base64 => metadata
+                    .into_iter()
+                    .map(|(k, v)| (k, encode_metadata_value(v)))
+                    .collect(),
+                MetadataEncoding::Raw => metadata,
+            },
+            None => return Ok((None, payload)),
+        };
+        let size: usize = metadata.iter().map(|(k, v)| k.len() + v.len()).sum();
+        if size <= MAX_METADATA_SIZE {
+            return Ok((Some(metadata), payload));
+        }
+        if !self.spill_oversized_metadata {
+            return Err(zerror!(
+                "Metadata of '{key}' is {size} bytes long, exceeding the {MAX_METADATA_SIZE} bytes \
+                allowed by S3 (enable `spill_oversized_metadata` to store it in the object body)."
+            )
+            .into());
+        }
+
+        // The timestamp and the complete key are needed by the listings, which only retrieve the
+        // metadata of the objects, the original length reflects the logical size of the object and
+        // the format version tells how to read it.
+        let kept = [
+            metadata.remove_entry(&self.timestamp_metadata_key),
+            metadata.remove_entry(FULL_KEY_METADATA_KEY),
+            metadata.remove_entry(ORIGINAL_LENGTH_METADATA_KEY),
+            metadata.remove_entry(FORMAT_VERSION_METADATA_KEY),
+        ];
+        let spilled = serde_json::to_vec(&metadata)
+            .map_err(|e| zerror!("Couldn't serialize the metadata of '{key}': {e}"))?;
+        let mut metadata: HashMap<String, String> = kept.into_iter().flatten().collect();
+        metadata.insert(SPILLED_METADATA_KEY.to_string(), spilled.len().to_string());
+        let size: usize = metadata.iter().map(|(k, v)| k.len() + v.len()).sum();
+        if size > MAX_METADATA_SIZE {
+            return Err(zerror!(
+                "Metadata of '{key}' is {size} bytes long once spilled, exceeding the \
+                {MAX_METADATA_SIZE} bytes allowed by S3."
+            )
+            .into());
+        }
+        let mut body = spilled;
+        body.extend(payload);
+        Ok((Some(metadata), body))
+    }
+
+    /// Uploads the body in parts of `multipart.part_size` bytes which are sent concurrently, the
+    /// parts of a spilled body being read from its temporary file upon their upload.
+    ///
+    /// Each part is retried on its own with an exponential backoff; the multipart upload is only
+    /// aborted once a part has exhausted its retries, or when this future is dropped before the
+    /// upload is completed, unless `multipart.abort_on_error` is disabled.
+    ///
+    /// The upload waits for its turn when `multipart.max_concurrent_uploads` uploads are already
+    /// in progress, and each part when `multipart.max_concurrent_parts` parts are. At most
+    /// `multipart.max_concurrent_parts` (8 by default) parts of the upload are sent at once.
+    async fn put_object_multipart(
+        &self,
+        key: String,
+        body: UploadBody,
+        metadata: Option<HashMap<String, String>>,
+        content_type: Option<String>,
+        multipart: &MultipartConfig,
+    ) -> ZResult<()> {
+        // The parts are uploaded, and the upload completed or aborted, under the object key.
+        let key = self.scoped_key(&key)?;
+        let _permit = match &self.multipart_uploads {
+            Some(semaphore) => Some(semaphore.acquire().await.map_err(|e| {
+                zerror!("Couldn't acquire a slot for the multipart upload of '{key}': {e}")
+            })?),
+            None => None,
+        };
+        let request = self
+            .client()
+            .create_multipart_upload()
+            .bucket(self.bucket.to_owned())
+            .key(key.to_owned())
+            .set_metadata(metadata)
+            .set_content_type(content_type)
+            .set_cache_control(self.cache_control.to_owned());
+        let request = with_sse_kms!(request, &self.sse_kms);
+        let output = with_sse_customer_key!(request, &self.sse_customer_key)
+            .send()
+            .await
+            .map_err(with_request_ids)?;
+        let upload_id = output
+            .upload_id()
+            .ok_or_else(|| zerror!("No upload id received for the multipart upload of '{key}'."))?
+            .to_string();
+        let guard = multipart.abort_on_error.then(|| MultipartUploadGuard {
+            client: self.client(),
+            bucket: self.bucket.to_owned(),
+            key: key.to_owned(),
+            upload_id: upload_id.to_owned(),
+            log_keys: self.log_keys,
+            armed: true,
+        });
+
+        let size = body.size();
+        let parts = futures::stream::iter((0..size).step_by(multipart.part_size).enumerate().map(
+            |(index, start)| {
+                let range = start..size.min(start + multipart.part_size);
+                self.upload_part(&key, &upload_id, index as i32 + 1, &body, range, multipart)
+            },
+        ))
+        .buffer_unordered(multipart.upload_concurrent_parts)
+        .try_collect::<Vec<CompletedPart>>()
+        .await
+        .map(|mut parts| {
+            // The parts of the completed upload must be listed in ascending order.
+            parts.sort_by_key(|part| part.part_number());
+            parts
+        });
+
+        let parts = match parts {
+            Ok(parts) => parts,
+            Err(err) => {
+                if let Some(guard) = guard {
+                    guard.abort().await;
+                }
+                return Err(err);
+            }
+        };
+
+        let completed_upload = CompletedMultipartUpload::builder()
+            .set_parts(Some(parts))
+            .build();
+        if let Err(err) = self
+            .client()
+            .complete_multipart_upload()
+            .bucket(self.bucket.to_owned())
+            .key(key.to_owned())
+            .upload_id(upload_id.to_owned())
+            .multipart_upload(completed_upload)
+            .send()
+            .await
+        {
+            let err = with_request_ids(err);
+            if let Some(guard) = guard {
+                guard.abort().await;
+            }
+            return Err(zerror!("Couldn't complete multipart upload of '{key}': {err}").into());
+        }
+        if let Some(guard) = guard {
+            guard.disarm();
+        }
+        Ok(())
+    }
+
+    /// Uploads a single part of a multipart upload, retrying it upon failure as specified by
+    /// `multipart.part_retry`.
+    async fn upload_part(
+        &self,
+        key: &str,
+        upload_id: &str,
+        part_number: i32,
+        body: &UploadBody,
+        range: Range<usize>,
+        multipart: &MultipartConfig,
+    ) -> ZResult<CompletedPart> {
+        // Held across the retries, so that a retried part doesn't let another one start.
+        let _permit = match &self.multipart_parts {
+            Some(semaphore) => Some(semaphore.acquire().await.map_err(|e| {
+                zerror!("Couldn't acquire a slot for part {part_number} of '{key}': {e}")
+            })?),
+            None => None,
+        };
+        // Read once the part is allowed to start, so that only the parts in progress are held.
+        let chunk = body.range(range)?;
+        let output = retry_with_backoff(
+            &multipart.part_retry,
+            self.retry_budget.as_ref(),
+            &format!("Upload of part {part_number} of '{key}'"),
+            |err| self.is_retryable(err, true),
+            || {
+                let request = self
+                    .client()
+                    .upload_part()
+                    .bucket(self.bucket.to_owned())
+                    .key(key)
+                    .upload_id(upload_id)
+                    .part_number(part_number)
+                    .body(ByteStream::from(chunk.to_vec()));
+                let request = with_sse_customer_key!(request, &self.sse_customer_key);
+                send_with_payload_signing!(request, self.payload_signing)
+            },
+        )
+        .await
+        .map_err(|err| {
+            let err = with_request_ids(err);
+            zerror!("Upload of part {part_number} of '{key}' failed: {err}")
+        })?;
+        Ok(CompletedPart::builder()
+            .set_e_tag(output.e_tag().map(|x| x.to_string()))
+            .part_number(part_number)
+            .build())
+    }
+
+    /// Aborts the multipart upload so that the already uploaded parts are discarded.
+    async fn abort_multipart_upload(&self, key: &str, upload_id: &str) {
+        abort_multipart_upload(&self.client(), &self.bucket, key, upload_id, self.log_keys).await
+    }
+
+    /// Aborts the multipart uploads of the bucket which were initiated more than [max_age] ago,
+    /// e.g. because the storage uploading them was stopped or crashed before completing them,
+    /// so that their already uploaded parts don't keep being stored (and billed) forever.
+    ///
+    /// Returns the amount of stale uploads, the failures to abort them being logged.
+    pub async fn abort_stale_multipart_uploads(&self, max_age: Duration) -> ZResult<usize> {
+        let deadline = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_err(|e| zerror!("Couldn't read the system time: {e}"))?
+            .saturating_sub(max_age);
+        let mut stale_uploads = vec![];
+        let mut key_marker = None;
+        let mut upload_id_marker = None;
+        loop {
+            let response = self
+                .client()
+                .list_multipart_uploads()
+                .bucket(self.bucket.to_owned())
+                .set_prefix(self.bucket_prefix.to_owned())
+                .set_key_marker(key_marker)
+                .set_upload_id_marker(upload_id_marker)
+                .send()
+                .await
+                .map_err(with_request_ids)?;
+            for upload in response.uploads().unwrap_or_default() {
+                if let (Some(key), Some(upload_id), Some(initiated)) =
+                    (upload.key(), upload.upload_id(), upload.initiated())
+                {
+                    if (initiated.secs() as u64) < deadline.as_secs() {
+                        stale_uploads.push((key.to_string(), upload_id.to_string()));
+                    }
+                }
+            }
+            if !response.is_truncated() {
+                break;
+            }
+            key_marker = response.next_key_marker().map(|x| x.to_string());
+            upload_id_marker = response.next_upload_id_marker().map(|x| x.to_string());
+        }
+
+        join_all(stale_uploads.iter().map(|(key, upload_id)| {
+            tracing::debug!(
+                "Aborting stale multipart upload '{upload_id}' of '{}'.",
+                redact_key(key, self.log_keys)
+            );
+            self.abort_multipart_upload(key, upload_id)
+        }))
+        .await;
+        Ok(stale_uploads.len())
+    }
+
+    /// Copies the object stored under [src_key] to [dst_key] within the bucket on the server side,
+    /// without downloading it. The metadata, encryption and storage class of the copy can be
+    /// changed through the [options].
+    pub async fn copy_object(
+        &self,
+        src_key: &str,
+        dst_key: &str,
+        options: CopyObjectOptions,
+    ) -> ZResult<CopyObjectOutput> {
+        // The system headers (e.g. the cache control and the content type) are replaced along
+        // with the metadata.
+        let replace = options.metadata.is_some();
+        let metadata_directive = match replace {
+            true => MetadataDirective::Replace,
+            false => MetadataDirective::Copy,
+        };
+        let cache_control = options
+            .cache_control
+            .or_else(|| self.cache_control.to_owned());
+        let src_object_key = self.scoped_key(src_key)?;
+        let request = self
+            .client()
+            .copy_object()
+            .bucket(self.bucket.to_owned())
+            .copy_source(format!(
+                "{}/{}",
+                self.bucket,
+                percent_encode_key(&src_object_key)
+            ))
+            .key(self.scoped_key(dst_key)?)
+            .metadata_directive(metadata_directive)
+            .set_metadata(options.metadata)
+            .set_cache_control(cache_control.filter(|_| replace))
+            .set_content_type(options.content_type.filter(|_| replace))
+            .set_content_encoding(options.content_encoding.filter(|_| replace))
+            .set_content_disposition(options.content_disposition.filter(|_| replace))
+            .set_content_language(options.content_language.filter(|_| replace))
+            .set_expires(options.expires.filter(|_| replace))
+            .set_storage_class(options.storage_class.as_deref().map(StorageClass::from))
+            .set_server_side_encryption(
+                options
+                    .server_side_encryption
+                    .as_deref()
+                    .map(ServerSideEncryption::from),
+            )
+            .set_ssekms_key_id(options.sse_kms_key_id);
+        // The source object is decrypted with the customer-provided key, which also encrypts the
+        // copy unless another server-side encryption is requested.
+        let request = match &self.sse_customer_key {
+            Some(sse_customer_key) => request
+                .copy_source_sse_customer_algorithm(SSE_CUSTOMER_ALGORITHM)
+                .copy_source_sse_customer_key(&sse_customer_key.key)
+                .copy_source_sse_customer_key_md5(&sse_customer_key.key_md5),
+            None => request,
+        };
+        let request = match options.server_side_encryption {
+            Some(_) => request,
+            None => {
+                let request = with_sse_customer_key!(request, &self.sse_customer_key);
+                with_sse_kms!(request, &self.sse_kms)
+            }
+        };
+        let result = request.send().await;
+        self.invalidate_cached_object(dst_key);
+        result.map_err(with_request_ids)
+    }
+
+    /// Performs a DELETE operation on the key specified, bounded by the operation deadline if
+    /// configured.
+    pub async fn delete_object(&self, key: String) -> ZResult<DeleteObjectOutput> {
+        let deadline_key = key.to_owned();
+        let delete = self.delete_object_unbounded(key);
+        self.within_deadline("Delete", &deadline_key, delete).await
+    }
+
+    async fn delete_object_unbounded(&self, key: String) -> ZResult<DeleteObjectOutput> {
+        let object_key = self.scoped_key(&key)?;
+        self.limit_rate().await?;
+        let result = self
+            .send_following_redirect(|| {
+                self.client()
+                    .delete_object()
+                    .bucket(self.bucket.to_owned())
+                    .key(object_key.to_owned())
+                    .send()
+            })
+            .await;
+        self.invalidate_cached_object(&key);
+        let output = result.map_err(with_request_ids)?;
+        self.track_deleted_key(&key);
+        Ok(output)
+    }
+
+    /// Deletes the specified objects from the bucket, retrying the request as specified by
+    /// `bucket_retry` upon the errors classified as retryable, by default the transient ones.
+    /// Fails if any of the objects couldn't be deleted.
+    pub async fn delete_objects_in_bucket(
+        &self,
+        objects: Vec<Object>,
+    ) -> ZResult<DeleteObjectsOutput> {
+        if objects.is_empty() {
+            return Ok(DeleteObjectsOutput::builder()
+                .set_deleted(Some(vec![]))
+                .build());
+        }
+
+        let mut object_identifiers: Vec<ObjectIdentifier> = vec![];
+
+        for object in objects {
+            let identifier = ObjectIdentifier::builder()
+                .set_key(object.key().map(|x| self.scoped_key(x)).transpose()?)
+                .build();
+            object_identifiers.push(identifier);
+        }
+
+        let delete = Delete::builder()
+            .set_objects(Some(object_identifiers))
+            .build();
+
+        self.limit_rate().await?;
+        let result = retry_with_backoff(
+            &self.bucket_retry,
+            self.retry_budget.as_ref(),
+            &format!("Emptying of bucket '{self}'"),
+            |err| self.is_retryable(err, is_transient_error(err)),
+            || {
+                self.client()
+                    .delete_objects()
+                    .bucket(self.bucket.to_owned())
+                    .delete(delete.to_owned())
+                    .send()
+            },
+        )
+        .await;
+        if let Some(get_cache) = &self.get_cache {
+            get_cache.clear();
+        }
+        let output = result.map_err(with_request_ids)?;
+        // DeleteObjects succeeds even if some of the objects couldn't be deleted, reporting them
+        // along with their own errors.
+        if let Some(errors) = output.errors().filter(|errors| !errors.is_empty()) {
+            let errors = errors
+                .iter()
+                .map(|error| {
+                    format!(
+                        "'{}': {}",
+                        error
+                            .key()
+                            .and_then(|key| self.unscoped_key(key))
+                            .unwrap_or_default(),
+                        error.message().or(error.code()).unwrap_or_default()
+                    )
+                })
+                .collect::<Vec<_>>();
+            return Err(zerror!(
+                "Couldn't delete {} objects of bucket '{self}': {}",
+                errors.len(),
+                errors.join(", ")
+            )
+            .into());
+        }
+        Ok(output)
+    }
+
+    /// Asyncronically creates the bucket associated to this client upon construction on a new
+    /// tokio runtime.
+    /// Returns:
+    /// - Ok(Some(CreateBucketOutput)) in case the bucket was successfully created
+    /// - Ok(Some(None)) in case the `reuse_bucket` parameter is true and the bucket already exists
+    ///     and is owned by you, or its creation is denied but it is accessible and the bucket is
+    ///     probed upon denial
+    /// - Error in any other case
+    ///
+    /// The configured `bucket_tags` are applied to the created bucket, and to the reused one when
+    /// `manage_bucket_tags` is set.
+    pub async fn create_bucket(&self, reuse_bucket: bool) -> ZResult<Option<CreateBucketOutput>> {
+        let output = self.create_or_reuse_bucket(reuse_bucket).await?;
+        if !self.bucket_tags.is_empty() && (output.is_some() || self.manage_bucket_tags) {
+            self.tag_bucket().await?;
+        }
+        Ok(output)
+    }
+
+    /// Creates the bucket, or reuses it if [reuse_bucket] is set and it already exists, in which
+    /// case None is returned.
+    async fn create_or_reuse_bucket(
+        &self,
+        reuse_bucket: bool,
+    ) -> ZResult<Option<CreateBucketOutput>> {
+        let constraint = self
+            .region
+            .as_ref()
+            .map(|region| BucketLocationConstraint::from(region.as_str()));
+        let cfg = CreateBucketConfiguration::builder()
+            .set_location_constraint(constraint)
+            .build();
+        let result = retry_with_backoff(
+            &self.bucket_retry,
+            self.retry_budget.as_ref(),
+            &format!("Creation of bucket '{self}'"),
+            // A conflicting operation may be in progress on the bucket, for instance if it was
+            // recently deleted.
+            |err| match err {
+                SdkError::ServiceError { err, .. } if err.code() == Some("OperationAborted") => {
+                    true
+                }
+                err => self.is_retryable(err, is_transient_error(err)),
+            },
+            || {
+                self.client()
+                    .create_bucket()
+                    .create_bucket_configuration(cfg.to_owned())
+                    .bucket(self.bucket.to_owned())
+                    .send()
+            },
+        )
+        .await;
+
+        match result {
+            Ok(output) => Ok(Some(output)),
+            Err(SdkError::ServiceError { err, raw }) => {
+                if err.is_bucket_already_owned_by_you() && reuse_bucket {
+                    return Ok(None);
+                };
+                // Some S3-compatible stores report a bucket created concurrently by another of our
+                // storages as already existing rather than owned by us, in which case it is
+                // reused as long as it is accessible.
+                if err.is_bucket_already_exists()
+                    && reuse_bucket
+                    && self.is_bucket_accessible().await
+                {
+                    tracing::debug!("Bucket '{self}' was created concurrently, reusing it.");
+                    return Ok(None);
+                }
+                // The credentials may only grant object-level permissions, in which case the
+                // bucket is reused if it already exists and is accessible.
+                if err.code() == Some("AccessDenied")
+                    && reuse_bucket
+                    && self.probe_bucket_on_denied
+                    && self.is_bucket_accessible().await
+                {
+                    tracing::debug!(
+                        "Creation of bucket '{self}' was denied but the bucket is accessible, \
+                        reusing it."
+                    );
+                    return Ok(None);
+                }
+                Err(zerror!("Couldn't associate bucket '{self}': {raw:?}").into())
+            }
+            Err(err) => {
+                Err(zerror!("Couldn't create or associate bucket '{self}': {err:?}.").into())
+            }
+        }
+    }
+
+    /// Replaces the tags of the bucket by the configured `bucket_tags`.
+    async fn tag_bucket(&self) -> ZResult<()> {
+        let tag_set = self
+            .bucket_tags
+            .iter()
+            .map(|(key, value)| Tag::builder().key(key).value(value).build())
+            .collect();
+        self.client()
+            .put_bucket_tagging()
+            .bucket(self.bucket.to_owned())
+            .tagging(Tagging::builder().set_tag_set(Some(tag_set)).build())
+            .send()
+            .await
+            .map_err(|e| {
+                let e = with_request_ids(e);
+                zerror!("Couldn't tag bucket '{self}': {e}")
+            })?;
+        tracing::debug!("Tagged bucket '{self}' with {:?}.", self.bucket_tags);
+        Ok(())
+    }
+
+    /// Retrieves the region where the bucket is located, or None if the bucket doesn't exist.
+    pub async fn get_bucket_region(&self) -> ZResult<Option<String>> {
+        match self
+            .client()
+            .get_bucket_location()
+            .bucket(self.bucket.to_owned())
+            .send()
+            .await
+        {
+            // The buckets of `us-east-1` have no location constraint, and `EU` is the legacy name
+            // of `eu-west-1`.
+            Ok(output) => Ok(Some(
+                match output.location_constraint().map(|x| x.as_str()) {
+                    None | Some("") => "us-east-1",
+                    Some("EU") => "eu-west-1",
+                    Some(region) => region,
+                }
+                .to_string(),
+            )),
+            Err(SdkError::ServiceError { err, .. }) if err.code() == Some("NoSuchBucket") => {
+                Ok(None)
+            }
+            Err(err) => Err(with_request_ids(err)),
+        }
+    }
+
+    /// Checks the health of the [endpoint] (the default endpoint of S3 in the [region] if None)
+    /// stage by stage, failing with the first stage at fault: the resolution of its host name, the
+    /// TCP connection to it, then a HEAD request on the bucket. The bucket not existing yet isn't
+    /// a failure, as it may be created afterwards.
+    pub async fn health_check(&self, endpoint: Option<&str>, region: Option<&str>) -> ZResult<()> {
+        let (host, port) = endpoint_authority(endpoint, region)?;
+        let addresses: Vec<SocketAddr> = tokio::net::lookup_host((host.as_str(), port))
+            .await
+            .map_err(|e| zerror!("Health check failed: couldn't resolve host '{host}': {e}"))?
+            .collect();
+        if addresses.is_empty() {
+            return Err(
+                zerror!("Health check failed: host '{host}' resolved to no address.").into(),
+            );
+        }
+        match tokio::time::timeout(
+            HEALTH_CHECK_CONNECT_TIMEOUT,
+            tokio::net::TcpStream::connect(addresses.as_slice()),
+        )
+        .await
+        {
+            Ok(Ok(_)) => (),
+            Ok(Err(e)) => {
+                return Err(zerror!(
+                    "Health check failed: couldn't connect to '{host}:{port}' ({addresses:?}): {e}"
+                )
+                .into())
+            }
+            Err(_) => {
+                return Err(zerror!(
+                    "Health check failed: connection to '{host}:{port}' ({addresses:?}) timed out \
+                    after {HEALTH_CHECK_CONNECT_TIMEOUT:?}."
+                )
+                .into())
+            }
+        }
+        match self
+            .client()
+            .head_bucket()
+            .bucket(self.bucket.to_owned())
+            .send()
+            .await
+        {
+            Ok(_) => (),
+            Err(SdkError::ServiceError { raw, .. })
+                if raw.http().status() == http::StatusCode::NOT_FOUND =>
+            {
+                tracing::debug!("Health check: bucket '{self}' doesn't exist yet.");
+            }
+            Err(err) => {
+                let err = with_request_ids(err);
+                return Err(zerror!(
+                    "Health check failed: HEAD request on bucket '{self}' failed: {err}"
+                )
+                .into());
+            }
+        }
+        tracing::debug!("Health check of '{host}:{port}' for bucket '{self}' succeeded.");
+        Ok(())
+    }
+
+    /// Checks whether the bucket exists and is accessible with the credentials of this client.
+    async fn is_bucket_accessible(&self) -> bool {
+        self.client()
+            .head_bucket()
+            .bucket(self.bucket.to_owned())
+            .send()
+            .await
+            .is_ok()
+    }
+
+    /// Deletes the bucket associated to this storage.
+    ///
+    /// In order to fulfill this operation, all the contained files in the bucket are deleted.
+    /// When a bucket prefix is specified, only the objects under it are deleted and the bucket,
+    /// shared with other storages, is kept.
+    pub async fn delete_bucket(&self) -> ZResult<()> {
+        self.empty_bucket().await?;
+        if let Some(bucket_prefix) = &self.bucket_prefix {
+            tracing::debug!("Emptied prefix '{bucket_prefix}' of bucket '{self}', keeping it.");
+            return Ok(());
+        }
+        retry_with_backoff(
+            &self.bucket_retry,
+            self.retry_budget.as_ref(),
+            &format!("Deletion of bucket '{self}'"),
+            |err| self.is_retryable(err, is_transient_error(err)),
+            || self.client().delete_bucket().bucket(&self.bucket).send(),
+        )
+        .await
+        .map_err(with_request_ids)?;
+        tracing::debug!("Deleted bucket '{}'.", self.bucket.to_owned());
+        Ok(())
+    }
+
+    /// Deletes all the objects contained in the bucket, retrying the listing and the deletion of
+    /// each page upon the errors classified as retryable, by default the transient ones.
+    pub async fn empty_bucket(&self) -> ZResult<()> {
+        let prefix = &self.scoped_prefix(None)?;
+        // The listing is paginated, each page holding up to 1000 objects, i.e. a batch allowed by
+        // DeleteObjects. Each page is deleted as soon as listed, up to `empty_bucket_concurrency`
+        // of them at once, so that the listed objects aren't all held in memory.
+        futures::stream::try_unfold(Some(None), |continuation_token| async move {
+            let continuation_token: Option<String> = match continuation_token {
+                Some(continuation_token) => continuation_token,
+                None => return ZResult::Ok(None),
+            };
+            self.limit_rate().await?;
+            let response = retry_with_backoff(
+                &self.bucket_retry,
+                self.retry_budget.as_ref(),
+                &format!("Listing of bucket '{self}'"),
+                |err| self.is_retryable(err, is_transient_error(err)),
+                || {
+                    self.send_following_redirect(|| {
+                        self.read_client()
+                            .list_objects_v2()
+                            .bucket(self.bucket.to_owned())
+                            .set_prefix(prefix.to_owned())
+                            .set_continuation_token(continuation_token.to_owned())
+                            .send()
+                    })
+                },
+            )
+            .await
+            .map_err(with_request_ids)?;
+            let (objects, next_continuation_token) = self.listed_page(&response);
+            Ok(Some((objects, next_continuation_token.map(Some))))
+        })
+        .map_ok(|objects| self.delete_objects_in_bucket(objects))
+        .try_buffer_unordered(self.empty_bucket_concurrency)
+        .try_collect::<Vec<_>>()
+        .await?;
+        Ok(())
+    }
+
+    /// Lists a page of the objects contained in the bucket whose key starts with the [prefix], if
+    /// specified, starting from the [continuation_token] returned along with the previous page, if
+    /// any. Returns the listed objects and the token of the next page, which is None for the last
+    /// page.
+    pub async fn list_objects_page(
+        &self,
+        prefix: Option<&str>,
+        continuation_token: Option<String>,
+    ) -> ZResult<(Vec<Object>, Option<String>)> {
+        let prefix = self.scoped_prefix(prefix)?;
+        self.limit_rate().await?;
+        let response = self
+            .send_following_redirect(|| {
+                self.read_client()
+                    .list_objects_v2()
+                    .bucket(self.bucket.to_owned())
+                    .set_prefix(prefix.to_owned())
+                    .set_continuation_token(continuation_token.to_owned())
+                    .send()
+            })
+            .await
+            .map_err(with_request_ids)?;
+        Ok(self.listed_page(&response))
+    }
+
+    /// Returns the objects of a listing page [response], relative to the bucket prefix, along
+    /// with the token of the next page, which is None for the last page.
+    fn listed_page(&self, response: &ListObjectsV2Output) -> (Vec<Object>, Option<String>) {
+        let next_continuation_token = match response.is_truncated() {
+            true => response.next_continuation_token().map(|x| x.to_string()),
+            false => None,
+        };
+        (
+            self.unscoped_objects(response.contents().unwrap_or_default()),
+            next_continuation_token,
+        )
+    }
+
+    /// Lists the keys of all the objects whose key starts with the [prefix], in lexicographic
+    /// order.
+    pub async fn list_keys_with_prefix(&self, prefix: &str) -> ZResult<Vec<String>> {
+        let mut keys = vec![];
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) = self
+                .list_objects_page(Some(prefix), continuation_token)
+                .await?;
+            keys.extend(
+                objects
+                    .iter()
+                    .filter_map(|object| object.key().map(|x| x.to_string())),
+            );
+            if next_continuation_token.is_none() {
+                return Ok(keys);
+            }
+            continuation_token = next_continuation_token;
+        }
+    }
+
+    /// Lists the distinct prefixes of the keys of the objects up to the first occurrence of the
+    /// [delimiter], e.g. the top-level "folders" of the bucket for `/`.
+    pub async fn list_common_prefixes(&self, delimiter: &str) -> ZResult<Vec<String>> {
+        let mut prefixes = vec![];
+        let mut continuation_token = None;
+        loop {
+            self.limit_rate().await?;
+            let response = self
+                .read_client()
+                .list_objects_v2()
+                .bucket(self.bucket.to_owned())
+                .set_prefix(self.bucket_prefix.to_owned())
+                .delimiter(delimiter)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(with_request_ids)?;
+            prefixes.extend(
+                response
+                    .common_prefixes()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|x| x.prefix().and_then(|x| self.unscoped_key(x)))
+                    .map(|x| x.to_string()),
+            );
+            if !response.is_truncated() {
+                return Ok(prefixes);
+            }
+            continuation_token = response.next_continuation_token().map(|x| x.to_string());
+        }
+    }
+
+    /// Lists all the objects contained in the bucket by first listing the top-level prefixes of
+    /// their keys up to the first occurrence of the [delimiter], then listing the objects under up
+    /// to [concurrency] of these prefixes at once.
+    pub async fn list_objects_in_parallel(
+        &self,
+        delimiter: &str,
+        concurrency: usize,
+    ) -> ZResult<Vec<Object>> {
+        // The objects whose key doesn't contain the delimiter are listed along with the prefixes.
+        let mut objects = vec![];
+        let mut prefixes = vec![];
+        let mut continuation_token = None;
+        loop {
+            self.limit_rate().await?;
+            let response = self
+                .read_client()
+                .list_objects_v2()
+                .bucket(self.bucket.to_owned())
+                .set_prefix(self.bucket_prefix.to_owned())
+                .delimiter(delimiter)
+                .set_continuation_token(continuation_token)
+                .send()
+                .await
+                .map_err(with_request_ids)?;
+            objects.extend(self.unscoped_objects(response.contents().unwrap_or_default()));
+            prefixes.extend(
+                response
+                    .common_prefixes()
+                    .unwrap_or_default()
+                    .iter()
+                    .filter_map(|x| x.prefix().and_then(|x| self.unscoped_key(x)))
+                    .map(|x| x.to_string()),
+            );
+            if !response.is_truncated() {
+                break;
+            }
+            continuation_token = response.next_continuation_token().map(|x| x.to_string());
+        }
+        let prefixed_objects: Vec<Vec<Object>> = futures::stream::iter(prefixes)
+            .map(|prefix| async move {
+                let mut objects = vec![];
+                let mut continuation_token = None;
+                loop {
+                    let (page, next_continuation_token) = self
+                        .list_objects_page(Some(&prefix), continuation_token)
+                        .await?;
+                    objects.extend(page);
+                    match next_continuation_token {
+                        Some(token) => continuation_token = Some(token),
+                        None => return ZResult::Ok(objects),
+                    }
+                }
+            })
+            .buffer_unordered(concurrency)
+            .try_collect()
+            .await?;
+        objects.extend(prefixed_objects.into_iter().flatten());
+        Ok(objects)
+    }
+
+    /// Lists a page of the versions of the objects contained in the bucket, starting from the
+    /// [key_marker] and [version_id_marker] returned along with the previous page, if any. Returns
+    /// the listed versions and the markers of the next page, which are None for the last page.
+    ///
+    /// As stated by the S3 API, the versions of each key are listed from the newest to the oldest.
+    pub async fn list_object_versions_page(
+        &self,
+        key_marker: Option<String>,
+        version_id_marker: Option<String>,
+    ) -> ZResult<(Vec<ObjectVersion>, Option<VersionMarkers>)> {
+        self.limit_rate().await?;
+        let response = self
+            .read_client()
+            .list_object_versions()
+            .bucket(self.bucket.to_owned())
+            .set_prefix(self.bucket_prefix.to_owned())
+            .set_key_marker(key_marker)
+            .set_version_id_marker(version_id_marker)
+            .send()
+            .await
+            .map_err(with_request_ids)?;
+        let versions = response
+            .versions()
+            .unwrap_or_default()
+            .iter()
+            .filter_map(|version| {
+                let key = self.unscoped_key(version.key()?)?.to_string();
+                let mut version = version.to_owned();
+                version.key = Some(key);
+                Some(version)
+            })
+            .collect();
+        let next_markers = response.is_truncated().then(|| {
+            (
+                response.next_key_marker().map(|x| x.to_string()),
+                response.next_version_id_marker().map(|x| x.to_string()),
+            )
+        });
+        Ok((versions, next_markers))
+    }
+
+    /// Deletes the specified object versions, given as pairs of key and version id, in batches.
+    ///
+    /// The versions failing to be deleted, as reported by the DeleteObjects responses, don't
+    /// prevent the deletion of the others. Returns the amount of deleted versions, along with the
+    /// descriptions of the failures.
+    pub async fn delete_object_versions(
+        &self,
+        versions: Vec<(String, String)>,
+    ) -> ZResult<(usize, Vec<String>)> {
+        let mut deleted = 0;
+        let mut failures = vec![];
+        for batch in versions.chunks(MAX_DELETE_OBJECTS_BATCH) {
+            let object_identifiers = batch
+                .iter()
+                .map(|(key, version_id)| {
+                    Ok(ObjectIdentifier::builder()
+                        .key(self.scoped_key(key)?)
+                        .version_id(version_id)
+                        .build())
+                })
+                .collect::<ZResult<Vec<_>>>()?;
+            let delete = Delete::builder()
+                .set_objects(Some(object_identifiers))
+                .build();
+            self.limit_rate().await?;
+            let result = self
+                .client()
+                .delete_objects()
+                .bucket(self.bucket.to_owned())
+                .delete(delete)
+                .send()
+                .await;
+            match result {
+                Ok(output) => {
+                    let errors = output.errors().unwrap_or_default();
+                    deleted += batch.len() - errors.len();
+                    failures.extend(errors.iter().map(|error| {
+                        format!(
+                            "'{}' (version '{}'): {}",
+                            error
+                                .key()
+                                .and_then(|key| self.unscoped_key(key))
+                                .unwrap_or_default(),
+                            error.version_id().unwrap_or_default(),
+                            error.message().or(error.code()).unwrap_or_default()
+                        )
+                    }));
+                }
+                Err(err) => {
+                    let err = with_request_ids(err);
+                    failures.push(format!("batch of {} versions: {err}", batch.len()));
+                }
+            }
+        }
+        Ok((deleted, failures))
+    }
+
+    /// Deletes all the objects whose key starts with the [prefix] (e.g. a whole subtree of keys)
+    /// and is accepted by the [filter], listing them page by page and deleting each page with a
+    /// single DeleteObjects request.
+    ///
+    /// The objects failing to be deleted don't prevent the deletion of the others, but are
+    /// reported by the returned error once all the pages have been processed.
+    ///
+    /// Returns the amount of deleted objects.
+    pub async fn delete_prefix(
+        &self,
+        prefix: &str,
+        filter: impl Fn(&str) -> bool,
+    ) -> ZResult<usize> {
+        let mut deleted = 0;
+        let mut failures = vec![];
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) = self
+                .list_objects_page(Some(prefix), continuation_token)
+                .await?;
+            let keys: Vec<&str> = objects
+                .iter()
+                .filter_map(|object| object.key())
+                .filter(|key| filter(key))
+                .collect();
+            for batch in keys.chunks(MAX_DELETE_OBJECTS_BATCH) {
+                let object_identifiers = batch
+                    .iter()
+                    .map(|key| {
+                        Ok(ObjectIdentifier::builder()
+                            .key(self.scoped_key(key)?)
+                            .build())
+                    })
+                    .collect::<ZResult<Vec<_>>>()?;
+                let delete = Delete::builder()
+                    .set_objects(Some(object_identifiers))
+                    .build();
+                self.limit_rate().await?;
+                let result = self
+                    .client()
+                    .delete_objects()
+                    .bucket(self.bucket.to_owned())
+                    .delete(delete)
+                    .send()
+                    .await;
+                batch
+                    .iter()
+                    .for_each(|key| self.invalidate_cached_object(key));
+                match result {
+                    Ok(output) => {
+                        let errors = output.errors().unwrap_or_default();
+                        let error_key = |error: &aws_sdk_s3::model::Error| {
+                            error.key().and_then(|key| self.unscoped_key(key))
+                        };
+                        deleted += batch.len() - errors.len();
+                        batch
+                            .iter()
+                            .filter(|key| {
+                                !errors.iter().any(|error| error_key(error) == Some(**key))
+                            })
+                            .for_each(|key| self.track_deleted_key(key));
+                        failures.extend(errors.iter().map(|error| {
+                            format!(
+                                "'{}': {}",
+                                error_key(error).unwrap_or_default(),
+                                error.message().or(error.code()).unwrap_or_default()
+                            )
+                        }));
+                    }
+                    Err(err) => failures.push(format!("batch of {} objects: {err}", batch.len())),
+                }
+            }
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => break,
+            }
+        }
+        if failures.is_empty() {
+            Ok(deleted)
+        } else {
+            Err(zerror!(
+                "Deleted {} objects under prefix '{}' but failed to delete {}",
+                deleted,
+                prefix,
+                failures.join(", ")
+            )
+            .into())
+        }
+    }
+
+    /// Re-encrypts the objects of the bucket which aren't encrypted with the configured SSE-KMS
+    /// key, by copying each of them onto itself on the server side with the current encryption
+    /// settings, without transferring their body and preserving their metadata. The bucket is
+    /// scanned page by page and the objects are copied one at a time, within the rate limit if
+    /// configured.
+    ///
+    /// The objects already encrypted with the key are skipped, so that an interrupted
+    /// re-encryption resumes where it stopped.
+    ///
+    /// Returns the amounts of scanned and of re-encrypted objects.
+    pub async fn reencrypt_objects(&self) -> ZResult<(usize, usize)> {
+        let sse_kms = self
+            .sse_kms
+            .as_ref()
+            .ok_or_else(|| zerror!("Re-encryption of '{self}' requires an SSE-KMS key."))?;
+        let mut scanned = 0;
+        let mut reencrypted = 0;
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) =
+                self.list_objects_page(None, continuation_token).await?;
+            for key in objects.iter().filter_map(|object| object.key()) {
+                scanned += 1;
+                let head = self
+                    .get_head_object(key)
+                    .await
+                    .map_err(|e| zerror!("Couldn't retrieve metadata of '{key}': {e}"))?;
+                let is_encrypted = head.server_side_encryption()
+                    == Some(&ServerSideEncryption::AwsKms)
+                    && sse_kms.key_id.as_ref().map_or(true, |key_id| {
+                        head.ssekms_key_id()
+                            .map_or(false, |head_key_id| head_key_id.ends_with(key_id.as_str()))
+                    });
+                if is_encrypted {
+                    continue;
+                }
+                self.limit_rate().await?;
+                // The storage class is preserved, as it would otherwise be reset by the copy.
+                let options = CopyObjectOptions {
+                    storage_class: head.storage_class().map(|x| x.as_str().to_string()),
+                    ..Default::default()
+                };
+                self.copy_object(key, key, options)
+                    .await
+                    .map_err(|e| zerror!("Couldn't re-encrypt '{key}': {e}"))?;
+                tracing::trace!("Re-encrypted '{}'.", redact_key(key, self.log_keys));
+                reencrypted += 1;
+            }
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok((scanned, reencrypted)),
+            }
+        }
+    }
+
+    /// Compacts the versions of the objects in the bucket, keeping only the newest
+    /// [keep_versions] versions of each key and deleting the older ones. The versions are listed
+    /// and deleted page by page, so that the memory usage doesn't grow with the amount of
+    /// versions.
+    ///
+    /// This is safe to run concurrently with writes: versions created after the listing are newer
+    /// than the listed ones, hence only versions which are already superseded get deleted.
+    ///
+    /// The versions failing to be deleted don't prevent the deletion of the others, but are
+    /// reported by the returned error once all the pages have been processed.
+    ///
+    /// Returns the amount of deleted versions.
+    pub async fn compact_object_versions(&self, keep_versions: usize) -> ZResult<usize> {
+        let mut deleted = 0;
+        let mut failures = vec![];
+        // The versions are listed key by key, so only the versions of the current key are counted.
+        let mut current_key = String::new();
+        let mut count = 0;
+        let mut markers: VersionMarkers = (None, None);
+        loop {
+            let (versions, next_markers) =
+                self.list_object_versions_page(markers.0, markers.1).await?;
+            let mut superseded = vec![];
+            for version in &versions {
+                let (key, version_id) = match (version.key(), version.version_id()) {
+                    (Some(key), Some(version_id)) => (key, version_id),
+                    _ => continue,
+                };
+                if key != current_key {
+                    current_key = key.to_string();
+                    count = 0;
+                }
+                count += 1;
+                if count > keep_versions && !version.is_latest() {
+                    superseded.push((key.to_string(), version_id.to_string()));
+                }
+            }
+            let (page_deleted, page_failures) = self.delete_object_versions(superseded).await?;
+            deleted += page_deleted;
+            failures.extend(page_failures);
+            match next_markers {
+                Some(next_markers) => markers = next_markers,
+                None => break,
+            }
+        }
+        if failures.is_empty() {
+            Ok(deleted)
+        } else {
+            Err(zerror!(
+                "Deleted {} versions of the objects of bucket '{}' but failed to delete {}",
+                deleted,
+                self,
+                failures.join(", ")
+            )
+            .into())
+        }
+    }
+
+    /// Adds a timestamp synthesized by the [synthesizer] to the metadata of the objects lacking
+    /// one, except the internal objects under the [reserved_prefix], by copying each of them onto
+    /// itself along with its system headers. The bucket is scanned page by page and the timestamps
+    /// of the objects of a page are synthesized in the order of their keys, so that they increase
+    /// along with the keys, while up to `maintenance_concurrency` objects of a page are retrieved
+    /// and stamped at once.
+    ///
+    /// Returns the amounts of scanned and of stamped objects.
+    pub async fn stamp_missing_timestamps(
+        &self,
+        synthesizer: &TimestampSynthesizer,
+        reserved_prefix: &str,
+    ) -> ZResult<(usize, usize)> {
+        let mut scanned = 0;
+        let mut stamped = 0;
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) =
+                self.list_objects_page(None, continuation_token).await?;
+            let keys = objects
+                .iter()
+                .filter_map(|object| object.key())
+                .filter(|key| !key.starts_with(reserved_prefix));
+            let mut heads = futures::stream::iter(
+                keys.map(|key| async move { (key, self.get_head_object(key).await) }),
+            )
+            .buffer_unordered(self.maintenance_concurrency)
+            .collect::<Vec<_>>()
+            .await;
+            // The timestamps are synthesized in the order of the keys, then the objects are
+            // stamped concurrently.
+            heads.sort_unstable_by(|(a, _), (b, _)| a.cmp(b));
+            let mut copies = vec![];
+            for (key, head) in heads {
+                scanned += 1;
+                let head =
+                    head.map_err(|e| zerror!("Couldn't retrieve metadata of '{key}': {e}"))?;
+                let mut metadata = head.metadata().cloned().unwrap_or_default();
+                if metadata.contains_key(&self.timestamp_metadata_key) {
+                    continue;
+                }
+                let timestamp = synthesizer.new_timestamp();
+                metadata.insert(
+                    self.timestamp_metadata_key.to_owned(),
+                    timestamp.to_string(),
+                );
+                // The system headers of the object are kept, as they are replaced along with the
+                // metadata.
+                let options = CopyObjectOptions {
+                    metadata: Some(metadata),
+                    storage_class: head.storage_class().map(|x| x.as_str().to_string()),
+                    server_side_encryption: head
+                        .server_side_encryption()
+                        .map(|x| x.as_str().to_string()),
+                    sse_kms_key_id: head.ssekms_key_id().map(|x| x.to_string()),
+                    content_type: head.content_type().map(|x| x.to_string()),
+                    content_encoding: head.content_encoding().map(|x| x.to_string()),
+                    content_disposition: head.content_disposition().map(|x| x.to_string()),
+                    content_language: head.content_language().map(|x| x.to_string()),
+                    cache_control: head.cache_control().map(|x| x.to_string()),
+                    expires: head.expires().cloned(),
+                };
+                copies.push((key, timestamp, options));
+            }
+            stamped += futures::stream::iter(copies.into_iter().map(
+                |(key, timestamp, options)| async move {
+                    self.copy_object(key, key, options)
+                        .await
+                        .map_err(|e| zerror!("Couldn't stamp '{key}': {e}"))?;
+                    tracing::trace!(
+                        "Stamped '{}' with the synthesized timestamp {timestamp}.",
+                        redact_key(key, self.log_keys)
+                    );
+                    ZResult::Ok(())
+                },
+            ))
+            .buffer_unordered(self.maintenance_concurrency)
+            .try_collect::<Vec<_>>()
+            .await?
+            .len();
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok((scanned, stamped)),
+            }
+        }
+    }
+
+    /// Exports the objects of the bucket accepted by the [filter] to the local directory [dir], up
+    /// to [concurrency] at once: the body of each object is written to the path of its key under
+    /// [dir], along with a sidecar file (the path suffixed with `.metadata.json`) holding its
+    /// metadata, content type and encoding, ETag and last modification date. The bucket is
+    /// listed page by page.
+    ///
+    /// The sidecar is written once the body is complete, and the objects whose sidecar records
+    /// their current ETag are skipped, so that an interrupted export resumes where it stopped.
+    /// The objects failing to be exported (e.g. whose key can't be mapped to a path) don't
+    /// prevent the export of the others, but are reported by the returned error.
+    ///
+    /// Returns the amounts of exported and of skipped objects, already exported or deleted since
+    /// they were listed.
+    pub async fn export_objects(
+        &self,
+        dir: &Path,
+        concurrency: usize,
+        filter: impl Fn(&str) -> bool,
+    ) -> ZResult<(usize, usize)> {
+        let mut exported = 0;
+        let mut skipped = 0;
+        let mut failures = vec![];
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) =
+                self.list_objects_page(None, continuation_token).await?;
+            let results: Vec<(&str, ZResult<bool>)> = futures::stream::iter(
+                objects
+                    .iter()
+                    .filter_map(|object| Some((object.key()?, object)))
+                    .filter(|(key, _)| filter(key)),
+            )
+            .map(|(key, object)| async move { (key, self.export_object(dir, key, object).await) })
+            .buffer_unordered(concurrency)
+            .collect()
+            .await;
+            for (key, result) in results {
+                match result {
+                    Ok(true) => exported += 1,
+                    Ok(false) => skipped += 1,
+                    Err(e) => failures.push(format!("'{}': {e}", redact_key(key, self.log_keys))),
+                }
+            }
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => break,
+            }
+        }
+        if failures.is_empty() {
+            Ok((exported, skipped))
+        } else {
+            Err(zerror!(
+                "Exported {} objects to '{}' but failed to export {}",
+                exported,
+                dir.display(),
+                failures.join(", ")
+            )
+            .into())
+        }
+    }
+
+    /// Exports the listed [object] stored under [key] to the directory [dir] (see
+    /// [S3Client::export_objects]), returning whether it was exported rather than skipped.
+    async fn export_object(&self, dir: &Path, key: &str, object: &Object) -> ZResult<bool> {
+        // The keys are only mapped to paths which stay under the directory: the leading slash of
+        // the default layout is stripped, as joining an absolute path would replace the directory.
+        let relative_key = key.strip_prefix('/').unwrap_or(key);
+        if relative_key
+            .split('/')
+            .any(|x| x.is_empty() || x == "." || x == "..")
+            || key.ends_with(EXPORT_SIDECAR_SUFFIX)
+            || key.ends_with(EXPORT_PARTIAL_SUFFIX)
+        {
+            return Err(zerror!("Key can't be mapped to a path under the directory.").into());
+        }
+        let path = dir.join(relative_key);
+        let sidecar_path = with_suffix(&path, EXPORT_SIDECAR_SUFFIX);
+        if let (Some(e_tag), Ok(sidecar)) = (object.e_tag(), tokio::fs::read(&sidecar_path).await) {
+            let sidecar: Option<serde_json::Value> = serde_json::from_slice(&sidecar).ok();
+            if sidecar
+                .as_ref()
+                .and_then(|x| x.get("e_tag"))
+                .and_then(|x| x.as_str())
+                == Some(e_tag)
+            {
+                return Ok(false);
+            }
+        }
+        let content = match self.get_object(key).await? {
+            Some(content) => content,
+            None => return Ok(false),
+        };
+        if let Some(parent) = path.parent() {
+            tokio::fs::create_dir_all(parent)
+                .await
+                .map_err(|e| zerror!("Couldn't create directory '{}': {e}", parent.display()))?;
+        }
+        write_file_atomically(&path, &content.body)
+            .await
+            .map_err(|e| zerror!("Couldn't write '{}': {e}", path.display()))?;
+        let sidecar = serde_json::json!({
+            "e_tag": object.e_tag(),
+            "last_modified": object.last_modified().map(|date| date.secs()),
+            "content_type": content.content_type,
+            "content_encoding": content.content_encoding,
+            "metadata": content.metadata,
+        });
+        let sidecar = serde_json::to_vec_pretty(&sidecar)
+            .map_err(|e| zerror!("Couldn't serialize the metadata: {e}"))?;
+        write_file_atomically(&sidecar_path, &sidecar)
+            .await
+            .map_err(|e| zerror!("Couldn't write '{}': {e}", sidecar_path.display()))?;
+        Ok(true)
+    }
+
+    /// Audits the timestamps stored in the metadata of the objects without modifying them, except
+    /// the internal objects under the [reserved_prefix], calling [on_invalid] with the key of each
+    /// object whose timestamp is missing or can't be parsed, along with the reason, as soon as it
+    /// is found. The bucket is scanned page by page
+    /// so that the memory usage doesn't grow with the amount of objects, and the metadata of up to
+    /// `maintenance_concurrency` objects of a page is retrieved at once.
+    ///
+    /// Returns the amounts of scanned and of invalid objects.
+    pub async fn verify_object_timestamps(
+        &self,
+        reserved_prefix: &str,
+        on_invalid: impl Fn(&str, &str),
+    ) -> ZResult<(usize, usize)> {
+        let mut scanned = 0;
+        let mut invalid = 0;
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) =
+                self.list_objects_page(None, continuation_token).await?;
+            let keys = objects
+                .iter()
+                .filter_map(|object| object.key())
+                .filter(|key| !key.starts_with(reserved_prefix));
+            let mut heads = futures::stream::iter(keys.map(|key| async move {
+                let reason = match self.get_head_object(key).await {
+                    Ok(head) => match head
+                        .metadata()
+                        .and_then(|metadata| metadata.get(&self.timestamp_metadata_key))
+                    {
+                        Some(timestamp) => Timestamp::from_str(timestamp)
+                            .err()
+                            .map(|e| format!("unparseable timestamp '{timestamp}': {e:?}")),
+                        None => Some("missing timestamp".to_string()),
+                    },
+                    Err(e) => Some(format!("couldn't retrieve metadata: {e}")),
+                };
+                (key, reason)
+            }))
+            .buffer_unordered(self.maintenance_concurrency);
+            while let Some((key, reason)) = heads.next().await {
+                scanned += 1;
+                if let Some(reason) = reason {
+                    invalid += 1;
+                    on_invalid(key, &reason);
+                }
+            }
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => return Ok((scanned, invalid)),
+            }
+        }
+    }
+}
+
+/// Writes the [contents] to the file at [path] through a partial file renamed once complete, so
+/// that an interrupted write doesn't leave a truncated file behind.
+async fn write_file_atomically(path: &Path, contents: &[u8]) -> std::io::Result<()> {
+    let partial_path = with_suffix(path, EXPORT_PARTIAL_SUFFIX);
+    tokio::fs::write(&partial_path, contents).await?;
+    tokio::fs::rename(&partial_path, path).await
+}
+
+/// Returns the [path] with the [suffix] appended to its file name.
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+/// Aborts the multipart upload [upload_id] of [key] so that its already uploaded parts are
+/// discarded, logging the failure to do so.
+async fn abort_multipart_upload(
+    client: &Client,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    log_keys: LogKeys,
+) {
+    if let Err(err) = client
+        .abort_multipart_upload()
+        .bucket(bucket)
+        .key(key)
+        .upload_id(upload_id)
+        .send()
+        .await
+    {
+        let err = with_request_ids(err);
+        tracing::warn!(
+            "Couldn't abort multipart upload '{upload_id}' of '{}': {err}",
+            redact_key(key, log_keys)
+        );
+    }
+}
+
+/// Adds the [checksum] of the body to the [metadata] of an object, unless it would no longer fit
+/// within the size allowed by S3, in which case the object is stored without it.
+fn with_content_checksum(
+    metadata: Option<HashMap<String, String>>,
+    checksum: String,
+) -> HashMap<String, String> {
+    let mut metadata = metadata.unwrap_or_default();
+    let size: usize = metadata.iter().map(|(k, v)| k.len() + v.len()).sum();
+    if size + CONTENT_CHECKSUM_METADATA_KEY.len() + checksum.len() <= MAX_METADATA_SIZE {
+        metadata.insert(CONTENT_CHECKSUM_METADATA_KEY.to_string(), checksum);
+    }
+    metadata
+}
+
+/// Returns the request id and the extended request id of the response to the failed request of
+/// [err], if any, which identify the request to the AWS support.
+fn request_ids<E>(err: &SdkError<E>) -> Option<(String, String)> {
+    let headers = match err {
+        SdkError::ServiceError { raw, .. } | SdkError::ResponseError { raw, .. } => {
+            raw.http().headers()
+        }
+        _ => return None,
+    };
+    let header = |name| {
+        headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .map(|value| value.to_string())
+    };
+    match (
+        header(REQUEST_ID_HEADER),
+        header(EXTENDED_REQUEST_ID_HEADER),
+    ) {
+        (None, None) => None,
+        (request_id, extended_request_id) => Some((
+            request_id.unwrap_or_default(),
+            extended_request_id.unwrap_or_default(),
+        )),
+    }
+}
+
+/// Returns the region where the bucket is located when the failed request of [err] was
+/// permanently redirected to it.
+fn redirect_region<E>(err: &SdkError<E>) -> Option<String> {
+    match err {
+        SdkError::ServiceError { raw, .. } | SdkError::ResponseError { raw, .. }
+            if raw.http().status() == http::StatusCode::MOVED_PERMANENTLY =>
+        {
+            raw.http()
+                .headers()
+                .get(BUCKET_REGION_HEADER)
+                .and_then(|value| value.to_str().ok())
+                .map(|value| value.to_string())
+        }
+        _ => None,
+    }
+}
+
+/// Converts the [err] of a request into an error mentioning its request ids (see [request_ids]),
+/// which are also logged along with the span of the storage.
+fn with_request_ids<E: std::error::Error>(
+    err: SdkError<E>,
+) -> Box<dyn std::error::Error + Send + Sync> {
+    match request_ids(&err) {
+        Some((request_id, extended_request_id)) => {
+            tracing::debug!(request_id, extended_request_id, "Request failed: {err}");
+            zerror!("{err} (request id: {request_id}, extended request id: {extended_request_id})")
+                .into()
+        }
+        None => zerror!("{err}").into(),
+    }
+}
+
+/// Checks whether the error is transient, i.e. whether the failed request may succeed if retried:
+/// timeouts, connection failures, throttling and server errors.
+fn is_transient_error<E>(err: &SdkError<E>) -> bool {
+    match err {
+        SdkError::TimeoutError(_)
+        | SdkError::DispatchFailure(_)
+        | SdkError::ResponseError { .. } => true,
+        SdkError::ServiceError { raw, .. } => {
+            let status = raw.http().status();
+            status.is_server_error() || status == http::StatusCode::TOO_MANY_REQUESTS
+        }
+        _ => false,
+    }
+}
+
+impl std::fmt::Display for S3Client {
+    // It's sufficient to display the bucket name as we only have a single
+    // bucket and a single storage for each client.
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.bucket)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_s3::{self, MockS3};
+    use crate::TIMESTAMP_METADATA_KEY;
+    use serde_json::json;
+
+    #[tokio::test]
+    async fn multipart_upload_completes_after_part_retries() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "multipart": {
+                    "threshold": 16,
+                    "part_size": 8,
+                    "min_part_size": 8,
+                    "part_retries": 3,
+                    "part_retry_backoff_ms": 1,
+                },
+            }),
+        )
+        .await;
+        let body = mock_s3::error_body("InternalError", "Injected failure");
+        mock.inject(2, 500, vec![], body, |x| {
+            x.operation() == "UploadPart" && x.query("partNumber") == Some("2")
+        });
+
+        let value: Vec<u8> = (0..30).collect();
+        storage
+            .put(
+                mock_s3::key("a/b"),
+                Value::from(value.clone()),
+                mock_s3::timestamp(1),
+            )
+            .await
+            .unwrap();
+
+        assert_eq!(mock.object("/a/b").unwrap().body, value);
+        let second_parts = mock
+            .requests_of("UploadPart")
+            .into_iter()
+            .filter(|x| x.query("partNumber") == Some("2"))
+            .count();
+        assert_eq!(second_parts, 3);
+        assert!(mock.uploads().is_empty());
+    }
+
+    #[tokio::test]
+    async fn copies_preserve_or_replace_the_properties_of_the_objects() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[("origin", "a")]);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+
+        client
+            .copy_object("/a", "/b", CopyObjectOptions::default())
+            .await
+            .unwrap();
+        let copy = mock.object("/b").unwrap();
+        assert_eq!(copy.body, b"1");
+        assert_eq!(copy.metadata().get("origin").unwrap(), "a");
+
+        let options = CopyObjectOptions {
+            metadata: Some(HashMap::from([("origin".to_string(), "c".to_string())])),
+            storage_class: Some("STANDARD_IA".to_string()),
+            ..Default::default()
+        };
+        client.copy_object("/a", "/c", options).await.unwrap();
+        let copy = mock.object("/c").unwrap();
+        assert_eq!(copy.body, b"1");
+        assert_eq!(copy.metadata().get("origin").unwrap(), "c");
+        assert_eq!(
+            copy.headers.get("x-amz-storage-class").unwrap(),
+            "STANDARD_IA"
+        );
+        let request = &mock.requests_of("CopyObject")[1];
+        assert_eq!(request.header("x-amz-metadata-directive"), Some("REPLACE"));
+    }
+
+    #[tokio::test]
+    async fn attributes_are_parsed_without_the_payload() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"123", &[]);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+
+        let attributes = client.get_object_attributes("/a").await.unwrap().unwrap();
+        assert_eq!(attributes.object_size(), 3);
+        assert!(attributes.e_tag().is_some());
+        assert_eq!(
+            attributes.storage_class().map(|x| x.as_str()),
+            Some("STANDARD")
+        );
+        assert!(mock.requests_of("GetObject").is_empty());
+        assert!(client.get_object_attributes("/b").await.unwrap().is_none());
+    }
+
+    #[tokio::test]
+    async fn concurrent_gets_of_a_key_share_a_single_request() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        mock.set_delay(Some(Duration::from_millis(200)));
+
+        let objects = join_all((0..8).map(|_| client.get_object("/a"))).await;
+        for object in objects {
+            assert_eq!(object.unwrap().unwrap().body, b"1");
+        }
+        assert_eq!(mock.requests_of("GetObject").len(), 1);
+
+        // Once the request is done, the next get sees the latest state of the object.
+        mock.set_delay(None);
+        mock.insert_object("/a", b"2", &[]);
+        let object = client.get_object("/a").await.unwrap().unwrap();
+        assert_eq!(object.body, b"2");
+        assert_eq!(mock.requests_of("GetObject").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn coalesced_gets_take_a_single_token_of_the_rate_limit() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "rate_limit": { "ops_per_second": 1, "max_wait_ms": 0 } }),
+        )
+        .await;
+        mock.set_delay(Some(Duration::from_millis(200)));
+
+        let objects = join_all((0..8).map(|_| client.get_object("/a"))).await;
+        for object in objects {
+            assert_eq!(object.unwrap().unwrap().body, b"1");
+        }
+        assert_eq!(client.current_rate(), Some(1));
+
+        // The next request exceeds the rate.
+        let error = client
+            .get_object("/a")
+            .await
+            .err()
+            .expect("The rate limit should have been exceeded");
+        assert!(error.to_string().contains("Rate limit"), "{error}");
+        assert_eq!(mock.requests_of("GetObject").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn audit_reports_the_missing_and_unparseable_timestamps() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/good", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.insert_object("/missing", b"1", &[]);
+        mock.insert_object("/unparseable", b"1", &[(TIMESTAMP_METADATA_KEY, "1")]);
+        mock.insert_object("__zenoh__/segments/1", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({ "maintenance_concurrency": 2 })).await;
+
+        let invalid = Mutex::new(vec![]);
+        let result = client
+            .verify_object_timestamps("__zenoh__/", |key, reason| {
+                invalid
+                    .lock()
+                    .unwrap()
+                    .push((key.to_string(), reason.to_string()))
+            })
+            .await
+            .unwrap();
+        assert_eq!(result, (3, 2));
+        let mut invalid = invalid.into_inner().unwrap();
+        invalid.sort();
+        assert_eq!(
+            invalid[0],
+            ("/missing".to_string(), "missing timestamp".to_string())
+        );
+        assert_eq!(invalid[1].0, "/unparseable");
+        assert!(invalid[1].1.starts_with("unparseable timestamp '1'"));
+        assert!(mock.requests_of("PutObject").is_empty());
+    }
+
+    #[tokio::test]
+    async fn subtrees_are_deleted_page_by_page() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        for i in 0..1200 {
+            mock.insert_object(&format!("/a/k{i:04}"), b"1", &[]);
+        }
+        mock.insert_object("/b", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+
+        let deleted = client.delete_prefix("/a/", |_| true).await.unwrap();
+        assert_eq!(deleted, 1200);
+        assert_eq!(mock.keys(), vec!["/b".to_string()]);
+        assert_eq!(mock.requests_of("ListObjectsV2").len(), 2);
+        assert_eq!(mock.requests_of("DeleteObjects").len(), 2);
+
+        // The objects failing to be deleted are reported.
+        mock.insert_object("/c/1", b"1", &[]);
+        mock.insert_object("/c/2", b"1", &[]);
+        let body = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<DeleteResult>\
+            <Deleted><Key>/c/1</Key></Deleted>\
+            <Error><Key>/c/2</Key><Code>AccessDenied</Code><Message>Access Denied</Message>\
+            </Error></DeleteResult>";
+        let headers = vec![("content-type".to_string(), "application/xml".to_string())];
+        mock.inject(1, 200, headers, body.into(), |x| {
+            x.operation() == "DeleteObjects"
+        });
+        let error = client
+            .delete_prefix("/c/", |_| true)
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("Deleted 1 objects"), "{error}");
+        assert!(error.contains("'/c/2': Access Denied"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn concurrent_creations_of_the_bucket_converge() {
+        let mock = MockS3::start().await;
+        let (first, second) = (
+            mock_s3::create_client(&mock, json!({})).await,
+            mock_s3::create_client(&mock, json!({})).await,
+        );
+        mock.set_delay(Some(Duration::from_millis(100)));
+        // Some stores report the bucket created concurrently as already existing.
+        let body = mock_s3::error_body("BucketAlreadyExists", "Injected failure");
+        mock.inject(1, 409, vec![], body, |x| x.operation() == "CreateBucket");
+
+        let (first, second) = tokio::join!(first.create_bucket(true), second.create_bucket(true));
+        let created = [first.unwrap(), second.unwrap()]
+            .iter()
+            .filter(|x| x.is_some())
+            .count();
+        assert_eq!(created, 1);
+        assert!(mock.bucket_exists());
+    }
+
+    #[tokio::test]
+    async fn retries_stop_once_the_retry_budget_is_exhausted() {
+        let mock = MockS3::start().await;
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "retry_budget": 12, "bucket_retries": 10, "bucket_retry_backoff_ms": 1 }),
+        )
+        .await;
+        // Errors which the SDK doesn't retry by itself.
+        mock.fail("CreateBucket", 10, 409, "OperationAborted");
+
+        assert!(client.create_bucket(true).await.is_err());
+        // Each retry withdraws 5 tokens: 2 retries fit in the budget.
+        assert_eq!(mock.requests_of("CreateBucket").len(), 3);
+        assert_eq!(client.retry_budget_level(), Some(2));
+    }
+
+    #[tokio::test]
+    async fn gets_of_archived_objects_initiate_their_restoration() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        mock.set_header("/a", "x-amz-storage-class", "GLACIER");
+
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        let error = client.get_object("/a").await.err().unwrap();
+        assert!(error.to_string().contains("must be restored"), "{error}");
+        assert!(mock.requests_of("RestoreObject").is_empty());
+
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "restore_archived": { "days": 2, "tier": "bulk", "max_wait_ms": 0 } }),
+        )
+        .await;
+        let error = client.get_object("/a").await.err().unwrap();
+        assert!(
+            error.to_string().contains("restoration is in progress"),
+            "{error}"
+        );
+        let request = &mock.requests_of("RestoreObject")[0];
+        let body = String::from_utf8_lossy(&request.body);
+        assert!(body.contains("<Days>2</Days>"), "{body}");
+        assert!(body.contains("<Tier>Bulk</Tier>"), "{body}");
+
+        // The get waiting for the restoration retrieves the object once restored.
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "restore_archived": { "days": 2, "max_wait_ms": 100 } }),
+        )
+        .await;
+        mock.set_header("/a", "x-amz-restore", "ongoing-request=\"false\"");
+        mock.fail("GetObject", 1, 403, "InvalidObjectState");
+        let object = client.get_object("/a").await.unwrap().unwrap();
+        assert_eq!(object.body, b"1");
+        assert_eq!(mock.requests_of("RestoreObject").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn bodies_shorter_than_their_content_length_are_detected() {
+        // A gateway declaring the length of the complete object while streaming a part of it.
+        let url = mock_s3::start_raw(
+            b"HTTP/1.1 200 OK\r\nContent-Length: 5\r\nTransfer-Encoding: chunked\r\n\
+            Connection: close\r\n\r\n3\r\nhel\r\n0\r\n\r\n",
+        )
+        .await;
+
+        let client = mock_s3::create_client_at(&url, json!({})).await;
+        let error = client.get_object("/a").await.err().unwrap();
+        let error = error.to_string();
+        assert!(error.contains("Retrieved 3 bytes"), "{error}");
+        assert!(error.contains("Content-Length is 5"), "{error}");
+
+        let client =
+            mock_s3::create_client_at(&url, json!({ "content_length_mismatch": "warn" })).await;
+        let object = client.get_object("/a").await.unwrap().unwrap();
+        assert_eq!(object.body, b"hel");
+    }
+
+    #[tokio::test]
+    async fn operations_retried_beyond_their_deadline_are_aborted() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({ "operation_deadline_ms": 300 })).await;
+        // The attempts allowed by the SDK take longer than the deadline.
+        mock.set_delay(Some(Duration::from_millis(150)));
+        mock.fail("GetObject", 3, 503, "SlowDown");
+
+        let start = Instant::now();
+        let error = client.get_object("/a").await.err().unwrap();
+        assert!(
+            error.to_string().contains("exceeded its deadline"),
+            "{error}"
+        );
+        assert!(start.elapsed() < Duration::from_secs(1));
+        assert!(!mock.requests_of("GetObject").is_empty());
+
+        // The operations completing in time succeed, once retried past the remaining failures.
+        mock.set_delay(None);
+        let client = mock_s3::create_client(&mock, json!({ "operation_deadline_ms": 30000 })).await;
+        let object = client.get_object("/a").await.unwrap().unwrap();
+        assert_eq!(object.body, b"1");
+    }
+
+    #[tokio::test]
+    async fn requests_fail_over_from_a_dead_endpoint() {
+        async fn failover_client(endpoints: Vec<String>, max_failovers: Option<usize>) -> S3Client {
+            let config = S3Config::new(&mock_s3::storage_config(json!({})))
+                .await
+                .unwrap();
+            let connection_config = ConnectionConfig {
+                max_failovers,
+                ..Default::default()
+            };
+            S3Client::new(
+                &config,
+                Some("us-east-1".to_string()),
+                endpoints,
+                None,
+                None,
+                None,
+                &connection_config,
+                None,
+            )
+            .await
+            .unwrap()
+        }
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        // The port of a closed listener refuses the connections.
+        let dead = {
+            let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+            format!("http://{}", listener.local_addr().unwrap())
+        };
+
+        let client = failover_client(vec![dead.to_owned(), mock.url.to_owned()], None).await;
+        assert_eq!(client.active_endpoint(), Some(dead.as_str()));
+        // The request failing to connect is not resent, the next ones are sent to the secondary.
+        let _ = client.get_object("/a").await;
+        assert_eq!(client.active_endpoint(), Some(mock.url.as_str()));
+        let object = client.get_object("/a").await.unwrap().unwrap();
+        assert_eq!(object.body, b"1");
+
+        // No failover happens once the maximum is reached.
+        let client = failover_client(vec![dead.to_owned(), mock.url.to_owned()], Some(0)).await;
+        assert!(client.get_object("/a").await.is_err());
+        assert_eq!(client.active_endpoint(), Some(dead.as_str()));
+    }
+
+    #[tokio::test]
+    async fn requests_are_sent_under_the_base_path_of_the_endpoint() {
+        let mock = MockS3::start().await;
+        let client = mock_s3::create_client_at(&format!("{}/s3-api", mock.url), json!({})).await;
+        // The mock doesn't serve the base path, only the path of the request matters.
+        let _ = client.get_object_metadata("a").await;
+        let requests = mock.requests();
+        assert!(!requests.is_empty());
+        for request in requests {
+            assert_eq!(
+                request.path,
+                format!("/s3-api/{}/a", mock_s3::BUCKET),
+                "{request:?}"
+            );
+        }
+
+        let config = S3Config::new(&mock_s3::storage_config(json!({})))
+            .await
+            .unwrap();
+        let err = S3Client::new(
+            &config,
+            Some("us-east-1".to_string()),
+            vec!["http://invalid endpoint".to_string()],
+            None,
+            None,
+            None,
+            &ConnectionConfig::default(),
+            None,
+        )
+        .await
+        .err()
+        .unwrap();
+        assert!(err.to_string().contains("Invalid endpoint"), "{err}");
+    }
+
+    #[tokio::test]
+    async fn missing_timestamps_are_stamped_in_the_order_of_the_keys() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/c", "/a", "/b"] {
+            mock.insert_object(key, b"1", &[("origin", "import")]);
+        }
+        mock.insert_object("/d", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.insert_object("__zenoh__/index", b"1", &[]);
+        let headers = [
+            ("content-type", "text/csv"),
+            ("content-encoding", "gzip"),
+            ("content-disposition", "attachment; filename=\"b.csv\""),
+            ("content-language", "fr"),
+            ("cache-control", "max-age=60"),
+        ];
+        for (name, value) in headers {
+            mock.set_header("/b", name, value);
+        }
+        let client = mock_s3::create_client(&mock, json!({ "maintenance_concurrency": 2 })).await;
+
+        let synthesizer = TimestampSynthesizer::new(crate::config::TimestampSource::WallClock);
+        let (scanned, stamped) = client
+            .stamp_missing_timestamps(&synthesizer, "__zenoh__/")
+            .await
+            .unwrap();
+        assert_eq!((scanned, stamped), (4, 3));
+        let timestamps: Vec<_> = ["/a", "/b", "/c"]
+            .iter()
+            .map(|key| {
+                let object = mock.object(key).unwrap();
+                let metadata = object.metadata();
+                assert_eq!(metadata.get("origin").unwrap(), "import", "{key}");
+                Timestamp::from_str(metadata.get(TIMESTAMP_METADATA_KEY).unwrap()).unwrap()
+            })
+            .collect();
+        assert!(timestamps.windows(2).all(|x| x[0] < x[1]), "{timestamps:?}");
+        // The system headers are kept along with the metadata.
+        let object = mock.object("/b").unwrap();
+        for (name, value) in headers {
+            assert_eq!(object.headers.get(name).unwrap(), value, "{name}");
+        }
+        assert_eq!(
+            mock.object("/d")
+                .unwrap()
+                .metadata()
+                .get(TIMESTAMP_METADATA_KEY),
+            Some(&timestamp)
+        );
+        assert!(mock
+            .object("__zenoh__/index")
+            .unwrap()
+            .metadata()
+            .is_empty());
+        assert_eq!(mock.requests_of("CopyObject").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn objects_are_reencrypted_with_the_configured_kms_key() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a", "/b", "/c"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        for (key, key_id) in [("/b", "key-2"), ("/c", "key-1")] {
+            mock.set_header(key, "x-amz-server-side-encryption", "aws:kms");
+            mock.set_header(
+                key,
+                "x-amz-server-side-encryption-aws-kms-key-id",
+                &format!("arn:aws:kms:us-east-1:111122223333:key/{key_id}"),
+            );
+        }
+        mock.set_header("/a", "x-amz-storage-class", "STANDARD_IA");
+        let client = mock_s3::create_client(&mock, json!({ "sse_kms_key_id": "key-2" })).await;
+
+        assert_eq!(client.reencrypt_objects().await.unwrap(), (3, 2));
+        for key in ["/a", "/c"] {
+            let object = mock.object(key).unwrap();
+            assert_eq!(
+                object.headers.get("x-amz-server-side-encryption").unwrap(),
+                "aws:kms",
+                "{key}"
+            );
+            assert!(object
+                .headers
+                .get("x-amz-server-side-encryption-aws-kms-key-id")
+                .unwrap()
+                .ends_with("key-2"));
+            assert_eq!(object.body, b"1");
+            assert_eq!(
+                object.metadata().get(TIMESTAMP_METADATA_KEY),
+                Some(&timestamp)
+            );
+        }
+        let object = mock.object("/a").unwrap();
+        assert_eq!(
+            object.headers.get("x-amz-storage-class").unwrap(),
+            "STANDARD_IA"
+        );
+        // The objects are copied on the server side, and not anymore once re-encrypted.
+        let copies = mock.requests_of("CopyObject");
+        assert_eq!(copies.len(), 2);
+        assert!(copies.iter().all(|copy| copy.body.is_empty()));
+        assert_eq!(client.reencrypt_objects().await.unwrap(), (3, 0));
+    }
+
+    #[tokio::test]
+    async fn created_buckets_are_tagged_with_the_configured_tags() {
+        let volume_cfg = json!({ "bucket_tags": { "team": "robotics", "env": "test" } });
+        let mock = MockS3::start().await;
+        let client = mock_s3::create_client(&mock, volume_cfg.clone()).await;
+        assert!(client.create_bucket(true).await.unwrap().is_some());
+        assert_eq!(mock.requests_of("PutBucketTagging").len(), 1);
+        let tagging = mock.bucket_tagging().unwrap();
+        for tag in [
+            "<Key>team</Key><Value>robotics</Value>",
+            "<Key>env</Key><Value>test</Value>",
+        ] {
+            assert!(tagging.contains(tag), "{tagging}");
+        }
+
+        // The tags of a reused bucket are left untouched unless they are managed.
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let client = mock_s3::create_client(&mock, volume_cfg).await;
+        assert!(client.create_bucket(true).await.unwrap().is_none());
+        assert!(mock.requests_of("PutBucketTagging").is_empty());
+        assert_eq!(mock.bucket_tagging(), None);
+
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "bucket_tags": { "team": "robotics" }, "manage_bucket_tags": true }),
+        )
+        .await;
+        assert!(client.create_bucket(true).await.unwrap().is_none());
+        assert_eq!(mock.requests_of("PutBucketTagging").len(), 1);
+        assert!(mock.bucket_tagging().unwrap().contains("<Key>team</Key>"));
+    }
+
+    #[tokio::test]
+    async fn buckets_beyond_a_listing_page_are_emptied_concurrently() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        for i in 0..2500 {
+            mock.insert_object(&format!("/k{i:04}"), b"1", &[]);
+        }
+        let client = mock_s3::create_client(&mock, json!({ "empty_bucket_concurrency": 3 })).await;
+        let delay = Duration::from_millis(200);
+        mock.set_delay(Some(delay));
+
+        // The 3 pages are listed in turn, each one being deleted while the next one is listed.
+        let start = Instant::now();
+        client.empty_bucket().await.unwrap();
+        assert!(start.elapsed() < delay * 5, "{:?}", start.elapsed());
+        assert!(mock.keys().is_empty());
+        assert_eq!(mock.requests_of("ListObjectsV2").len(), 3);
+        assert_eq!(mock.requests_of("DeleteObjects").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn buckets_are_emptied_retrying_transient_errors_by_default() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        // The SDK doesn't retry the requests itself.
+        let client = mock_s3::create_client(
+            &mock,
+            json!({ "bucket_retry_backoff_ms": 1, "sdk_max_attempts": 1 }),
+        )
+        .await;
+        mock.clear_requests();
+        mock.fail("ListObjectsV2", 1, 503, "SlowDown");
+        mock.fail("DeleteObjects", 1, 500, "InternalError");
+
+        client.empty_bucket().await.unwrap();
+        assert!(mock.keys().is_empty());
+        assert_eq!(mock.requests_of("ListObjectsV2").len(), 2);
+        assert_eq!(mock.requests_of("DeleteObjects").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn objects_failing_to_be_deleted_fail_the_emptying() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        let body = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<DeleteResult>\
+            <Error><Key>/a</Key><Code>AccessDenied</Code>\
+            <Message>Access Denied</Message></Error></DeleteResult>";
+        let headers = vec![("content-type".to_string(), "application/xml".to_string())];
+        mock.inject(1, 200, headers, body.into(), |x| {
+            x.operation() == "DeleteObjects"
+        });
+
+        let error = client.empty_bucket().await.unwrap_err().to_string();
+        assert!(error.contains("Couldn't delete 1 objects"), "{error}");
+        assert!(error.contains("'/a': Access Denied"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn errors_carry_the_ids_of_the_failed_requests() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        mock.inject(
+            1,
+            403,
+            vec![("x-amz-id-2".to_string(), "mock-extended-id".to_string())],
+            mock_s3::error_body("AccessDenied", "Access Denied"),
+            |request| request.operation() == "DeleteObject",
+        );
+        let error = client.delete_object("/a".to_string()).await.err().unwrap();
+        let message = error.to_string();
+        assert!(message.contains("request id: mock-request-id"), "{message}");
+        assert!(
+            message.contains("extended request id: mock-extended-id"),
+            "{message}"
+        );
+    }
+
+    #[tokio::test]
+    async fn health_checks_fail_with_the_stage_at_fault() {
+        let mock = MockS3::start().await;
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        // A missing bucket may still be created afterwards.
+        client
+            .health_check(Some(mock.url.as_str()), None)
+            .await
+            .unwrap();
+        mock.create_bucket(false);
+        client
+            .health_check(Some(mock.url.as_str()), None)
+            .await
+            .unwrap();
+
+        let error = client
+            .health_check(Some("http://zenoh-s3.invalid:9000"), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("couldn't resolve host"),
+            "{error}"
+        );
+
+        let closed_port = std::net::TcpListener::bind("127.0.0.1:0")
+            .unwrap()
+            .local_addr()
+            .unwrap()
+            .port();
+        let endpoint = format!("http://127.0.0.1:{closed_port}");
+        let error = client
+            .health_check(Some(endpoint.as_str()), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(error.to_string().contains("couldn't connect"), "{error}");
+
+        mock.fail("HeadBucket", 1, 403, "AccessDenied");
+        let error = client
+            .health_check(Some(mock.url.as_str()), None)
+            .await
+            .err()
+            .unwrap();
+        assert!(
+            error.to_string().contains("HEAD request on bucket"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn parts_in_progress_are_bounded_across_the_uploads() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let client = mock_s3::create_client(
+            &mock,
+            json!({
+                "multipart": {
+                    "threshold": 16,
+                    "part_size": 8,
+                    "min_part_size": 8,
+                    "max_concurrent_uploads": 2,
+                    "max_concurrent_parts": 3,
+                },
+            }),
+        )
+        .await;
+        mock.set_delay(Some(Duration::from_millis(50)));
+
+        let value: Vec<u8> = (0..40).collect();
+        let puts =
+            (0..4).map(|i| client.put_object(format!("/k{i}"), Value::from(value.clone()), None));
+        for result in futures::future::join_all(puts).await {
+            result.unwrap();
+        }
+        assert_eq!(mock.keys().len(), 4);
+        assert_eq!(mock.requests_of("UploadPart").len(), 20);
+        assert_eq!(mock.max_in_flight("UploadPart"), 3);
+        assert!(mock.max_in_flight("CreateMultipartUpload") <= 2);
+    }
+
+    #[tokio::test]
+    async fn heads_redirected_to_the_region_of_the_bucket_are_sent_again() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let client = mock_s3::create_client(&mock, json!({ "region_mismatch": "follow" })).await;
+        mock.inject(
+            1,
+            301,
+            vec![("x-amz-bucket-region".to_string(), "eu-west-1".to_string())],
+            vec![],
+            |request| request.operation() == "HeadObject",
+        );
+
+        assert!(client
+            .get_head_object_if_exists("/a")
+            .await
+            .unwrap()
+            .is_some());
+        let requests = mock.requests_of("HeadObject");
+        assert_eq!(requests.len(), 2);
+        let authorization = requests[1].header("authorization").unwrap();
+        assert!(authorization.contains("/eu-west-1/s3/"), "{authorization}");
+        // The requests which follow are sent to the region of the bucket straight away.
+        assert!(client
+            .get_head_object_if_exists("/b")
+            .await
+            .unwrap()
+            .is_none());
+        assert_eq!(mock.requests_of("HeadObject").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn operations_out_of_the_bucket_prefix_are_rejected() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("tenant-b//a", b"2", &[]);
+        let client = mock_s3::create_client(&mock, json!({ "bucket_prefix": "tenant-a/" })).await;
+
+        client
+            .put_object("/a".to_string(), Value::from("1"), None)
+            .await
+            .unwrap();
+        assert_eq!(mock.keys(), vec!["tenant-a//a", "tenant-b//a"]);
+        assert!(client.get_object("/a").await.unwrap().is_some());
+        let (objects, _) = client.list_objects_page(None, None).await.unwrap();
+        let keys: Vec<_> = objects.iter().filter_map(|object| object.key()).collect();
+        assert_eq!(keys, vec!["/a"]);
+
+        mock.clear_requests();
+        for key in ["/../tenant-b//a", "/a/../../tenant-b//a", "/./a"] {
+            assert!(client.get_object(key).await.is_err(), "{key}");
+            assert!(client
+                .put_object(key.to_string(), Value::from("3"), None)
+                .await
+                .is_err());
+            assert!(
+                client.delete_object(key.to_string()).await.is_err(),
+                "{key}"
+            );
+        }
+        assert!(mock.requests().is_empty());
+        assert_eq!(mock.object("tenant-b//a").unwrap().body, b"2");
+    }
+
+    #[tokio::test]
+    async fn objects_are_exported_under_the_directory_with_their_metadata() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        // The keys of the default layout start with a slash, unlike those of the legacy layouts.
+        for key in ["/a/b", "/c", "legacy/d"] {
+            mock.insert_object(key, key.as_bytes(), &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("export");
+
+        assert_eq!(
+            client.export_objects(&dir, 2, |_| true).await.unwrap(),
+            (3, 0)
+        );
+        for (key, path) in [("/a/b", "a/b"), ("/c", "c"), ("legacy/d", "legacy/d")] {
+            assert_eq!(std::fs::read(dir.join(path)).unwrap(), key.as_bytes());
+            let sidecar =
+                std::fs::read(dir.join(format!("{path}{EXPORT_SIDECAR_SUFFIX}"))).unwrap();
+            let sidecar: serde_json::Value = serde_json::from_slice(&sidecar).unwrap();
+            assert_eq!(sidecar["e_tag"], json!(mock.object(key).unwrap().e_tag));
+            assert_eq!(
+                sidecar["metadata"][TIMESTAMP_METADATA_KEY],
+                json!(timestamp)
+            );
+        }
+        // The objects already exported are skipped upon the next export.
+        assert_eq!(
+            client.export_objects(&dir, 2, |_| true).await.unwrap(),
+            (0, 3)
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_escaping_the_export_directory_are_rejected() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in [
+            "/a",
+            "/../escaped",
+            "/b/../../escaped",
+            "/./c",
+            "/d//e",
+            "//f",
+        ] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let client = mock_s3::create_client(&mock, json!({})).await;
+        let parent = tempfile::tempdir().unwrap();
+        let dir = parent.path().join("export");
+
+        let error = client
+            .export_objects(&dir, 2, |_| true)
+            .await
+            .err()
+            .unwrap()
+            .to_string();
+        assert!(error.starts_with("Exported 1 objects"), "{error}");
+        for key in ["/../escaped", "/b/../../escaped", "/./c", "/d//e", "//f"] {
+            assert!(error.contains(&format!("'{key}'")), "{error}");
+        }
+        assert!(!parent.path().join("escaped").exists());
+        let mut files: Vec<_> = std::fs::read_dir(&dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        files.sort();
+        assert_eq!(files, vec!["a", "a.metadata.json"]);
+        // No object was retrieved but the exported one.
+        assert_eq!(mock.requests_of("GetObject").len(), 1);
     }
 }
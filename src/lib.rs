@@ -14,25 +14,39 @@
 
 pub mod client;
 pub mod config;
+mod log_store;
+#[cfg(test)]
+mod mock_s3;
 pub mod utils;
 
 use async_std::sync::Arc;
 use async_trait::async_trait;
 
-use client::S3Client;
-use config::{S3Config, TlsClientConfig, TLS_PROP};
-use futures::future::join_all;
-use futures::stream::FuturesUnordered;
+use aws_sdk_s3::model::Object;
+use client::{S3Client, SelectFormat};
+use config::{
+    ConnectionConfig, Durability, EmptyKeys, EmptyValues, EntriesOrder, HttpVersion,
+    ListingConsistency, MaxEntriesPolicy, MissingTimestamps, RegionMismatch, S3Config,
+    TimestampSource, TlsClientConfig, TrailingSlashKeys, TLS_PROP,
+};
+use futures::future::{join_all, Future};
+use futures::stream::{self, FuturesUnordered, StreamExt};
+use log_store::LogStore;
 #[cfg(feature = "dynamic_plugin")]
 use tokio::runtime::Runtime;
-use utils::S3Key;
+use utils::{
+    EntriesIndex, EntriesSpill, KeyOptions, MaintenanceOperation, MaintenanceReports,
+    PendingOperations, S3Key, TimestampSynthesizer, UploadBudget,
+};
 use zenoh_plugin_trait::{plugin_version, Plugin};
 
 #[cfg(feature = "dynamic_plugin")]
 use lazy_static::lazy_static;
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::convert::TryFrom;
 use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+use std::time::Duration;
 use std::vec;
 
 use zenoh::prelude::*;
@@ -46,12 +60,67 @@ use zenoh_core::zerror;
 // Properties used by the Backend
 pub const PROP_S3_ENDPOINT: &str = "url";
 pub const PROP_S3_REGION: &str = "region";
+pub const PROP_S3_METADATA_ENDPOINT: &str = "metadata_url";
+pub const PROP_S3_SIGNING_NAME: &str = "signing_name";
+pub const PROP_S3_USE_FIPS: &str = "use_fips";
+pub const PROP_S3_FAILOVER_ENDPOINTS: &str = "failover_urls";
+pub const PROP_S3_MAX_FAILOVERS: &str = "max_failovers";
+// Environment variables used, following the AWS SDK conventions, when the endpoint or the region
+// are not specified in the configuration.
+const ENV_AWS_ENDPOINT_URL: [&str; 2] = ["AWS_ENDPOINT_URL_S3", "AWS_ENDPOINT_URL"];
+const ENV_AWS_REGION: [&str; 2] = ["AWS_REGION", "AWS_DEFAULT_REGION"];
+const ENV_AWS_USE_FIPS_ENDPOINT: [&str; 1] = ["AWS_USE_FIPS_ENDPOINT"];
+const ENV_AWS_CONFIG_FILE: [&str; 1] = ["AWS_CONFIG_FILE"];
+const ENV_AWS_PROFILE: [&str; 1] = ["AWS_PROFILE"];
+pub const PROP_S3_MAX_IN_FLIGHT_UPLOAD_BYTES: &str = "max_in_flight_upload_bytes";
+pub const PROP_S3_HTTP_VERSION: &str = "http_version";
+pub const PROP_S3_TCP_KEEPALIVE_MS: &str = "tcp_keepalive_ms";
+pub const PROP_S3_IDLE_TIMEOUT_MS: &str = "idle_timeout_ms";
 
-// Special key for None (when the prefix being stripped exactly matches the key)
+// Parameters of the queries filtering the content of an object on the server side with S3 Select,
+// e.g. `s3/example/data?s3_select=SELECT * FROM s3object s WHERE s.city = 'Paris'`.
+pub const PARAM_S3_SELECT: &str = "s3_select";
+pub const PARAM_S3_SELECT_FORMAT: &str = "s3_select_format";
+// Parameter of the queries triggering a maintenance operation of the storage in the background,
+// e.g. `s3/example?s3_operation=compact`, whose progress is reported in its admin status.
+pub const PARAM_S3_OPERATION: &str = "s3_operation";
+
+// Name of the prefix of the segment objects of the log-structured mode, under the reserved prefix.
+const LOG_SEGMENTS_NAME: &str = "segments/";
+
+// Special key for None (when the prefix being stripped exactly matches the key), whose object is
+// stored under the reserved prefix.
 pub const NONE_KEY: &str = "@@none_key@@";
 
+// Error code returned by S3 upon an operation on a bucket which doesn't exist.
+const NO_SUCH_BUCKET_ERROR_CODE: &str = "NoSuchBucket";
+
 // Metadata keys
+// Default key of the timestamp metadata, which can be overridden with the `timestamp_metadata_key`
+// option.
 pub const TIMESTAMP_METADATA_KEY: &str = "timestamp_uhlc";
+// The zenoh encoding is stored under a dedicated metadata field (sent as the
+// `x-amz-meta-zenoh-encoding` header) rather than as the HTTP Content-Encoding, which may
+// otherwise collide with a transfer encoding such as a compression.
+pub const ENCODING_METADATA_KEY: &str = "zenoh-encoding";
+// Size of the metadata stored as a prefix of the object body when it exceeds the size allowed by
+// S3 for the metadata (see the `spill_oversized_metadata` option).
+pub const SPILLED_METADATA_KEY: &str = "zenoh-spilled-metadata";
+// Percent-encoded complete key of an object whose key was truncated and hashed to fit within the
+// length allowed by S3 (see [utils::fit_object_key]).
+pub const FULL_KEY_METADATA_KEY: &str = "zenoh-full-key";
+// Length of the value stored in an object, which may differ from the length of its body (see the
+// `preserve_original_length` option).
+pub const ORIGINAL_LENGTH_METADATA_KEY: &str = "zenoh-original-length";
+// SHA-256 of the body of an object, compared to skip the upload of unchanged values (see the
+// `skip_unchanged_puts` option).
+pub const CONTENT_CHECKSUM_METADATA_KEY: &str = "zenoh-content-sha256";
+// Version of the format of an object (i.e. of its body and metadata), sent as the
+// `x-amz-meta-zenoh-format-version` header, as a hook for the future migrations. The objects
+// without it are read as of the first version.
+pub const FORMAT_VERSION_METADATA_KEY: &str = "zenoh-format-version";
+// Version of the format of the objects written by this backend.
+const FORMAT_VERSION: &str = "1";
 
 // Amount of worker threads to be used by the tokio runtime of the [S3Storage] to handle incoming
 // operations.
@@ -89,8 +158,20 @@ impl Plugin for S3Backend {
             .rest
             .insert("version".into(), Self::PLUGIN_LONG_VERSION.into());
 
-        let endpoint = get_optional_string_property(PROP_S3_ENDPOINT, &config)?;
-        let region = get_optional_string_property(PROP_S3_REGION, &config)?;
+        let endpoint = get_optional_string_property(PROP_S3_ENDPOINT, &config)?
+            .or_else(|| get_env_variable(&ENV_AWS_ENDPOINT_URL))
+            .or_else(get_shared_config_endpoint)
+            .map(|endpoint| normalize_endpoint(PROP_S3_ENDPOINT, endpoint))
+            .transpose()?;
+        let region = get_optional_string_property(PROP_S3_REGION, &config)?
+            .or_else(|| get_env_variable(&ENV_AWS_REGION));
+        let endpoint = match load_use_fips(&config)? {
+            true => Some(load_fips_endpoint(endpoint, region.as_ref())?),
+            false => endpoint,
+        };
+        let failover_endpoints = load_failover_endpoints(&config, endpoint.as_ref())?;
+        let metadata_endpoint = load_metadata_endpoint(&config, endpoint.as_ref())?;
+        let signing_name = load_signing_name(&config, endpoint.as_ref())?;
 
         let mut properties = Properties::default();
         properties.insert("version".into(), Self::PLUGIN_LONG_VERSION.into());
@@ -100,13 +181,20 @@ impl Plugin for S3Backend {
             .map(|(k, v)| (k, serde_json::Value::String(v)))
             .collect();
 
-        let tls_config = load_tls_config(&config)?;
+        let connection_config = load_connection_config(&config)?;
+        let tls_config = load_tls_config(&config, &connection_config)?;
+        let upload_budget = load_upload_budget(&config)?;
 
         Ok(Box::new(S3Volume {
             admin_status,
             endpoint,
+            failover_endpoints,
+            metadata_endpoint,
+            signing_name,
             region,
             tls_config,
+            connection_config,
+            upload_budget,
         }))
     }
 }
@@ -122,19 +210,296 @@ fn get_optional_string_property(property: &str, config: &VolumeConfig) -> ZResul
     }
 }
 
-fn load_tls_config(config: &VolumeConfig) -> ZResult<Option<TlsClientConfig>> {
+/// Returns the value of the first of the given environment variables which is set and not empty.
+fn get_env_variable(variables: &[&str]) -> Option<String> {
+    variables.iter().find_map(|variable| {
+        std::env::var(variable)
+            .ok()
+            .filter(|value| !value.is_empty())
+            .map(|value| {
+                tracing::debug!("Using environment variable '{variable}': '{value}'.");
+                value
+            })
+    })
+}
+
+/// Returns the endpoint of S3 configured for the current profile (`AWS_PROFILE`, or else
+/// `default`) in the AWS shared config file (`AWS_CONFIG_FILE`, or else `~/.aws/config`), if any.
+fn get_shared_config_endpoint() -> Option<String> {
+    let path = get_env_variable(&ENV_AWS_CONFIG_FILE).or_else(|| {
+        get_env_variable(&["HOME", "USERPROFILE"]).map(|home| format!("{home}/.aws/config"))
+    })?;
+    let content = match std::fs::read_to_string(&path) {
+        Ok(content) => content,
+        Err(e) => {
+            tracing::debug!("Couldn't read the AWS shared config file '{path}': {e}");
+            return None;
+        }
+    };
+    let profile = get_env_variable(&ENV_AWS_PROFILE).unwrap_or_else(|| "default".to_string());
+    let endpoint = utils::shared_config_endpoint(&content, &profile)?;
+    tracing::debug!("Using the endpoint of profile '{profile}' in '{path}': '{endpoint}'.");
+    Some(endpoint)
+}
+
+fn load_use_fips(config: &VolumeConfig) -> ZResult<bool> {
+    match config.rest.get(PROP_S3_USE_FIPS) {
+        Some(serde_json::Value::Bool(use_fips)) => Ok(*use_fips),
+        None => Ok(get_env_variable(&ENV_AWS_USE_FIPS_ENDPOINT)
+            .map_or(false, |use_fips| use_fips.eq_ignore_ascii_case("true"))),
+        _ => Err(zerror!("Property '{PROP_S3_USE_FIPS}' for S3 Backend must be a boolean.").into()),
+    }
+}
+
+/// Resolves the FIPS endpoint of the [region], in its partition. The default resolver of the SDK
+/// doesn't resolve the FIPS endpoints, hence they are set as custom endpoints.
+fn load_fips_endpoint(endpoint: Option<String>, region: Option<&String>) -> ZResult<String> {
+    if let Some(endpoint) = endpoint {
+        return Err(zerror!(
+            "Property '{PROP_S3_USE_FIPS}' can't be combined with a custom endpoint ('{endpoint}')."
+        )
+        .into());
+    }
+    let region = region.ok_or_else(|| {
+        zerror!("Property '{PROP_S3_USE_FIPS}' requires '{PROP_S3_REGION}' to be specified.")
+    })?;
+    let endpoint = utils::fips_endpoint(region)?;
+    tracing::debug!("Using FIPS endpoint '{endpoint}' for region '{region}'.");
+    Ok(endpoint)
+}
+
+/// Loads the service name the requests are signed with instead of `s3`, which requires the
+/// endpoint of the gateway to be specified as well.
+fn load_signing_name(config: &VolumeConfig, endpoint: Option<&String>) -> ZResult<Option<String>> {
+    match get_optional_string_property(PROP_S3_SIGNING_NAME, config)? {
+        Some(signing_name) if signing_name.is_empty() => {
+            Err(zerror!("Property '{PROP_S3_SIGNING_NAME}' must not be empty.").into())
+        }
+        // The signing name is set along with the endpoint, the AWS endpoints being signed as `s3`.
+        Some(_) if endpoint.is_none() => Err(zerror!(
+            "Property '{PROP_S3_SIGNING_NAME}' requires the endpoint of the gateway to be \
+            specified with '{PROP_S3_ENDPOINT}'."
+        )
+        .into()),
+        signing_name => Ok(signing_name),
+    }
+}
+
+/// Checks that the [endpoint] specified by the [property] is a valid URL without a query, and
+/// strips its trailing slashes.
+///
+/// The path of the endpoint, if any, is kept as the base path of the requests (e.g. for a gateway
+/// mounting the S3 API under `/s3-api` behind a shared reverse proxy): the SDK prepends it to the
+/// path of each request, which is signed as such.
+fn normalize_endpoint(property: &str, endpoint: String) -> ZResult<String> {
+    let uri = endpoint
+        .parse::<http::Uri>()
+        .map_err(|e| zerror!("Property '{property}' is not a valid endpoint: {e}"))?;
+    if uri.scheme().is_none() || uri.authority().is_none() {
+        return Err(zerror!(
+            "Property '{property}' must be an absolute URL (e.g. 'https://host:port'), got \
+            '{endpoint}'."
+        )
+        .into());
+    }
+    if uri.query().is_some() {
+        return Err(zerror!("Property '{property}' can't contain a query: '{endpoint}'.").into());
+    }
+    let endpoint = endpoint.trim_end_matches('/').to_string();
+    let base_path = uri.path().trim_end_matches('/');
+    if !base_path.is_empty() {
+        tracing::debug!("Requests to '{endpoint}' are sent under the base path '{base_path}'.");
+    }
+    Ok(endpoint)
+}
+
+/// Loads the endpoints the requests fail over to, in order, upon connection failures to the
+/// endpoint, which must be specified as well.
+fn load_failover_endpoints(
+    config: &VolumeConfig,
+    endpoint: Option<&String>,
+) -> ZResult<Vec<String>> {
+    let failover_endpoints = match config.rest.get(PROP_S3_FAILOVER_ENDPOINTS) {
+        Some(serde_json::Value::Array(values)) => values
+            .iter()
+            .map(|value| match value {
+                serde_json::Value::String(value) => {
+                    normalize_endpoint(PROP_S3_FAILOVER_ENDPOINTS, value.to_owned())
+                }
+                _ => Err(zerror!(
+                    "Property '{PROP_S3_FAILOVER_ENDPOINTS}' must be an array of strings."
+                )),
+            })
+            .collect::<Result<Vec<_>, _>>()?,
+        None => return Ok(vec![]),
+        _ => {
+            return Err(zerror!(
+                "Property '{PROP_S3_FAILOVER_ENDPOINTS}' must be an array of strings."
+            )
+            .into())
+        }
+    };
+    let endpoint = endpoint.ok_or_else(|| {
+        zerror!(
+            "Property '{PROP_S3_FAILOVER_ENDPOINTS}' requires '{PROP_S3_ENDPOINT}' to be \
+            specified."
+        )
+    })?;
+    if failover_endpoints.contains(endpoint) {
+        return Err(zerror!(
+            "Property '{PROP_S3_FAILOVER_ENDPOINTS}' can't contain the endpoint '{endpoint}'."
+        )
+        .into());
+    }
+    Ok(failover_endpoints)
+}
+
+/// Loads the endpoint the HEAD requests of the listings are sent to, which requires the endpoint of
+/// the data to be specified as well.
+fn load_metadata_endpoint(
+    config: &VolumeConfig,
+    endpoint: Option<&String>,
+) -> ZResult<Option<String>> {
+    let metadata_endpoint = match get_optional_string_property(PROP_S3_METADATA_ENDPOINT, config)? {
+        Some(metadata_endpoint) => {
+            normalize_endpoint(PROP_S3_METADATA_ENDPOINT, metadata_endpoint)?
+        }
+        None => return Ok(None),
+    };
+    let endpoint = endpoint.ok_or_else(|| {
+        zerror!(
+            "Property '{PROP_S3_METADATA_ENDPOINT}' requires the endpoint of the data to be \
+            specified as well with '{PROP_S3_ENDPOINT}'."
+        )
+    })?;
+    if metadata_endpoint == *endpoint {
+        tracing::debug!(
+            "Property '{PROP_S3_METADATA_ENDPOINT}' is the same as '{PROP_S3_ENDPOINT}'."
+        );
+        return Ok(None);
+    }
+    Ok(Some(metadata_endpoint))
+}
+
+fn load_tls_config(
+    config: &VolumeConfig,
+    connection_config: &ConnectionConfig,
+) -> ZResult<Option<TlsClientConfig>> {
+    let http_version = load_http_version(config)?;
     match config.rest.get(TLS_PROP) {
-        Some(serde_json::Value::Object(tls_config)) => Ok(Some(TlsClientConfig::new(tls_config)?)),
+        Some(serde_json::Value::Object(tls_config)) => Ok(Some(TlsClientConfig::new(
+            tls_config,
+            http_version,
+            connection_config,
+        )?)),
+        // HTTP/2 is only negotiated during the TLS handshake.
+        None if http_version == HttpVersion::Http2 => Err(zerror!(
+            "Property '{PROP_S3_HTTP_VERSION}' can only be \"http2\" when '{TLS_PROP}' is \
+            configured."
+        )
+        .into()),
         None => Ok(None),
         _ => Err(zerror!("Property {TLS_PROP} is malformed.").into()),
     }
 }
 
+fn load_connection_config(config: &VolumeConfig) -> ZResult<ConnectionConfig> {
+    let load_duration = |property: &str| match config.rest.get(property) {
+        Some(serde_json::Value::Number(value)) => value
+            .as_u64()
+            .filter(|x| *x > 0)
+            .map(|x| Some(Duration::from_millis(x)))
+            .ok_or_else(|| zerror!("Property '{property}' must be a positive integer.")),
+        None => Ok(None),
+        _ => Err(zerror!("Property '{property}' must be a positive integer.")),
+    };
+    let max_failovers = match config.rest.get(PROP_S3_MAX_FAILOVERS) {
+        Some(serde_json::Value::Number(value)) => Some(value.as_u64().ok_or_else(|| {
+            zerror!("Property '{PROP_S3_MAX_FAILOVERS}' must be a non-negative integer.")
+        })? as usize),
+        None => None,
+        _ => {
+            return Err(zerror!(
+                "Property '{PROP_S3_MAX_FAILOVERS}' must be a non-negative integer."
+            )
+            .into())
+        }
+    };
+    Ok(ConnectionConfig {
+        tcp_keepalive: load_duration(PROP_S3_TCP_KEEPALIVE_MS)?,
+        idle_timeout: load_duration(PROP_S3_IDLE_TIMEOUT_MS)?,
+        max_failovers,
+    })
+}
+
+fn load_http_version(config: &VolumeConfig) -> ZResult<HttpVersion> {
+    match config.rest.get(PROP_S3_HTTP_VERSION) {
+        Some(serde_json::Value::String(s)) if s == "http1" => Ok(HttpVersion::Http1),
+        Some(serde_json::Value::String(s)) if s == "http2" => Ok(HttpVersion::Http2),
+        None => Ok(HttpVersion::Http1),
+        _ => Err(zerror!(
+            r#"Property '{PROP_S3_HTTP_VERSION}' for S3 Backend must be either "http1" (default)
+            or "http2""#
+        )
+        .into()),
+    }
+}
+
+fn load_upload_budget(config: &VolumeConfig) -> ZResult<Option<UploadBudget>> {
+    match config.rest.get(PROP_S3_MAX_IN_FLIGHT_UPLOAD_BYTES) {
+        Some(serde_json::Value::Number(value)) => value
+            .as_u64()
+            .filter(|x| *x > 0)
+            .and_then(|x| u32::try_from(x).ok())
+            .map(|x| Some(UploadBudget::new(x)))
+            .ok_or_else(|| {
+                zerror!(
+                    "Property '{PROP_S3_MAX_IN_FLIGHT_UPLOAD_BYTES}' must be a positive integer \
+                    not greater than {}.",
+                    u32::MAX
+                )
+                .into()
+            }),
+        None => Ok(None),
+        _ => Err(zerror!(
+            "Property '{PROP_S3_MAX_IN_FLIGHT_UPLOAD_BYTES}' for S3 Backend must be an integer."
+        )
+        .into()),
+    }
+}
+
 pub struct S3Volume {
     admin_status: serde_json::Value,
     endpoint: Option<String>,
+    failover_endpoints: Vec<String>,
+    metadata_endpoint: Option<String>,
+    signing_name: Option<String>,
     region: Option<String>,
     tls_config: Option<TlsClientConfig>,
+    connection_config: ConnectionConfig,
+    upload_budget: Option<UploadBudget>,
+}
+
+impl S3Volume {
+    /// Creates the client of a storage of this volume, communicating with the [region].
+    async fn create_client(&self, config: &S3Config, region: Option<String>) -> ZResult<S3Client> {
+        S3Client::new(
+            config,
+            region,
+            self.endpoint
+                .iter()
+                .chain(&self.failover_endpoints)
+                .cloned()
+                .collect(),
+            self.metadata_endpoint.to_owned(),
+            self.signing_name.to_owned(),
+            self.tls_config.to_owned(),
+            &self.connection_config,
+            self.upload_budget.to_owned(),
+        )
+        .await
+        .map_err(|e| zerror!("Couldn't create storage: {e}").into())
+    }
 }
 
 #[async_trait]
@@ -145,18 +510,70 @@ impl Volume for S3Volume {
 
     async fn create_storage(&self, config: StorageConfig) -> ZResult<Box<dyn Storage>> {
         tracing::debug!("Creating storage...");
-        let config: S3Config = S3Config::new(&config).await?;
-
-        let client = Arc::new(
-            S3Client::new(
-                config.credentials.to_owned(),
-                config.bucket.to_owned(),
-                self.region.to_owned(),
-                self.endpoint.to_owned(),
-                self.tls_config.to_owned(),
-            )
-            .await,
-        );
+        let mut config: S3Config = S3Config::new(&config).await?;
+        let effective_config =
+            config.effective_config(self.endpoint.as_ref(), self.region.as_ref());
+        if let Some(admin_status) = config.admin_status.as_object_mut() {
+            admin_status.insert("effective_config".to_string(), effective_config);
+        }
+
+        let mut client = Arc::new(self.create_client(&config, self.region.to_owned()).await?);
+
+        if config.startup_health_check {
+            #[cfg(feature = "dynamic_plugin")]
+            let result = {
+                let c_client = client.clone();
+                let endpoint = self.endpoint.to_owned();
+                let region = self.region.to_owned();
+                STORAGE_RUNTIME
+                    .spawn(async move {
+                        c_client
+                            .health_check(endpoint.as_deref(), region.as_deref())
+                            .await
+                    })
+                    .await
+                    .map_err(|e| zerror!("Couldn't create storage: {e}"))?
+            };
+            #[cfg(not(feature = "dynamic_plugin"))]
+            let result = client
+                .health_check(self.endpoint.as_deref(), self.region.as_deref())
+                .await;
+            result.map_err(|e| zerror!("Couldn't create storage: {e}"))?;
+        }
+
+        // Requests to a bucket located in another region than the configured one fail with
+        // redirections.
+        let bucket_region = resolve_bucket_region(client.clone()).await;
+        if let (Some(bucket_region), Some(region)) = (&bucket_region, &self.region) {
+            if bucket_region != region {
+                match config.region_mismatch {
+                    RegionMismatch::Warn => tracing::warn!(
+                        "Bucket '{}' is located in region '{}' instead of the configured '{}'.",
+                        client,
+                        bucket_region,
+                        region
+                    ),
+                    RegionMismatch::Correct | RegionMismatch::Follow => {
+                        tracing::info!(
+                            "Bucket '{}' is located in region '{}' instead of the configured '{}', \
+                            using its region.",
+                            client,
+                            bucket_region,
+                            region
+                        );
+                        client = Arc::new(
+                            self.create_client(&config, Some(bucket_region.to_owned()))
+                                .await?,
+                        );
+                    }
+                }
+            }
+        }
+        if let (Some(bucket_region), Some(admin_status)) =
+            (bucket_region, config.admin_status.as_object_mut())
+        {
+            admin_status.insert("bucket_region".to_string(), bucket_region.into());
+        }
 
         // This is a workaroud to make sure the plugin works in both
         // dynamic loading, and static linking.
@@ -167,30 +584,152 @@ impl Volume for S3Volume {
         // has access to the static, thus futures can run without needing to
         // crate a runtime.
         #[cfg(feature = "dynamic_plugin")]
-        {
+        let owns_bucket = {
             let c_client = client.clone();
             STORAGE_RUNTIME
                 .spawn(async move { c_client.create_bucket(config.reuse_bucket_is_enabled).await })
                 .await
                 .map_err(|e| zerror!("Couldn't create storage: {e}"))?
+                .map_err(|e| zerror!("Couldn't create storage: {e}"))?
                 .map_or_else(
-                    |_| tracing::debug!("Reusing existing bucket '{}'.", client),
-                    |_| tracing::debug!("Bucket '{}' successfully created.", client),
-                );
-        }
+                    || {
+                        tracing::debug!("Reusing existing bucket '{}'.", client);
+                        false
+                    },
+                    |_| {
+                        tracing::debug!("Bucket '{}' successfully created.", client);
+                        true
+                    },
+                )
+        };
         #[cfg(not(feature = "dynamic_plugin"))]
-        {
-            client
-                .create_bucket(config.reuse_bucket_is_enabled)
+        let owns_bucket = client
+            .create_bucket(config.reuse_bucket_is_enabled)
+            .await
+            .map_err(|e| zerror!("Couldn't create storage: {e}"))?
+            .map_or_else(
+                || {
+                    tracing::debug!("Reusing existing bucket '{}'.", client);
+                    false
+                },
+                |_| {
+                    tracing::debug!("Bucket '{}' successfully created.", client);
+                    true
+                },
+            );
+
+        // The write credentials are checked by the creation of the bucket.
+        #[cfg(feature = "dynamic_plugin")]
+        let read_access = {
+            let c_client = client.clone();
+            STORAGE_RUNTIME
+                .spawn(async move { c_client.check_read_access().await })
                 .await
                 .map_err(|e| zerror!("Couldn't create storage: {e}"))?
-                .map_or_else(
-                    || tracing::debug!("Reusing existing bucket '{}'.", client),
-                    |_| tracing::debug!("Bucket '{}' successfully created.", client),
-                );
+        };
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let read_access = client.check_read_access().await;
+        read_access.map_err(|e| zerror!("Couldn't create storage: {e}"))?;
+
+        if let Some(max_age) = config.abort_stale_uploads_after {
+            let c_client = client.clone();
+            let cleanup = async move {
+                match c_client.abort_stale_multipart_uploads(max_age).await {
+                    Ok(0) => {}
+                    Ok(stale) => tracing::info!(
+                        "Aborted {} stale multipart uploads of '{}'.",
+                        stale,
+                        c_client
+                    ),
+                    Err(e) => tracing::error!(
+                        "Cleanup of the stale multipart uploads of '{}' failed: {}",
+                        c_client,
+                        e
+                    ),
+                }
+            };
+            #[cfg(feature = "dynamic_plugin")]
+            STORAGE_RUNTIME.spawn(cleanup);
+            #[cfg(not(feature = "dynamic_plugin"))]
+            tokio::task::spawn(cleanup);
+        }
+
+        if let Some(source) = config.stamp_missing_timestamps {
+            let c_client = client.clone();
+            let reserved_prefix = config.reserved_prefix.to_owned();
+            let stamping = async move {
+                let synthesizer = TimestampSynthesizer::new(source);
+                match c_client
+                    .stamp_missing_timestamps(&synthesizer, &reserved_prefix)
+                    .await
+                {
+                    Ok((scanned, stamped)) => tracing::info!(
+                        "Stamped {} objects of '{}' lacking a timestamp out of {}.",
+                        stamped,
+                        c_client,
+                        scanned
+                    ),
+                    Err(e) => tracing::error!("Stamping of '{}' failed: {}", c_client, e),
+                }
+            };
+            #[cfg(feature = "dynamic_plugin")]
+            STORAGE_RUNTIME.spawn(stamping);
+            #[cfg(not(feature = "dynamic_plugin"))]
+            tokio::task::spawn(stamping);
+        }
+
+        let log_store = match &config.log_structured {
+            Some(log_structured) => Some(
+                start_log_store(
+                    client.clone(),
+                    log_structured.to_owned(),
+                    config.reserved_object_key(LOG_SEGMENTS_NAME),
+                    config.default_encoding.to_owned(),
+                )
+                .await?,
+            ),
+            None => None,
+        };
+
+        if let (Some(metrics), Some(interval)) = (client.metrics(), config.metrics_interval) {
+            let weak_metrics = Arc::downgrade(metrics);
+            let publisher = async move {
+                loop {
+                    tokio::time::sleep(interval).await;
+                    match weak_metrics.upgrade() {
+                        Some(metrics) => metrics.publish(),
+                        None => break,
+                    }
+                }
+            };
+            #[cfg(feature = "dynamic_plugin")]
+            STORAGE_RUNTIME.spawn(publisher);
+            #[cfg(not(feature = "dynamic_plugin"))]
+            tokio::task::spawn(publisher);
         }
 
-        Ok(Box::new(S3Storage { config, client }))
+        let entries_index = config.entries_index.then(|| Arc::new(EntriesIndex::new()));
+        let last_modified_stamps = (config.missing_timestamps == MissingTimestamps::LastModified)
+            .then(|| TimestampSynthesizer::new(TimestampSource::WallClock));
+        let span = tracing::info_span!(
+            "s3_storage",
+            bucket = %config.bucket,
+            key_expr = %config.key_expr
+        );
+
+        Ok(Box::new(S3Storage {
+            config,
+            client,
+            span,
+            owns_bucket,
+            bucket_missing: AtomicBool::new(false),
+            pending_operations: PendingOperations::default(),
+            log_store,
+            failed_async_puts: Arc::new(AtomicUsize::new(0)),
+            entries_index,
+            last_modified_stamps,
+            maintenance: MaintenanceReports::default(),
+        }))
     }
 
     fn incoming_data_interceptor(&self) -> Option<Arc<dyn Fn(Sample) -> Sample + Send + Sync>> {
@@ -211,182 +750,683 @@ impl Volume for S3Volume {
     }
 }
 
+/// Emits an event at a level only known at runtime.
+macro_rules! log_at {
+    ($level:expr, $($arg:tt)+) => {
+        match $level {
+            tracing::Level::ERROR => tracing::error!($($arg)+),
+            tracing::Level::WARN => tracing::warn!($($arg)+),
+            tracing::Level::INFO => tracing::info!($($arg)+),
+            tracing::Level::DEBUG => tracing::debug!($($arg)+),
+            tracing::Level::TRACE => tracing::trace!($($arg)+),
+        }
+    };
+}
+
 struct S3Storage {
     config: S3Config,
     client: Arc<S3Client>,
+    // Span carrying the identity of the storage, parent of the spans of its operations, so that
+    // the logs of a single storage can be filtered, e.g. with
+    // `RUST_LOG=[s3_storage{bucket=zenoh-bucket}]=debug`.
+    span: tracing::Span,
+    // Whether the bucket was created by the storage, rather than reused.
+    owns_bucket: bool,
+    // Whether the last operation failed because the bucket doesn't exist anymore.
+    bucket_missing: AtomicBool,
+    // Puts and deletes in flight, awaited upon the closure of the storage.
+    pending_operations: PendingOperations,
+    // Store of the values in the log-structured mode, if enabled.
+    log_store: Option<Arc<LogStore>>,
+    // Amount of puts which failed after being acknowledged, in the async durability mode.
+    failed_async_puts: Arc<AtomicUsize>,
+    // Index of the entries returned by `get_all_entries`, if enabled.
+    entries_index: Option<Arc<EntriesIndex>>,
+    // Generator of the timestamps of the objects lacking one, with the `last_modified` policy.
+    last_modified_stamps: Option<TimestampSynthesizer>,
+    // Reports of the maintenance operations triggered through the queries.
+    maintenance: MaintenanceReports,
 }
 
 #[async_trait]
 impl Storage for S3Storage {
     fn get_admin_status(&self) -> serde_json::Value {
-        self.config.admin_status.to_owned()
+        let mut admin_status = self.config.admin_status.to_owned();
+        if self.bucket_missing.load(Ordering::Relaxed) {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                admin_status.insert("bucket_missing".to_string(), true.into());
+            }
+        }
+        if let Some(level) = self.client.retry_budget_level() {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                admin_status.insert("retry_budget_level".to_string(), level.into());
+            }
+        }
+        if let Some(endpoint) = self.client.active_endpoint() {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                let endpoint = config::redact_uri_credentials(endpoint);
+                admin_status.insert("active_endpoint".to_string(), endpoint.into());
+            }
+        }
+        if let Some(rate) = self.client.current_rate() {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                admin_status.insert("current_ops_per_second".to_string(), rate.into());
+            }
+        }
+        if let Some(metrics) = self.client.metrics() {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                admin_status.insert("operation_metrics".to_string(), metrics.snapshot());
+            }
+        }
+        if let Some(log_store) = &self.log_store {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                let corrupted = log_store.corrupted_segments();
+                admin_status.insert("corrupted_log_segments".to_string(), corrupted.into());
+            }
+        }
+        if self.config.durability == Durability::Async {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                let failed = self.failed_async_puts.load(Ordering::Relaxed);
+                admin_status.insert("failed_async_puts".to_string(), failed.into());
+            }
+        }
+        if let Some(reports) = self.maintenance.snapshot() {
+            if let Some(admin_status) = admin_status.as_object_mut() {
+                admin_status.insert("maintenance_operations".to_string(), reports);
+            }
+        }
+        admin_status
     }
 
     /// Function to retrieve the sample associated with a single key.
+    #[tracing::instrument(parent = &self.span, skip_all)]
     async fn get(
         &mut self,
         key: Option<OwnedKeyExpr>,
-        _parameters: &str,
+        parameters: &str,
     ) -> ZResult<Vec<StoredData>> {
+        if let Some(operation) = utils::get_parameter(parameters, PARAM_S3_OPERATION) {
+            if !self.config.allow_maintenance_queries {
+                return Err(zerror!(
+                    "Maintenance operation '{operation}' of '{}' rejected: \
+                    `allow_maintenance_queries` is disabled.",
+                    self.client
+                )
+                .into());
+            }
+            self.start_operation(MaintenanceOperation::from_str(operation)?)?;
+            return Ok(vec![]);
+        }
+        let is_none_key = key.is_none();
         let key = key.map_or_else(|| OwnedKeyExpr::from_str(NONE_KEY), Ok)?;
-        tracing::debug!("GET called on client {}. Key: '{}'", self.client, key);
+        log_at!(
+            self.config.log_level,
+            "GET called on client {}. Key: '{}'",
+            self.client,
+            utils::redact_key(key.as_str(), self.config.log_keys)
+        );
 
-        let s3_key = S3Key::from_key_expr(self.config.path_prefix.as_ref(), key.to_owned())?;
+        let s3_key = S3Key::from_key_expr(
+            self.config.path_prefix.as_ref(),
+            key.to_owned(),
+            &self.config.key_options,
+        )?;
 
-        let get_result = self.get_stored_value(&s3_key.into()).await?;
-        if let Some((timestamp, value)) = get_result {
-            let stored_data = StoredData { value, timestamp };
-            Ok(vec![stored_data])
-        } else {
-            Ok(vec![])
+        let s3_key = self.stored_object_key(is_none_key, s3_key);
+        if let Some(log_store) = &self.log_store {
+            if utils::get_parameter(parameters, PARAM_S3_SELECT).is_some() {
+                return Err(
+                    zerror!("S3 Select is not supported in the log-structured mode.").into(),
+                );
+            }
+            return Ok(log_store
+                .get(&s3_key)
+                .await
+                .map_err(|e| zerror!("Get operation failed for key '{s3_key}': {e}"))?
+                .map(|(timestamp, value)| vec![StoredData { value, timestamp }])
+                .unwrap_or_default());
+        }
+        let select = match utils::get_parameter(parameters, PARAM_S3_SELECT) {
+            Some(expression) => Some((
+                expression,
+                utils::get_parameter(parameters, PARAM_S3_SELECT_FORMAT)
+                    .map_or(Ok(SelectFormat::Csv), SelectFormat::from_str)?,
+            )),
+            None => None,
+        };
+        let s3_keys = self.check_bucket(self.object_keys(s3_key).await).await?;
+        // The versions of a key suffixed with their timestamps sort in the order of the latter.
+        let s3_keys = match self.config.timestamp_key_suffix {
+            true => s3_keys.into_iter().max().into_iter().collect(),
+            false => s3_keys,
+        };
+        // With isolated origins, the most recent of the values stored by the origins is returned.
+        let mut latest: Option<(Timestamp, Value)> = None;
+        for s3_key in s3_keys {
+            let get_result = match select {
+                Some((expression, format)) => {
+                    self.get_selected_value(&s3_key, expression.to_string(), format)
+                        .await
+                }
+                None => self.get_stored_value(&s3_key).await,
+            };
+            if let Some((timestamp, value)) = self.check_bucket(get_result).await? {
+                if latest
+                    .as_ref()
+                    .map_or(true, |(latest, _)| *latest < timestamp)
+                {
+                    latest = Some((timestamp, value));
+                }
+            }
         }
+        Ok(latest
+            .map(|(timestamp, value)| vec![StoredData { value, timestamp }])
+            .unwrap_or_default())
     }
 
     /// Function called for each incoming data ([`Sample`]) to be stored in this storage.
+    #[tracing::instrument(parent = &self.span, skip_all)]
     async fn put(
         &mut self,
         key: Option<OwnedKeyExpr>,
         value: Value,
         timestamp: Timestamp,
     ) -> ZResult<StorageInsertionResult> {
+        if self.config.empty_values == EmptyValues::Delete && value.payload.len() == 0 {
+            tracing::debug!("Received PUT of an empty value, treated as a DELETE");
+            return self.delete(key, timestamp).await;
+        }
+        let is_none_key = key.is_none();
         let key = key.map_or_else(|| OwnedKeyExpr::from_str(NONE_KEY), Ok)?;
-        tracing::debug!("Put called on client {}. Key: '{}'", self.client, key);
+        log_at!(
+            self.config.log_level,
+            "Put called on client {}. Key: '{}'",
+            self.client,
+            utils::redact_key(key.as_str(), self.config.log_keys)
+        );
 
-        let s3_key = S3Key::from_key_expr(self.config.path_prefix.as_ref(), key)
-            .map_or_else(|err| Err(zerror!("Error getting s3 key: {}", err)), Ok)?;
-        if !self.config.is_read_only {
+        let s3_key = S3Key::from_key_expr(
+            self.config.path_prefix.as_ref(),
+            key,
+            &self.config.key_options,
+        )
+        .map_or_else(|err| Err(zerror!("Error getting s3 key: {}", err)), Ok)?;
+        if !self.is_read_only(&s3_key.key_expr) {
+            let full_key = s3_key.full_object_key();
+            let s3_key = self.stored_object_key(is_none_key, s3_key);
+            let s3_key = self.origin_key(s3_key, &timestamp);
+            if let Some(log_store) = &self.log_store {
+                return log_store
+                    .append(s3_key, timestamp, Some(value))
+                    .await
+                    .map_err(|e| zerror!("Put operation failed: {e}").into());
+            }
+            if self
+                .check_bucket(self.is_outdated(&s3_key, &timestamp).await)
+                .await?
+            {
+                tracing::debug!(
+                    "Received outdated PUT on {} - ignored",
+                    utils::redact_key(&s3_key, self.config.log_keys)
+                );
+                return Ok(StorageInsertionResult::Outdated);
+            }
             let mut metadata: HashMap<String, String> = HashMap::new();
-            metadata.insert(TIMESTAMP_METADATA_KEY.to_string(), timestamp.to_string());
+            metadata.insert(
+                self.config.timestamp_metadata_key.to_owned(),
+                timestamp.to_string(),
+            );
+            metadata.insert(
+                ENCODING_METADATA_KEY.to_string(),
+                value.encoding.to_string(),
+            );
+            if utils::is_hashed_object_key(&s3_key) {
+                metadata.insert(
+                    FULL_KEY_METADATA_KEY.to_string(),
+                    utils::percent_encode_key(&full_key),
+                );
+            }
+            if self.config.preserve_original_length {
+                metadata.insert(
+                    ORIGINAL_LENGTH_METADATA_KEY.to_string(),
+                    value.payload.len().to_string(),
+                );
+            }
+            if self.config.write_format_version {
+                metadata.insert(
+                    FORMAT_VERSION_METADATA_KEY.to_string(),
+                    FORMAT_VERSION.to_string(),
+                );
+            }
+            let s3_key = match self.config.timestamp_key_suffix {
+                true => utils::timestamp_suffixed_key(&s3_key, &timestamp),
+                false => s3_key,
+            };
+            if self.config.durability == Durability::Async {
+                self.put_in_background(s3_key, value, metadata);
+                return Ok(StorageInsertionResult::Inserted);
+            }
             #[cfg(feature = "dynamic_plugin")]
-            {
+            let result = {
                 let client2 = self.client.clone();
-                let key2 = s3_key.into();
+                // The operation completes even if this future is dropped, hence is tracked
+                // within the spawned task.
+                let operation = self.pending_operations.start();
                 STORAGE_RUNTIME
-                    .spawn(async move { client2.put_object(key2, value, Some(metadata)).await })
+                    .spawn(async move {
+                        let result = client2.put_object(s3_key, value, Some(metadata)).await;
+                        drop(operation);
+                        result
+                    })
                     .await
                     .map_err(|e| zerror!("Put operation failed: {e}"))?
-                    .map_err(|e| zerror!("Put operation failed: {e}"))?;
-            }
+            };
             #[cfg(not(feature = "dynamic_plugin"))]
-            {
-                self.client
-                    .put_object(s3_key.into(), value, Some(metadata))
-                    .await
-                    .map_err(|e| zerror!("Put operation failed: {e}"))?;
-            }
+            let result = {
+                let _operation = self.pending_operations.start();
+                self.client.put_object(s3_key, value, Some(metadata)).await
+            };
+            self.check_bucket(result)
+                .await
+                .map_err(|e| zerror!("Put operation failed: {e}"))?;
 
             Ok(StorageInsertionResult::Inserted)
         } else {
-            tracing::warn!("Received PUT for read-only DB on {} - ignored", s3_key);
+            tracing::warn!(
+                "Received PUT for read-only DB on {} - ignored",
+                utils::redact_key(s3_key.key_expr.as_str(), self.config.log_keys)
+            );
             Err("Received update for read-only DB".into())
         }
     }
 
     /// Function called for each incoming delete request to this storage.
+    #[tracing::instrument(parent = &self.span, skip_all)]
     async fn delete(
         &mut self,
         key: Option<OwnedKeyExpr>,
-        _timestamp: Timestamp,
+        timestamp: Timestamp,
     ) -> ZResult<StorageInsertionResult> {
+        let is_none_key = key.is_none();
         let key = key.map_or_else(|| OwnedKeyExpr::from_str(NONE_KEY), Ok)?;
-        tracing::debug!("Delete called on client {}. Key: '{}'", self.client, key);
-        let s3_key = S3Key::from_key_expr(self.config.path_prefix.as_ref(), key)?;
+        log_at!(
+            self.config.log_level,
+            "Delete called on client {}. Key: '{}'",
+            self.client,
+            utils::redact_key(key.as_str(), self.config.log_keys)
+        );
+        let s3_key = S3Key::from_key_expr(
+            self.config.path_prefix.as_ref(),
+            key,
+            &self.config.key_options,
+        )?;
 
-        if !self.config.is_read_only {
-            #[cfg(feature = "dynamic_plugin")]
+        if !self.is_read_only(&s3_key.key_expr) {
+            if s3_key.key_expr.is_wild() {
+                if self.log_store.is_some() {
+                    return Err(zerror!(
+                        "Deletes with wildcards are not supported in the log-structured mode."
+                    )
+                    .into());
+                }
+                return self.delete_matching(&s3_key).await;
+            }
+            let s3_key = self.stored_object_key(is_none_key, s3_key);
+            let origin_key = self.origin_key(s3_key.to_owned(), &timestamp);
+            if let Some(log_store) = &self.log_store {
+                return log_store
+                    .append(origin_key, timestamp, None)
+                    .await
+                    .map_err(|e| zerror!("Delete operation failed: {e}").into());
+            }
+            if self
+                .check_bucket(self.is_outdated(&origin_key, &timestamp).await)
+                .await?
             {
+                tracing::debug!(
+                    "Received outdated DELETE on {} - ignored",
+                    utils::redact_key(&origin_key, self.config.log_keys)
+                );
+                return Ok(StorageInsertionResult::Outdated);
+            }
+            let s3_keys = self.check_bucket(self.object_keys(s3_key).await).await?;
+            #[cfg(feature = "dynamic_plugin")]
+            let result = {
                 let client2 = self.client.clone();
-                let key2 = s3_key.into();
+                let operation = self.pending_operations.start();
                 STORAGE_RUNTIME
-                    .spawn(async move { client2.delete_object(key2).await })
+                    .spawn(async move {
+                        let result =
+                            join_all(s3_keys.into_iter().map(|key| client2.delete_object(key)))
+                                .await
+                                .into_iter()
+                                .collect::<ZResult<Vec<_>>>();
+                        drop(operation);
+                        result
+                    })
                     .await
                     .map_err(|e| zerror!("Delete operation failed: {e}"))?
-                    .map_err(|e| zerror!("Delete operation failed: {e}"))?;
-            }
+            };
             #[cfg(not(feature = "dynamic_plugin"))]
-            {
-                self.client
-                    .delete_object(s3_key.into())
-                    .await
-                    .map_err(|e| zerror!("Delete operation failed: {e}"))?;
-            }
+            let result = {
+                let _operation = self.pending_operations.start();
+                join_all(
+                    s3_keys
+                        .into_iter()
+                        .map(|key| self.client.delete_object(key)),
+                )
+                .await
+                .into_iter()
+                .collect::<ZResult<Vec<_>>>()
+            };
+            self.check_bucket(result)
+                .await
+                .map_err(|e| zerror!("Delete operation failed: {e}"))?;
             Ok(StorageInsertionResult::Deleted)
         } else {
-            tracing::warn!("Received DELETE for read-only DB on {} - ignored", s3_key);
+            tracing::warn!(
+                "Received DELETE for read-only DB on {} - ignored",
+                utils::redact_key(s3_key.key_expr.as_str(), self.config.log_keys)
+            );
             Err("Received update for read-only DB".into())
         }
     }
 
+    #[tracing::instrument(parent = &self.span, skip_all)]
     async fn get_all_entries(&self) -> ZResult<Vec<(Option<OwnedKeyExpr>, Timestamp)>> {
-        #[cfg(feature = "dynamic_plugin")]
+        if let Some(log_store) = &self.log_store {
+            let none_key = self.config.reserved_object_key(NONE_KEY);
+            let entries = log_store
+                .entries()
+                .into_iter()
+                .filter_map(|(object_key, timestamp)| {
+                    if object_key == none_key {
+                        return Some((None, timestamp));
+                    }
+                    let key_expr = entry_key_expr(
+                        self.config.path_prefix.as_ref(),
+                        &self.config.key_options,
+                        &self.config.key_expr,
+                        self.config.empty_keys,
+                        self.config.key_options.strip_hash_segment(&object_key)?,
+                        &utils::redact_key(&object_key, self.config.log_keys),
+                    )?;
+                    Some((key_expr, timestamp))
+                })
+                .collect();
+            return Ok(self.sorted_entries(entries));
+        }
+
+        // The listing is paginated, so that `max_entries` is enforced as the pages are listed
+        // rather than once the whole bucket is, and the entries of each page are collected, and
+        // possibly spilled, before the next page is listed.
+        // Several objects may map to the same key once normalized (e.g. `a/b/` and `a//b`), in
+        // which case only the most recent entry is kept.
+        let mut entries = EntriesSpill::new(self.config.entries_spill.to_owned());
+        // Keys of the listed objects, out of which the entries of the index are retained.
+        let mut listed_keys = self.entries_index.as_ref().map(|_| HashSet::new());
+        let mut listed = 0;
+        let mut continuation_token = None;
+        let complete = loop {
+            let (mut objects, next_continuation_token) = self
+                .list_entries_page(continuation_token)
+                .await
+                .map_err(|e| zerror!("Get operation failed: {e}"))?;
+            listed += objects.len();
+            let truncated = match self.config.max_entries {
+                Some(max_entries) if listed > max_entries => match self.config.max_entries_policy {
+                    MaxEntriesPolicy::Error => {
+                        return Err(zerror!(
+                            "Get operation failed: more than {max_entries} objects listed in \
+                            bucket '{}', exceeding the {max_entries} allowed by `max_entries`.",
+                            self.client
+                        )
+                        .into())
+                    }
+                    MaxEntriesPolicy::Truncate => {
+                        tracing::warn!(
+                            "More than {max_entries} objects listed in bucket '{}', only \
+                            considering the first {max_entries} allowed by `max_entries`.",
+                            self.client
+                        );
+                        objects.truncate(objects.len() - (listed - max_entries));
+                        true
+                    }
+                },
+                _ => false,
+            };
+            if let Some(listed_keys) = &mut listed_keys {
+                listed_keys.extend(
+                    objects
+                        .iter()
+                        .filter_map(|object| object.key().map(|key| key.to_string())),
+                );
+            }
+            self.collect_entries(objects, &mut entries).await?;
+            match next_continuation_token {
+                Some(token) if !truncated => continuation_token = Some(token),
+                _ => break !truncated,
+            }
+        };
+        // The objects beyond a truncated listing may still exist, their entries are kept.
+        if let (Some(entries_index), Some(listed_keys), true) =
+            (&self.entries_index, &listed_keys, complete)
+        {
+            entries_index.retain_listed(&listed_keys.iter().map(String::as_str).collect());
+        }
+        let entries = entries
+            .into_entries()
+            .map_err(|e| zerror!("Get operation failed: {e}"))?;
+        // Sorted once all the entries are collected, so that the head requests remain concurrent.
+        Ok(self.sorted_entries(entries))
+    }
+}
+
+impl S3Storage {
+    /// Lists the page of the objects of the bucket following the [continuation_token], or all of
+    /// them at once if the listing is parallel.
+    async fn list_entries_page(
+        &self,
+        continuation_token: Option<String>,
+    ) -> ZResult<(Vec<Object>, Option<String>)> {
         let client = self.client.clone();
+        let listing_concurrency = self.config.listing_concurrency;
+        let list = async move {
+            match listing_concurrency {
+                Some(concurrency) => client
+                    .list_objects_in_parallel("/", concurrency)
+                    .await
+                    .map(|objects| (objects, None)),
+                None => client.list_objects_page(None, continuation_token).await,
+            }
+        };
 
         #[cfg(feature = "dynamic_plugin")]
-        let objects = STORAGE_RUNTIME
-            .spawn(async move { client.list_objects_in_bucket().await })
+        let result = STORAGE_RUNTIME
+            .spawn(list)
             .await
-            .map_err(|e| zerror!("Get operation failed: {e}"))?
-            .map_err(|e| zerror!("Get operation failed: {e}"))?;
+            .map_err(|e| zerror!("{e}"))?;
 
         #[cfg(not(feature = "dynamic_plugin"))]
-        let objects = self
-            .client
-            .list_objects_in_bucket()
-            .await
-            .map_err(|e| zerror!("Get operation failed: {e}"))?;
+        let result = list.await;
+
+        self.check_bucket(result).await
+    }
+
+    /// Collects into the [entries] the key and timestamp of each of the listed [objects], taken
+    /// from the index or else retrieved with a HEAD request of the object.
+    async fn collect_entries(
+        &self,
+        objects: Vec<Object>,
+        entries: &mut EntriesSpill,
+    ) -> ZResult<()> {
+        // Entries taken from the index, whose objects don't need a HEAD request.
+        let mut indexed_entries = vec![];
 
         let futures = objects.into_iter().filter_map(|object| {
             let object_key = match object.key() {
-                Some(key) if key == NONE_KEY => return None,
+                // The internal objects, as well as the None key object of the previous versions.
+                Some(key) if key == NONE_KEY || key.starts_with(&self.config.reserved_prefix) => {
+                    return None
+                }
+                Some(key)
+                    if self.config.timestamp_key_suffix
+                        && utils::strip_timestamp_suffix(key).is_none() =>
+                {
+                    tracing::debug!(
+                        "Ignoring object without timestamp suffix '{}'.",
+                        utils::redact_key(key, self.config.log_keys)
+                    );
+                    return None;
+                }
+                Some(key)
+                    if self.config.trailing_slash_keys == TrailingSlashKeys::Ignore
+                        && utils::has_empty_segments(self.unsuffixed_key(key)) =>
+                {
+                    tracing::debug!(
+                        "Ignoring object with trailing or doubled slashes '{}'.",
+                        utils::redact_key(key, self.config.log_keys)
+                    );
+                    return None;
+                }
+                // The origin prefix of the isolated origins replaces the leading slash.
+                Some(key)
+                    if !self.config.isolate_origins
+                        && !self
+                            .config
+                            .key_options
+                            .matches_leading_slash(self.unsuffixed_key(key)) =>
+                {
+                    tracing::debug!(
+                        "Ignoring object not matching the leading slash policy '{}'.",
+                        utils::redact_key(key, self.config.log_keys)
+                    );
+                    return None;
+                }
                 Some(key) => key.to_string(),
                 None => {
                     tracing::error!("Could not get key for object {:?}", object);
                     return None;
                 }
             };
-
-            match S3Key::from_key(self.config.path_prefix.as_ref(), object_key.to_owned()) {
-                Ok(s3_key) => {
-                    if !s3_key.key_expr.intersects(&self.config.key_expr) {
-                        return None;
-                    }
-                }
-                Err(err) => {
-                    tracing::error!("Error filtering storage entries: ${err}.");
+            let version = object
+                .e_tag()
+                .zip(object.last_modified())
+                .map(|(etag, last_modified)| (etag.to_string(), last_modified.as_nanos()));
+            let entries_index = self.entries_index.to_owned();
+            if let (Some(entries_index), Some((etag, last_modified))) = (&entries_index, &version) {
+                if let Some(entry) = entries_index.get(&object_key, etag, *last_modified) {
+                    indexed_entries.push(Ok(Some((Some(entry.0), entry.1))));
                     return None;
                 }
+            }
+            // The complete key of a hashed object key is only known from its metadata.
+            let key_expr = if utils::is_hashed_object_key(self.unsuffixed_key(&object_key)) {
+                None
+            } else {
+                let stored_key = if self.config.isolate_origins {
+                    match utils::strip_origin(&object_key) {
+                        NONE_KEY => return None,
+                        stored_key if stored_key.starts_with(&self.config.reserved_prefix) => {
+                            return None
+                        }
+                        stored_key => stored_key.to_string(),
+                    }
+                } else {
+                    self.unsuffixed_key(&object_key).to_owned()
+                };
+                let stored_key = match self.config.key_options.strip_hash_segment(&stored_key) {
+                    Some(stored_key) => stored_key,
+                    None => {
+                        tracing::debug!(
+                            "Ignoring object not matching its hash segment '{}'.",
+                            utils::redact_key(&object_key, self.config.log_keys)
+                        );
+                        return None;
+                    }
+                };
+                Some(entry_key_expr(
+                    self.config.path_prefix.as_ref(),
+                    &self.config.key_options,
+                    &self.config.key_expr,
+                    self.config.empty_keys,
+                    stored_key,
+                    &utils::redact_key(&object_key, self.config.log_keys),
+                )?)
             };
 
             let client = self.client.clone();
+            let timestamp_metadata_key = self.config.timestamp_metadata_key.to_owned();
+            let path_prefix = self.config.path_prefix.to_owned();
+            let key_options = self.config.key_options.to_owned();
+            let storage_key_expr = self.config.key_expr.to_owned();
+            let empty_keys = self.config.empty_keys;
+            let log_keys = self.config.log_keys;
+            let listing_consistency = self.config.listing_consistency;
 
             let fut = async move {
-                let result = client.get_head_object(&object_key).await;
+                let result = client.get_object_metadata(&object_key).await;
                 match result {
-                    Ok(value) => {
-                        let metadata = value.metadata.ok_or_else(|| {
-                            zerror!("Unable to retrieve metadata for key '{}'.", object_key)
-                        })?;
-                        let timestamp = metadata.get(TIMESTAMP_METADATA_KEY).ok_or_else(|| {
+                    // The object was deleted since it was listed.
+                    Ok(None) if listing_consistency == ListingConsistency::Snapshot => {
+                        tracing::debug!(
+                            "Skipping object deleted since the listing '{}'.",
+                            utils::redact_key(&object_key, log_keys)
+                        );
+                        Ok(None)
+                    }
+                    Ok(None) => Err(zerror!(
+                        "Unable to retrieve metadata for key '{}', deleted since the listing.",
+                        object_key
+                    )),
+                    Ok(Some(metadata)) => {
+                        let key_expr = match key_expr {
+                            Some(key_expr) => key_expr,
+                            None => {
+                                let full_key = metadata
+                                    .get(FULL_KEY_METADATA_KEY)
+                                    .ok_or_else(|| {
+                                        zerror!("Unable to retrieve full key of '{object_key}'.")
+                                    })
+                                    .and_then(|full_key| {
+                                        utils::percent_decode_key(full_key)
+                                            .map_err(|e| zerror!("{e}"))
+                                    })?;
+                                match entry_key_expr(
+                                    path_prefix.as_ref(),
+                                    &key_options,
+                                    &storage_key_expr,
+                                    empty_keys,
+                                    full_key,
+                                    &utils::redact_key(&object_key, log_keys),
+                                ) {
+                                    Some(key_expr) => key_expr,
+                                    None => return Ok(None),
+                                }
+                            }
+                        };
+                        let timestamp = metadata.get(&timestamp_metadata_key).ok_or_else(|| {
                             zerror!("Unable to retrieve timestamp for key '{}'.", object_key)
                         })?;
-                        let key_expr = OwnedKeyExpr::from_str(object_key.trim_start_matches('/'))
-                            .map_err(|err| {
+                        let timestamp = Timestamp::from_str(timestamp.as_str()).map_err(|e| {
                             zerror!(
-                                "Unable to generate key expression for key '{}': {}",
-                                &object_key,
-                                &err
+                                "Unable to obtain timestamp for key: {}. {:?}",
+                                object_key,
+                                e
                             )
                         })?;
-                        Ok((
-                            Some(key_expr),
-                            Timestamp::from_str(timestamp.as_str()).map_err(|e| {
-                                zerror!(
-                                    "Unable to obtain timestamp for key: {}. {:?}",
-                                    object_key,
-                                    e
-                                )
-                            })?,
-                        ))
+                        if let (Some(entries_index), Some((etag, last_modified)), Some(key_expr)) =
+                            (entries_index, version, &key_expr)
+                        {
+                            entries_index.insert(
+                                object_key,
+                                etag,
+                                last_modified,
+                                key_expr.to_owned(),
+                                timestamp,
+                            );
+                        }
+                        Ok(Some((key_expr, timestamp)))
                     }
                     Err(err) => Err(zerror!(
                         "Unable to get '{}' object from storage: {}",
@@ -401,23 +1441,534 @@ impl Storage for S3Storage {
             #[cfg(not(feature = "dynamic_plugin"))]
             return Some(tokio::task::spawn(fut));
         });
-        let futures_results = join_all(futures.collect::<FuturesUnordered<_>>()).await;
-        let entries: Vec<(Option<OwnedKeyExpr>, Timestamp)> = futures_results
-            .into_iter()
-            .flatten()
-            .filter_map(|x| match x {
-                Ok(value) => Some(value),
-                Err(err) => {
-                    tracing::error!("{}", err);
-                    None
-                }
+        // The results are collected as they complete, so that they can be spilled if needed. The
+        // failure of a task, e.g. upon a panic, is logged as the failure of its entry.
+        let mut results = futures
+            .collect::<FuturesUnordered<_>>()
+            .map(|result| {
+                result.unwrap_or_else(|e| Err(zerror!("Unable to collect an entry: {e}")))
             })
-            .collect();
-        Ok(entries)
+            .chain(stream::iter(indexed_entries));
+        while let Some(result) = results.next().await {
+            match result {
+                Ok(Some((key_expr, timestamp))) => entries
+                    .insert(key_expr, timestamp)
+                    .map_err(|e| zerror!("Get operation failed: {e}"))?,
+                Ok(None) => (),
+                Err(err) => tracing::error!("{}", err),
+            }
+        }
+        Ok(())
+    }
+
+    /// Uploads the object in background, for the puts acknowledged before S3 confirms the write.
+    /// The upload is tracked as a pending operation, and its failure is logged and counted.
+    fn put_in_background(&self, s3_key: String, value: Value, metadata: HashMap<String, String>) {
+        let client2 = self.client.clone();
+        let failed_async_puts = self.failed_async_puts.clone();
+        let log_keys = self.config.log_keys;
+        let operation = self.pending_operations.start();
+        let upload = async move {
+            if let Err(e) = client2
+                .put_object(s3_key.to_owned(), value, Some(metadata))
+                .await
+            {
+                failed_async_puts.fetch_add(1, Ordering::Relaxed);
+                tracing::error!(
+                    "Asynchronous put of '{}' on {client2} failed: {e}",
+                    utils::redact_key(&s3_key, log_keys)
+                );
+            }
+            drop(operation);
+        };
+        #[cfg(feature = "dynamic_plugin")]
+        STORAGE_RUNTIME.spawn(upload);
+        #[cfg(not(feature = "dynamic_plugin"))]
+        tokio::task::spawn(upload);
+    }
+
+    /// Checks whether the updates of the [key_expr] are rejected, as the storage is read only or
+    /// the key expression intersects one of its read-only subtrees.
+    fn is_read_only(&self, key_expr: &OwnedKeyExpr) -> bool {
+        self.config.is_read_only
+            || self
+                .config
+                .read_only_key_exprs
+                .iter()
+                .any(|read_only| read_only.intersects(key_expr))
+    }
+
+    /// Sorts the [entries] according to the configured [EntriesOrder].
+    fn sorted_entries(
+        &self,
+        mut entries: Vec<(Option<OwnedKeyExpr>, Timestamp)>,
+    ) -> Vec<(Option<OwnedKeyExpr>, Timestamp)> {
+        let key = |key_expr: &Option<OwnedKeyExpr>| key_expr.as_ref().map(|x| x.as_str());
+        match self.config.entries_order {
+            EntriesOrder::Unordered => (),
+            EntriesOrder::Key => entries.sort_by(|(a, _), (b, _)| key(a).cmp(&key(b))),
+            EntriesOrder::Timestamp => entries.sort_by(|(a, a_timestamp), (b, b_timestamp)| {
+                a_timestamp
+                    .cmp(b_timestamp)
+                    .then_with(|| key(a).cmp(&key(b)))
+            }),
+        }
+        entries
+    }
+
+    /// Starts the maintenance [operation] in the background, unless it is already running.
+    fn start_operation(&self, operation: MaintenanceOperation) -> ZResult<()> {
+        match operation {
+            MaintenanceOperation::Compact => {
+                if self.config.is_read_only {
+                    return Err(
+                        zerror!("Compaction of the read-only storage '{}'.", self.client).into(),
+                    );
+                }
+                let keep_versions = self.config.compaction_keep_versions.ok_or_else(|| {
+                    zerror!("Compaction requires 'compaction_keep_versions' to be configured.")
+                })?;
+                let client = self.client.clone();
+                self.spawn_operation(operation, async move {
+                    client
+                        .compact_object_versions(keep_versions)
+                        .await
+                        .map(|deleted| serde_json::json!({ "deleted_versions": deleted }))
+                });
+            }
+            MaintenanceOperation::Verify => {
+                let client = self.client.clone();
+                let reserved_prefix = self.config.reserved_prefix.to_owned();
+                let log_keys = self.config.log_keys;
+                self.spawn_operation(operation, async move {
+                    client
+                        .verify_object_timestamps(&reserved_prefix, |key, reason| {
+                            tracing::warn!(
+                                "Invalid timestamp for object '{}' of '{client}': {reason}.",
+                                utils::redact_key(key, log_keys)
+                            )
+                        })
+                        .await
+                        .map(|(scanned, invalid)| {
+                            serde_json::json!({
+                                "scanned_objects": scanned,
+                                "invalid_timestamps": invalid,
+                            })
+                        })
+                });
+            }
+            MaintenanceOperation::Reencrypt => {
+                if self.config.is_read_only {
+                    return Err(zerror!(
+                        "Re-encryption of the read-only storage '{}'.",
+                        self.client
+                    )
+                    .into());
+                }
+                if self.config.sse_kms.is_none() {
+                    return Err(zerror!(
+                        "Re-encryption requires 'sse_kms_key_id' or 'sse_kms_encryption_context' \
+                        to be configured."
+                    )
+                    .into());
+                }
+                let client = self.client.clone();
+                self.spawn_operation(operation, async move {
+                    client
+                        .reencrypt_objects()
+                        .await
+                        .map(|(scanned, reencrypted)| {
+                            serde_json::json!({
+                                "scanned_objects": scanned,
+                                "reencrypted_objects": reencrypted,
+                            })
+                        })
+                });
+            }
+            MaintenanceOperation::Export => {
+                let export = self
+                    .config
+                    .export
+                    .to_owned()
+                    .ok_or_else(|| zerror!("Export requires 'export' to be configured."))?;
+                let client = self.client.clone();
+                let path_prefix = self.config.path_prefix.to_owned();
+                let key_options = self.config.key_options.to_owned();
+                let reserved_prefix = self.config.reserved_prefix.to_owned();
+                let timestamp_key_suffix = self.config.timestamp_key_suffix;
+                let isolate_origins = self.config.isolate_origins;
+                self.spawn_operation(operation, async move {
+                    client
+                        .export_objects(&export.dir, export.concurrency, |object_key| {
+                            export.key_expr.as_ref().map_or(true, |key_expr| {
+                                is_exported(
+                                    path_prefix.as_ref(),
+                                    &key_options,
+                                    &reserved_prefix,
+                                    timestamp_key_suffix,
+                                    isolate_origins,
+                                    key_expr,
+                                    object_key,
+                                )
+                            })
+                        })
+                        .await
+                        .map(|(exported, skipped)| {
+                            serde_json::json!({
+                                "exported_objects": exported,
+                                "skipped_objects": skipped,
+                            })
+                        })
+                });
+            }
+        }
+        Ok(())
+    }
+
+    /// Runs the [task] of the maintenance [operation] in the background, reporting its result in
+    /// the admin status of the storage.
+    fn spawn_operation<F>(&self, operation: MaintenanceOperation, task: F)
+    where
+        F: Future<Output = ZResult<serde_json::Value>> + Send + 'static,
+    {
+        if !self.maintenance.start(operation) {
+            tracing::debug!(
+                "Operation '{}' of '{}' is already running.",
+                operation.name(),
+                self.client
+            );
+            return;
+        }
+        let reports = self.maintenance.clone();
+        let client = self.client.clone();
+        let task = async move {
+            let result = task.await;
+            match &result {
+                Ok(report) => tracing::info!(
+                    "Operation '{}' of '{}' completed: {}",
+                    operation.name(),
+                    client,
+                    report
+                ),
+                Err(e) => tracing::error!(
+                    "Operation '{}' of '{}' failed: {}",
+                    operation.name(),
+                    client,
+                    e
+                ),
+            }
+            reports.complete(operation, &result);
+        };
+        #[cfg(feature = "dynamic_plugin")]
+        STORAGE_RUNTIME.spawn(task);
+        #[cfg(not(feature = "dynamic_plugin"))]
+        tokio::task::spawn(task);
+    }
+
+    /// Returns the key of the object storing the value of [s3_key]. The value of the key matching
+    /// exactly the path prefix is stored in an internal object, under the reserved prefix.
+    fn stored_object_key(&self, is_none_key: bool, s3_key: S3Key) -> String {
+        if is_none_key {
+            self.config.reserved_object_key(NONE_KEY)
+        } else {
+            s3_key.into()
+        }
+    }
+
+    /// Returns the object key under which a value with the given [timestamp] is stored: the
+    /// [s3_key] itself or, when the origins are isolated, the key within the prefix of the origin
+    /// of the value.
+    fn origin_key(&self, s3_key: String, timestamp: &Timestamp) -> String {
+        if self.config.isolate_origins {
+            utils::origin_key(
+                &timestamp.get_id().to_string(),
+                &s3_key,
+                self.config.key_options.max_length,
+            )
+        } else {
+            s3_key
+        }
+    }
+
+    /// Returns the [object_key] stripped from its timestamp suffix, when the timestamps are
+    /// suffixed to the keys.
+    fn unsuffixed_key<'a>(&self, object_key: &'a str) -> &'a str {
+        match self.config.timestamp_key_suffix {
+            true => utils::strip_timestamp_suffix(object_key).unwrap_or(object_key),
+            false => object_key,
+        }
+    }
+
+    /// Deletes all the objects whose key is included in the wildcard key expression of the
+    /// [s3_key] (e.g. `a/**` or `a/*`), listing the objects under the prefix preceding the first
+    /// wildcard and filtering them with the key expression.
+    ///
+    /// The deleted keys are not checked for outdated timestamps.
+    async fn delete_matching(&self, s3_key: &S3Key<'_>) -> ZResult<StorageInsertionResult> {
+        // With isolated origins, the objects are stored under the prefixes of their origins.
+        let prefix = match self.config.isolate_origins {
+            true => String::new(),
+            false => utils::wildcard_object_prefix(s3_key),
+        };
+        let key_expr = s3_key.key_expr.to_owned();
+        let path_prefix = self.config.path_prefix.to_owned();
+        let key_options = self.config.key_options.to_owned();
+        let isolate_origins = self.config.isolate_origins;
+        let timestamp_key_suffix = self.config.timestamp_key_suffix;
+        let filter = move |object_key: &str| {
+            let stored_key = match isolate_origins {
+                true => utils::strip_origin(object_key),
+                false => object_key,
+            };
+            let stored_key = match timestamp_key_suffix {
+                true => match utils::strip_timestamp_suffix(stored_key) {
+                    Some(stored_key) => stored_key,
+                    None => return false,
+                },
+                false => stored_key,
+            };
+            key_options
+                .strip_hash_segment(stored_key)
+                .and_then(|stored_key| {
+                    S3Key::from_key(path_prefix.as_ref(), stored_key, &key_options).ok()
+                })
+                .map_or(false, |s3_key| key_expr.includes(&s3_key.key_expr))
+        };
+
+        #[cfg(feature = "dynamic_plugin")]
+        let result = {
+            let client2 = self.client.clone();
+            let operation = self.pending_operations.start();
+            STORAGE_RUNTIME
+                .spawn(async move {
+                    let result = client2.delete_prefix(&prefix, filter).await;
+                    drop(operation);
+                    result
+                })
+                .await
+                .map_err(|e| zerror!("Delete operation failed: {e}"))?
+        };
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let result = {
+            let _operation = self.pending_operations.start();
+            self.client.delete_prefix(&prefix, filter).await
+        };
+        let deleted = self
+            .check_bucket(result)
+            .await
+            .map_err(|e| zerror!("Delete operation failed: {e}"))?;
+        log_at!(
+            self.config.log_level,
+            "Deleted {} objects matching '{}' from client {}.",
+            deleted,
+            utils::redact_key(s3_key.key_expr.as_str(), self.config.log_keys),
+            self.client
+        );
+        Ok(StorageInsertionResult::Deleted)
+    }
+
+    /// Returns the object keys under which a value of [s3_key] may be stored: the key itself or,
+    /// when the origins are isolated, the key within the prefix of each origin or, when the
+    /// timestamps are suffixed to the keys, the versions of the key in the order of their
+    /// timestamps.
+    async fn object_keys(&self, s3_key: String) -> ZResult<Vec<String>> {
+        if self.config.timestamp_key_suffix {
+            return self.timestamped_keys(s3_key).await;
+        }
+        if !self.config.isolate_origins {
+            return Ok(vec![s3_key]);
+        }
+
+        #[cfg(feature = "dynamic_plugin")]
+        let client2 = self.client.clone();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let origins = STORAGE_RUNTIME
+            .spawn(async move { client2.list_common_prefixes("/").await })
+            .await
+            .map_err(|e| zerror!("Listing of the origins failed: {e}"))??;
+
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let origins = self.client.list_common_prefixes("/").await?;
+
+        Ok(origins
+            .iter()
+            .map(|origin| {
+                utils::origin_key(
+                    origin.trim_end_matches('/'),
+                    &s3_key,
+                    self.config.key_options.max_length,
+                )
+            })
+            .collect())
+    }
+
+    /// Lists the versions of [s3_key] stored under keys suffixed with their timestamps, in the
+    /// order of their timestamps.
+    async fn timestamped_keys(&self, s3_key: String) -> ZResult<Vec<String>> {
+        let prefix = format!("{s3_key}{}", utils::TIMESTAMP_SUFFIX_SEPARATOR);
+
+        #[cfg(feature = "dynamic_plugin")]
+        let client2 = self.client.clone();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let keys = STORAGE_RUNTIME
+            .spawn(async move { client2.list_keys_with_prefix(&prefix).await })
+            .await
+            .map_err(|e| zerror!("Listing of the versions of '{s3_key}' failed: {e}"))??;
+
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let keys = self.client.list_keys_with_prefix(&prefix).await?;
+
+        // The prefix also matches the hashed keys whose truncated part equals the key.
+        Ok(keys
+            .into_iter()
+            .filter(|key| utils::strip_timestamp_suffix(key) == Some(s3_key.as_str()))
+            .collect())
+    }
+
+    /// Checks whether the [result] of an operation is a failure due to the bucket not existing
+    /// anymore (e.g. because it was deleted externally), in which case a distinct error is
+    /// returned and the admin status of the storage reports the missing bucket.
+    ///
+    /// When `recreate_on_missing` is set and the bucket was created by the storage, the bucket is
+    /// recreated, though the failed operation is not replayed.
+    async fn check_bucket<T>(&self, result: ZResult<T>) -> ZResult<T> {
+        let err = match result {
+            Ok(value) => {
+                self.bucket_missing.store(false, Ordering::Relaxed);
+                return Ok(value);
+            }
+            Err(err) if err.to_string().contains(NO_SUCH_BUCKET_ERROR_CODE) => err,
+            Err(err) => return Err(err),
+        };
+        self.bucket_missing.store(true, Ordering::Relaxed);
+        if !self.config.recreate_on_missing || !self.owns_bucket {
+            return Err(zerror!(
+                "Bucket '{}' doesn't exist anymore, it may have been deleted externally: {}",
+                self.client,
+                err
+            )
+            .into());
+        }
+
+        tracing::warn!(
+            "Bucket '{}' doesn't exist anymore, recreating it...",
+            self.client
+        );
+        #[cfg(feature = "dynamic_plugin")]
+        let created = {
+            let client2 = self.client.clone();
+            STORAGE_RUNTIME
+                .spawn(async move { client2.create_bucket(true).await })
+                .await
+                .unwrap_or_else(|e| Err(zerror!("{e}").into()))
+        };
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let created = self.client.create_bucket(true).await;
+        match created {
+            Ok(_) => {
+                self.bucket_missing.store(false, Ordering::Relaxed);
+                Err(zerror!(
+                    "Bucket '{}' was missing and has been recreated, the operation failed: {}",
+                    self.client,
+                    err
+                )
+                .into())
+            }
+            Err(e) => Err(zerror!(
+                "Bucket '{}' doesn't exist anymore and couldn't be recreated: {}",
+                self.client,
+                e
+            )
+            .into()),
+        }
+    }
+
+    /// Checks whether a write with the given [timestamp] is outdated with respect to the object
+    /// currently stored under [key].
+    ///
+    /// The check is only performed when `timestamp_skew_tolerance` is configured, in which case
+    /// the write is considered outdated if its timestamp is older than the stored one by more than
+    /// the tolerance. When the timestamps are suffixed to the keys, the most recent version of
+    /// [key] is checked.
+    async fn is_outdated(&self, key: &String, timestamp: &Timestamp) -> ZResult<bool> {
+        let tolerance = match self.config.timestamp_skew_tolerance {
+            Some(tolerance) => tolerance,
+            None => return Ok(false),
+        };
+        let key = &match self.config.timestamp_key_suffix {
+            true => match self.timestamped_keys(key.to_owned()).await?.pop() {
+                Some(latest_key) => latest_key,
+                None => return Ok(false),
+            },
+            false => key.to_owned(),
+        };
+
+        #[cfg(feature = "dynamic_plugin")]
+        let client2 = self.client.clone();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let key2 = key.to_owned();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let head = STORAGE_RUNTIME
+            .spawn(async move { client2.get_head_object_if_exists(key2.as_str()).await })
+            .await
+            .map_err(|e| zerror!("Head operation failed for key '{key}': {e}"))?
+            .map_err(|e| zerror!("Head operation failed for key '{key}': {e}"))?;
+
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let head = self
+            .client
+            .get_head_object_if_exists(key.as_str())
+            .await
+            .map_err(|e| zerror!("Head operation failed for key '{key}': {e}"))?;
+
+        let stored_timestamp = match head
+            .as_ref()
+            .and_then(|head| head.metadata.as_ref())
+            .and_then(|metadata| metadata.get(&self.config.timestamp_metadata_key))
+        {
+            Some(stored_timestamp) => Timestamp::from_str(stored_timestamp.as_str())
+                .map_err(|e| zerror!("Unable to obtain timestamp for key: {}. {:?}", key, e))?,
+            None => return Ok(false),
+        };
+
+        Ok(timestamp.get_time().to_duration() + tolerance
+            < stored_timestamp.get_time().to_duration())
+    }
+
+    /// Returns the timestamp of the object stored under [key], read from its [metadata] or, with
+    /// the `last_modified` policy for the missing timestamps, synthesized from the [last_modified]
+    /// date of the object (in nanoseconds since the UNIX epoch).
+    fn object_timestamp(
+        &self,
+        key: &str,
+        metadata: Option<&HashMap<String, String>>,
+        last_modified: Option<i128>,
+    ) -> ZResult<Timestamp> {
+        if let Some(timestamp) =
+            metadata.and_then(|metadata| metadata.get(&self.config.timestamp_metadata_key))
+        {
+            return Timestamp::from_str(timestamp.as_str()).map_err(|e| {
+                zerror!("Unable to obtain timestamp for key: {}. {:?}", key, e).into()
+            });
+        }
+        match (&self.last_modified_stamps, last_modified) {
+            (Some(synthesizer), Some(last_modified)) => {
+                tracing::debug!(
+                    "Synthesizing the timestamp of '{}' from its last modification.",
+                    utils::redact_key(key, self.config.log_keys)
+                );
+                let time = Duration::from_nanos(last_modified.max(0) as u64);
+                Ok(synthesizer.timestamp_at(time))
+            }
+            _ if metadata.is_none() => Err(zerror!("Unable to retrieve metadata.").into()),
+            _ => Err(zerror!("Unable to retrieve timestamp.").into()),
+        }
     }
-}
 
-impl S3Storage {
     async fn get_stored_value(&self, key: &String) -> ZResult<Option<(Timestamp, Value)>> {
         #[cfg(feature = "dynamic_plugin")]
         let client2 = self.client.clone();
@@ -434,49 +1985,343 @@ impl S3Storage {
         #[cfg(not(feature = "dynamic_plugin"))]
         let res = self.client.get_object(key.as_str()).await;
 
-        let output_result = match res {
-            Ok(result) => Ok(result),
-            Err(e) => {
-                if e.to_string().contains("NoSuchKey") {
-                    return Ok(None);
-                }
-                Err(zerror!("Get operation failed for key '{key}': {e}"))
+        let object = match res {
+            Ok(Some(object)) => object,
+            Ok(None) => return Ok(None),
+            Err(e) => return Err(zerror!("Get operation failed for key '{key}': {e}").into()),
+        };
+
+        let metadata = object.metadata.as_ref();
+        check_format_version(key, metadata)?;
+        let timestamp = self.object_timestamp(key, metadata, object.last_modified)?;
+
+        // Objects stored by former versions of this backend carry the zenoh encoding as their
+        // Content-Encoding.
+        let mut encoding = metadata
+            .and_then(|metadata| metadata.get(ENCODING_METADATA_KEY))
+            .or(object.content_encoding.as_ref())
+            .map(|x| x.to_string());
+        if encoding.is_none() {
+            encoding = object.content_type.as_ref().and_then(|content_type| {
+                self.config
+                    .content_types
+                    .iter()
+                    .find(|(_, mapped)| *mapped == content_type)
+                    .map(|(encoding, _)| encoding.to_owned())
+            });
+        }
+        if let Some(sniffing_bytes) = self.config.content_sniffing_bytes {
+            if encoding.is_none() && utils::is_untyped(object.content_type.as_deref()) {
+                encoding =
+                    utils::sniff_content_type(&object.body, sniffing_bytes).map(|content_type| {
+                        tracing::trace!(
+                            "Inferred content type '{content_type}' of '{}'.",
+                            utils::redact_key(key, self.config.log_keys)
+                        );
+                        content_type.to_string()
+                    });
             }
-        }?;
+        }
 
-        let metadata = output_result
-            .metadata
-            .as_ref()
-            .ok_or_else(|| zerror!("Unable to retrieve metadata."))?;
-        let timestamp = metadata
-            .get(TIMESTAMP_METADATA_KEY)
-            .ok_or_else(|| zerror!("Unable to retrieve timestamp."))?;
-        let timestamp = Timestamp::from_str(timestamp.as_str())
-            .map_err(|e| zerror!("Unable to obtain timestamp for key: {}. {:?}", key, e))?;
-
-        let encoding = output_result.content_encoding().map(|x| x.to_string());
-        let bytes = output_result
-            .body
-            .collect()
-            .await
-            .map(|data| data.into_bytes())
-            .map_err(|e| {
-                zerror!("Get operation failed. Couldn't process retrieved contents: {e}")
-            })?;
-
-        let value = match encoding {
-            Some(encoding) => Encoding::try_from(encoding).map_or_else(
-                |_| Value::from(Vec::from(bytes.to_owned())),
-                |result| Value::from(Vec::from(bytes.to_owned())).encoding(result),
+        Ok(Some((
+            timestamp,
+            value_with_encoding(
+                object.body.clone(),
+                encoding,
+                self.config.default_encoding.as_ref(),
             ),
-            None => Value::from(Vec::from(bytes)),
+        )))
+    }
+
+    /// Retrieves the records of the object stored under [key] matching the S3 Select
+    /// [expression], along with the timestamp of the object.
+    async fn get_selected_value(
+        &self,
+        key: &String,
+        expression: String,
+        format: SelectFormat,
+    ) -> ZResult<Option<(Timestamp, Value)>> {
+        #[cfg(feature = "dynamic_plugin")]
+        let client2 = self.client.clone();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let key2 = key.to_owned();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let head = STORAGE_RUNTIME
+            .spawn(async move { client2.get_head_object_if_exists(key2.as_str()).await })
+            .await
+            .map_err(|e| zerror!("Get operation failed for key '{key}': {e}"))?
+            .map_err(|e| zerror!("Get operation failed for key '{key}': {e}"))?;
+
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let head = self
+            .client
+            .get_head_object_if_exists(key.as_str())
+            .await
+            .map_err(|e| zerror!("Get operation failed for key '{key}': {e}"))?;
+
+        let head = match head {
+            Some(head) => head,
+            None => return Ok(None),
+        };
+        let metadata = head.metadata.as_ref();
+        check_format_version(key, metadata)?;
+        let last_modified = head.last_modified.map(|date| date.as_nanos());
+        let timestamp = self.object_timestamp(key, metadata, last_modified)?;
+        let encoding = metadata
+            .and_then(|metadata| metadata.get(ENCODING_METADATA_KEY))
+            .map(|x| utils::decode_metadata_value(x.to_string()))
+            .transpose()?;
+
+        #[cfg(feature = "dynamic_plugin")]
+        let client2 = self.client.clone();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let key2 = key.to_owned();
+
+        #[cfg(feature = "dynamic_plugin")]
+        let records = STORAGE_RUNTIME
+            .spawn(async move {
+                client2
+                    .select_object_content(key2.as_str(), expression, format)
+                    .await
+            })
+            .await
+            .map_err(|e| zerror!("Select operation failed for key '{key}': {e}"))?
+            .map_err(|e| zerror!("Select operation failed for key '{key}': {e}"))?;
+
+        #[cfg(not(feature = "dynamic_plugin"))]
+        let records = self
+            .client
+            .select_object_content(key.as_str(), expression, format)
+            .await
+            .map_err(|e| zerror!("Select operation failed for key '{key}': {e}"))?;
+
+        Ok(Some((
+            timestamp,
+            value_with_encoding(records, encoding, self.config.default_encoding.as_ref()),
+        )))
+    }
+}
+
+/// Resolves the region where the bucket of the [client] is located, or None if the bucket doesn't
+/// exist yet or its region can't be retrieved (e.g. for lack of permissions).
+async fn resolve_bucket_region(client: Arc<S3Client>) -> Option<String> {
+    #[cfg(feature = "dynamic_plugin")]
+    let result = {
+        let client2 = client.clone();
+        STORAGE_RUNTIME
+            .spawn(async move { client2.get_bucket_region().await })
+            .await
+            .unwrap_or_else(|e| Err(zerror!("{e}").into()))
+    };
+    #[cfg(not(feature = "dynamic_plugin"))]
+    let result = client.get_bucket_region().await;
+    result.unwrap_or_else(|e| {
+        tracing::warn!("Couldn't retrieve the region of bucket '{}': {}", client, e);
+        None
+    })
+}
+
+/// Builds a [Value] from the retrieved bytes, with the given encoding if it is valid, otherwise
+/// with the [default_encoding] if configured.
+fn value_with_encoding(
+    bytes: Vec<u8>,
+    encoding: Option<String>,
+    default_encoding: Option<&Encoding>,
+) -> Value {
+    let encoding = encoding.and_then(|encoding| match Encoding::try_from(encoding.to_owned()) {
+        Ok(encoding) => Some(encoding),
+        Err(e) => {
+            tracing::warn!("Ignoring the invalid encoding '{encoding}' of the object: {e:?}");
+            None
+        }
+    });
+    match encoding {
+        Some(encoding) => Value::from(bytes).encoding(encoding),
+        None => match default_encoding {
+            Some(default_encoding) => Value::from(bytes).encoding(default_encoding.to_owned()),
+            None => Value::from(bytes),
+        },
+    }
+}
+
+/// Creates the [LogStore] of a storage in the log-structured mode, loading the index of its
+/// segments, and spawns the task flushing its buffer periodically until the storage is dropped.
+async fn start_log_store(
+    client: Arc<S3Client>,
+    config: config::LogStructuredConfig,
+    segment_prefix: String,
+    default_encoding: Option<Encoding>,
+) -> ZResult<Arc<LogStore>> {
+    let log_store = Arc::new(LogStore::new(
+        client,
+        config,
+        segment_prefix,
+        default_encoding,
+    ));
+
+    #[cfg(feature = "dynamic_plugin")]
+    let loaded = {
+        let c_log_store = log_store.clone();
+        STORAGE_RUNTIME
+            .spawn(async move { c_log_store.load().await })
+            .await
+            .map_err(|e| zerror!("Couldn't load the log segments: {e}"))?
+    };
+    #[cfg(not(feature = "dynamic_plugin"))]
+    let loaded = log_store.load().await;
+    let loaded = loaded.map_err(|e| zerror!("Couldn't load the log segments: {e}"))?;
+    tracing::debug!("Loaded the index of {loaded} log segments.");
+
+    let weak_log_store = Arc::downgrade(&log_store);
+    let flush_interval = log_store.flush_interval();
+    let flusher = async move {
+        loop {
+            tokio::time::sleep(flush_interval).await;
+            match weak_log_store.upgrade() {
+                Some(log_store) => {
+                    if let Err(e) = log_store.flush().await {
+                        tracing::error!("{e}");
+                    }
+                }
+                None => break,
+            }
+        }
+    };
+    #[cfg(feature = "dynamic_plugin")]
+    STORAGE_RUNTIME.spawn(flusher);
+    #[cfg(not(feature = "dynamic_plugin"))]
+    tokio::task::spawn(flusher);
+    Ok(log_store)
+}
+
+/// Checks that the format version of the object stored under [key], read from its [metadata],
+/// is supported by this backend. The objects of the first version, including those stored
+/// without format version, are read as they are.
+fn check_format_version(key: &str, metadata: Option<&HashMap<String, String>>) -> ZResult<()> {
+    match metadata.and_then(|metadata| metadata.get(FORMAT_VERSION_METADATA_KEY)) {
+        None => Ok(()),
+        Some(version) if version == FORMAT_VERSION => Ok(()),
+        Some(version) => Err(zerror!(
+            "Object '{key}' has the format version {version}, which isn't supported by this \
+            version of the backend (up to {FORMAT_VERSION})."
+        )
+        .into()),
+    }
+}
+
+/// Returns whether the object stored under [object_key] holds a value whose key intersects the
+/// [key_expr] filtering an export. The internal objects and the hashed object keys, whose
+/// complete key is only known from their metadata, are never exported by a filtered export.
+fn is_exported(
+    path_prefix: Option<&String>,
+    key_options: &KeyOptions,
+    reserved_prefix: &str,
+    timestamp_key_suffix: bool,
+    isolate_origins: bool,
+    key_expr: &OwnedKeyExpr,
+    object_key: &str,
+) -> bool {
+    let stored_key = match timestamp_key_suffix {
+        true => match utils::strip_timestamp_suffix(object_key) {
+            Some(stored_key) => stored_key,
+            None => return false,
+        },
+        false => object_key,
+    };
+    let stored_key = match isolate_origins {
+        true => utils::strip_origin(stored_key),
+        false => stored_key,
+    };
+    if stored_key == NONE_KEY
+        || stored_key.starts_with(reserved_prefix)
+        || object_key.starts_with(reserved_prefix)
+        || utils::is_hashed_object_key(stored_key)
+    {
+        return false;
+    }
+    match key_options.strip_hash_segment(stored_key) {
+        Some(stored_key) => S3Key::from_key(path_prefix, stored_key, key_options)
+            .map_or(false, |s3_key| s3_key.key_expr.intersects(key_expr)),
+        None => false,
+    }
+}
+
+/// Returns the key expression, stripped from the prefix, of the entry stored under an object whose
+/// (complete) key without origin is [stored_key], or None if the entry isn't included in the key
+/// expression of the storage or its key is invalid. The object key is logged as [logged_key].
+///
+/// The entry of a key which is empty once stripped from the prefix is handled according to
+/// [empty_keys], either skipped or reported as the None key (i.e. `Some(None)`).
+fn entry_key_expr(
+    path_prefix: Option<&String>,
+    key_options: &KeyOptions,
+    storage_key_expr: &OwnedKeyExpr,
+    empty_keys: EmptyKeys,
+    stored_key: String,
+    logged_key: &str,
+) -> Option<Option<OwnedKeyExpr>> {
+    if utils::is_empty_key(path_prefix, &stored_key, key_options) {
+        return match empty_keys {
+            EmptyKeys::Skip => {
+                tracing::debug!("Ignoring object with empty key '{logged_key}'.");
+                None
+            }
+            EmptyKeys::NoneKey => Some(None),
         };
-        Ok(Some((timestamp, value)))
+    }
+    match S3Key::from_key(path_prefix, stored_key, key_options) {
+        Ok(s3_key) => {
+            if !s3_key.key_expr.intersects(storage_key_expr) {
+                return None;
+            }
+            match s3_key.stripped_key_expr() {
+                Ok(key_expr) => Some(Some(key_expr)),
+                Err(err) => {
+                    tracing::error!(
+                        "Unable to generate key expression for key '{}': {}",
+                        logged_key,
+                        &err
+                    );
+                    None
+                }
+            }
+        }
+        Err(err) => {
+            tracing::error!("Error filtering storage entries: ${err}.");
+            None
+        }
     }
 }
 
 impl Drop for S3Storage {
     fn drop(&mut self) {
+        // The buffered entries of the log-structured mode are flushed along with the pending
+        // operations.
+        if let Some(log_store) = self.log_store.take() {
+            let operation = self.pending_operations.start();
+            async_std::task::spawn(async move {
+                if let Err(e) = log_store.flush().await {
+                    tracing::error!("{e}");
+                }
+                drop(operation);
+            });
+        }
+        // Waits for the puts and deletes still in flight so that they are not lost (or don't
+        // race with the destruction of the bucket).
+        let pending = self
+            .pending_operations
+            .flush(self.config.close_flush_timeout);
+        if pending > 0 {
+            tracing::warn!(
+                "Closing S3 storage '{}' with {} operations still pending after {:?}.",
+                self.client,
+                pending,
+                self.config.close_flush_timeout
+            );
+        }
         match self.config.on_closure {
             config::OnClosure::DestroyBucket => {
                 let client2 = self.client.clone();
@@ -502,3 +2347,2628 @@ impl Drop for S3Storage {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::mock_s3::{self, MockS3};
+    use serde_json::json;
+    use zenoh_buffers::buffer::SplitBuffer;
+
+    /// Waits for the maintenance [operation] of the [storage] to be done, returning its report.
+    async fn operation_report(storage: &dyn Storage, operation: &str) -> serde_json::Value {
+        loop {
+            let status = storage.get_admin_status();
+            let report = &status["maintenance_operations"][operation];
+            if report["state"] != "running" {
+                return report.to_owned();
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+    }
+
+    /// Subscriber recording the spans created, as their name and fields (e.g.
+    /// `s3_storage{bucket=zenoh-test-bucket key_expr=test/**}`) along with their explicit parent.
+    #[derive(Clone, Default)]
+    struct SpanRecorder(Arc<std::sync::Mutex<Vec<(String, Option<u64>)>>>);
+
+    impl SpanRecorder {
+        fn spans(&self) -> Vec<(String, Option<u64>)> {
+            self.0.lock().unwrap_or_else(|e| e.into_inner()).clone()
+        }
+    }
+
+    impl tracing::Subscriber for SpanRecorder {
+        fn enabled(&self, _: &tracing::Metadata<'_>) -> bool {
+            true
+        }
+
+        fn new_span(&self, span: &tracing::span::Attributes<'_>) -> tracing::span::Id {
+            let mut fields = vec![];
+            span.record(
+                &mut |field: &tracing::field::Field, value: &dyn std::fmt::Debug| {
+                    fields.push(format!("{field}={value:?}"))
+                },
+            );
+            let name = format!("{}{{{}}}", span.metadata().name(), fields.join(" "));
+            let mut spans = self.0.lock().unwrap_or_else(|e| e.into_inner());
+            spans.push((name, span.parent().map(|x| x.into_u64())));
+            tracing::span::Id::from_u64(spans.len() as u64)
+        }
+
+        fn record(&self, _: &tracing::span::Id, _: &tracing::span::Record<'_>) {}
+
+        fn record_follows_from(&self, _: &tracing::span::Id, _: &tracing::span::Id) {}
+
+        fn event(&self, _: &tracing::Event<'_>) {}
+
+        fn enter(&self, _: &tracing::span::Id) {}
+
+        fn exit(&self, _: &tracing::span::Id) {}
+    }
+
+    /// Waits for the [condition] to hold, e.g. once a background operation is done.
+    async fn wait_until(condition: impl Fn() -> bool) {
+        for _ in 0..500 {
+            if condition() {
+                return;
+            }
+            tokio::time::sleep(Duration::from_millis(10)).await;
+        }
+        panic!("Condition still not met after 5s.");
+    }
+
+    #[tokio::test]
+    async fn writes_outdated_beyond_the_skew_tolerance_are_rejected() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "timestamp_skew_tolerance": 500 })).await;
+        let at = |ms| mock_s3::timestamp_at(Duration::from_millis(ms));
+
+        let result = storage
+            .put(mock_s3::key("a"), Value::from("1"), at(10_000))
+            .await;
+        assert!(matches!(result, Ok(StorageInsertionResult::Inserted)));
+        // Exactly at the boundary of the tolerance, the write is accepted.
+        let result = storage
+            .put(mock_s3::key("a"), Value::from("2"), at(9_500))
+            .await;
+        assert!(matches!(result, Ok(StorageInsertionResult::Inserted)));
+        // The stored timestamp is now the one of the accepted write.
+        let result = storage
+            .put(mock_s3::key("a"), Value::from("3"), at(8_999))
+            .await;
+        assert!(matches!(result, Ok(StorageInsertionResult::Outdated)));
+        let result = storage.delete(mock_s3::key("a"), at(8_999)).await;
+        assert!(matches!(result, Ok(StorageInsertionResult::Outdated)));
+        assert_eq!(mock.object("/a").unwrap().body, b"2");
+    }
+
+    #[tokio::test]
+    async fn compaction_keeps_the_newest_versions_of_each_key() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(true);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "compaction_keep_versions": 2, "allow_maintenance_queries": true }),
+        )
+        .await;
+        for (i, value) in ["1", "2", "3", "4"].into_iter().enumerate() {
+            let timestamp = mock_s3::timestamp(i as u64 + 1);
+            storage
+                .put(mock_s3::key("a"), Value::from(value), timestamp)
+                .await
+                .unwrap();
+        }
+        storage
+            .put(mock_s3::key("b"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+
+        let replies = storage
+            .get(mock_s3::key("a"), "s3_operation=compact")
+            .await
+            .unwrap();
+        assert!(replies.is_empty());
+        assert_eq!(
+            operation_report(&*storage, "compact").await,
+            json!({ "state": "completed", "deleted_versions": 2 })
+        );
+        let bodies = |key: &str| {
+            mock.versions(key)
+                .into_iter()
+                .map(|x| x.body)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(bodies("/a"), vec![b"3".to_vec(), b"4".to_vec()]);
+        assert_eq!(bodies("/b"), vec![b"1".to_vec()]);
+    }
+
+    #[tokio::test]
+    async fn compaction_processes_the_versions_page_by_page() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(true);
+        // 1500 versions, listed in two pages splitting the versions of a key.
+        for i in 0..500 {
+            for version in ["1", "2", "3"] {
+                mock.insert_object(&format!("/k{i:03}"), version.as_bytes(), &[]);
+            }
+        }
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "compaction_keep_versions": 1, "allow_maintenance_queries": true }),
+        )
+        .await;
+
+        storage
+            .get(mock_s3::key("a"), "s3_operation=compact")
+            .await
+            .unwrap();
+        assert_eq!(
+            operation_report(&*storage, "compact").await,
+            json!({ "state": "completed", "deleted_versions": 1000 })
+        );
+        assert_eq!(mock.requests_of("ListObjectVersions").len(), 2);
+        for i in [0, 333, 499] {
+            let versions = mock.versions(&format!("/k{i:03}"));
+            assert_eq!(versions.len(), 1);
+            assert_eq!(versions[0].body, b"3");
+        }
+    }
+
+    #[tokio::test]
+    async fn compaction_reports_the_versions_failing_to_be_deleted() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(true);
+        for version in ["1", "2", "3"] {
+            mock.insert_object("/a", version.as_bytes(), &[]);
+        }
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "compaction_keep_versions": 1, "allow_maintenance_queries": true }),
+        )
+        .await;
+        let body = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<DeleteResult>\
+            <Deleted><Key>/a</Key><VersionId>v2</VersionId></Deleted>\
+            <Error><Key>/a</Key><VersionId>v1</VersionId><Code>AccessDenied</Code>\
+            <Message>Access Denied</Message></Error></DeleteResult>";
+        let headers = vec![("content-type".to_string(), "application/xml".to_string())];
+        mock.inject(1, 200, headers, body.into(), |x| {
+            x.operation() == "DeleteObjects"
+        });
+
+        storage
+            .get(mock_s3::key("a"), "s3_operation=compact")
+            .await
+            .unwrap();
+        let report = operation_report(&*storage, "compact").await;
+        assert_eq!(report["state"], "failed");
+        let error = report["error"].as_str().unwrap();
+        assert!(error.contains("Deleted 1 versions"), "{error}");
+        assert!(
+            error.contains("'/a' (version 'v1'): Access Denied"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn maintenance_queries_are_rejected_unless_allowed() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(true);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "compaction_keep_versions": 1 })).await;
+
+        let result = storage.get(mock_s3::key("a"), "s3_operation=compact").await;
+        assert!(result.is_err());
+        assert!(storage.get_admin_status()["maintenance_operations"].is_null());
+        assert!(mock.requests_of("ListObjectVersions").is_empty());
+    }
+
+    #[tokio::test]
+    async fn verification_reports_the_objects_with_invalid_timestamps() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/good", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.insert_object("/missing", b"1", &[]);
+        mock.insert_object("/unparseable", b"1", &[(TIMESTAMP_METADATA_KEY, "1")]);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "allow_maintenance_queries": true })).await;
+
+        storage
+            .get(mock_s3::key("a"), "s3_operation=verify")
+            .await
+            .unwrap();
+        assert_eq!(
+            operation_report(&*storage, "verify").await,
+            json!({ "state": "completed", "scanned_objects": 3, "invalid_timestamps": 2 })
+        );
+        assert!(mock.requests_of("PutObject").is_empty());
+    }
+
+    #[tokio::test]
+    async fn reencryption_requires_an_sse_kms_key() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a", "/b"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "allow_maintenance_queries": true })).await;
+        let result = storage
+            .get(mock_s3::key("a"), "s3_operation=reencrypt")
+            .await;
+        assert!(result.is_err());
+        assert!(storage.get_admin_status()["maintenance_operations"].is_null());
+
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "sse_kms_key_id": "key-2", "allow_maintenance_queries": true }),
+        )
+        .await;
+        storage
+            .get(mock_s3::key("a"), "s3_operation=reencrypt")
+            .await
+            .unwrap();
+        assert_eq!(
+            operation_report(&*storage, "reencrypt").await,
+            json!({ "state": "completed", "scanned_objects": 2, "reencrypted_objects": 2 })
+        );
+        assert_eq!(mock.requests_of("CopyObject").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn zenoh_encoding_is_kept_apart_from_the_content_encoding() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let encoding = Encoding::try_from("application/json".to_string()).unwrap();
+
+        let value = Value::from("{}").encoding(encoding);
+        storage
+            .put(mock_s3::key("a"), value, mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let object = mock.object("/a").unwrap();
+        assert_eq!(
+            object.metadata().get(ENCODING_METADATA_KEY).unwrap(),
+            "application/json"
+        );
+        assert!(!object.headers.contains_key("content-encoding"));
+
+        // The object is then compressed by another system.
+        mock.set_header("/a", "content-encoding", "gzip");
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].value.encoding.to_string(), "application/json");
+    }
+
+    #[tokio::test]
+    async fn bucket_operations_are_retried_upon_transient_failures() {
+        let mock = MockS3::start().await;
+        // Errors which the SDK doesn't retry by itself.
+        mock.fail("CreateBucket", 2, 409, "OperationAborted");
+        let storage = mock_s3::create_storage(
+            &mock,
+            json!({ "bucket_retry_backoff_ms": 1, "on_closure": "destroy_bucket" }),
+        )
+        .await;
+        assert_eq!(mock.requests_of("CreateBucket").len(), 3);
+        assert!(mock.bucket_exists());
+
+        mock.fail("DeleteBucket", 2, 429, "TooManyRequests");
+        drop(storage);
+        wait_until(|| !mock.bucket_exists()).await;
+        assert_eq!(mock.requests_of("DeleteBucket").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn keys_round_trip_in_the_legacy_layouts() {
+        for (layout, object_key) in [("no_leading_slash", "a/b"), ("full_key", "test/a/b")] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let mut storage =
+                mock_s3::create_storage(&mock, json!({ "legacy_layout": layout })).await;
+
+            storage
+                .put(mock_s3::key("a/b"), Value::from("1"), mock_s3::timestamp(1))
+                .await
+                .unwrap();
+            assert_eq!(mock.keys(), vec![object_key.to_string()], "{layout}");
+            let replies = storage.get(mock_s3::key("a/b"), "").await.unwrap();
+            assert_eq!(replies.len(), 1, "{layout}");
+            assert_eq!(
+                storage.get_all_entries().await.unwrap(),
+                vec![(mock_s3::key("a/b"), mock_s3::timestamp(1))],
+                "{layout}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn keys_with_empty_segments_are_normalized_or_ignored() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a//b", "/a/b/", "/c/", "//d", "/e"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let entries = |storage: Box<dyn Storage>| async move {
+            let mut keys = storage
+                .get_all_entries()
+                .await
+                .unwrap()
+                .into_iter()
+                .map(|(key, _)| key.unwrap().to_string())
+                .collect::<Vec<_>>();
+            keys.sort();
+            keys
+        };
+
+        let storage = mock_s3::create_storage(&mock, json!({})).await;
+        assert_eq!(entries(storage).await, vec!["a/b", "c", "d", "e"]);
+        let storage =
+            mock_s3::create_storage(&mock, json!({ "trailing_slash_keys": "ignore" })).await;
+        assert_eq!(entries(storage).await, vec!["e"]);
+    }
+
+    #[tokio::test]
+    async fn uploads_are_bounded_by_the_in_flight_bytes_budget() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let volume = mock_s3::start_volume(&mock, json!({ "max_in_flight_upload_bytes": 4 }));
+        let mut storages = vec![];
+        for _ in 0..3 {
+            let config = mock_s3::storage_config(json!({}));
+            storages.push(volume.create_storage(config).await.unwrap());
+        }
+        let delay = Duration::from_millis(200);
+        mock.set_delay(Some(delay));
+
+        // No two values of 3 bytes fit at once within the budget, so they are uploaded in turn.
+        let start = std::time::Instant::now();
+        let puts = storages.iter_mut().enumerate().map(|(i, storage)| {
+            let key = mock_s3::key(&format!("k{i}"));
+            storage.put(key, Value::from("abc"), mock_s3::timestamp(1))
+        });
+        for result in join_all(puts).await {
+            result.unwrap();
+        }
+        assert!(start.elapsed() >= delay * 3, "{:?}", start.elapsed());
+        assert_eq!(mock.keys().len(), 3);
+    }
+
+    // Serializes the tests setting environment variables, which are shared by the whole process.
+    static ENV_LOCK: std::sync::Mutex<()> = std::sync::Mutex::new(());
+
+    #[tokio::test]
+    async fn endpoint_and_region_fall_back_to_the_environment() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let config = VolumeConfig {
+            name: "s3".to_string(),
+            backend: None,
+            paths: None,
+            required: false,
+            rest: Default::default(),
+        };
+        let volume = {
+            let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            std::env::set_var("AWS_ENDPOINT_URL_S3", &mock.url);
+            std::env::set_var("AWS_REGION", "us-east-1");
+            let volume = S3Backend::start("s3", &config);
+            std::env::remove_var("AWS_ENDPOINT_URL_S3");
+            std::env::remove_var("AWS_REGION");
+            volume.unwrap()
+        };
+
+        let mut storage = volume
+            .create_storage(mock_s3::storage_config(json!({})))
+            .await
+            .unwrap();
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(mock.object("/a").unwrap().body, b"1");
+    }
+
+    #[tokio::test]
+    async fn endpoint_falls_back_to_the_aws_shared_config_file() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let config_file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(
+            config_file.path(),
+            format!(
+                "[profile other]\nendpoint_url = http://127.0.0.1:1\n\n\
+                [profile minio]\nendpoint_url = http://127.0.0.1:1\nservices = local\n\n\
+                [services local]\ns3 =\n  endpoint_url = {}\n",
+                mock.url
+            ),
+        )
+        .unwrap();
+        let config = VolumeConfig {
+            name: "s3".to_string(),
+            backend: None,
+            paths: None,
+            required: false,
+            rest: [("region".to_string(), "us-east-1".into())]
+                .into_iter()
+                .collect(),
+        };
+        let volume = {
+            let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+            std::env::set_var("AWS_CONFIG_FILE", config_file.path());
+            std::env::set_var("AWS_PROFILE", "minio");
+            let volume = S3Backend::start("s3", &config);
+            std::env::remove_var("AWS_CONFIG_FILE");
+            std::env::remove_var("AWS_PROFILE");
+            volume.unwrap()
+        };
+
+        // The endpoint of the s3 service takes precedence over the one of the profile.
+        let mut storage = volume
+            .create_storage(mock_s3::storage_config(json!({})))
+            .await
+            .unwrap();
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(mock.object("/a").unwrap().body, b"1");
+    }
+
+    #[tokio::test]
+    async fn keys_differing_by_their_case_are_handled_deterministically() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({ "key_case": "lowercase" })).await;
+
+        storage
+            .put(mock_s3::key("A/b"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        storage
+            .put(mock_s3::key("a/b"), Value::from("2"), mock_s3::timestamp(2))
+            .await
+            .unwrap();
+        assert_eq!(mock.keys(), vec!["/a/b".to_string()]);
+        assert_eq!(mock.object("/a/b").unwrap().body, b"2");
+        let replies = storage.get(mock_s3::key("A/b"), "").await.unwrap();
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(2));
+
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "key_case": "reject_uppercase" })).await;
+        let result = storage
+            .put(mock_s3::key("A/c"), Value::from("1"), mock_s3::timestamp(1))
+            .await;
+        assert!(result.is_err());
+        assert!(mock.object("/A/c").is_none());
+    }
+
+    #[tokio::test]
+    async fn operations_are_traced_within_the_span_of_their_storage() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let recorder = SpanRecorder::default();
+        let _guard = tracing::dispatcher::set_default(&tracing::Dispatch::new(recorder.clone()));
+
+        let mut storage = mock_s3::create_storage(&mock, json!({ "log_level": "debug" })).await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+
+        let spans = recorder.spans();
+        let storage_span = spans
+            .iter()
+            .position(|(name, _)| {
+                *name == format!("s3_storage{{bucket={} key_expr=test/**}}", mock_s3::BUCKET)
+            })
+            .unwrap_or_else(|| panic!("{spans:?}"));
+        let parent = spans
+            .iter()
+            .find_map(|(name, parent)| name.starts_with("put{").then_some(*parent));
+        assert_eq!(parent, Some(Some(storage_span as u64 + 1)), "{spans:?}");
+    }
+
+    #[tokio::test]
+    async fn rows_are_selected_from_a_csv_object() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        let csv = b"city,country\nParis,France\nTurin,Italy\nLyon,France\n";
+        mock.insert_object("/cities", csv, &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+
+        let parameters = "s3_select=SELECT * FROM s3object s WHERE s.country = 'France'";
+        let replies = storage
+            .get(mock_s3::key("cities"), parameters)
+            .await
+            .unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(1));
+        assert_eq!(
+            replies[0].value.payload.contiguous().to_vec(),
+            b"Paris,France\nLyon,France\n"
+        );
+        assert_eq!(mock.requests_of("GetObject").len(), 0);
+    }
+
+    #[tokio::test]
+    async fn gets_are_served_from_the_cache_until_expired_or_written() {
+        async fn get(storage: &mut dyn Storage) -> Vec<u8> {
+            let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+            replies[0].value.payload.contiguous().to_vec()
+        }
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "get_cache": { "ttl_ms": 300 } })).await;
+
+        // A miss, then a hit.
+        assert_eq!(get(&mut *storage).await, b"1");
+        assert_eq!(get(&mut *storage).await, b"1");
+        assert_eq!(mock.requests_of("GetObject").len(), 1);
+
+        // The cached object expires after its time to live.
+        mock.insert_object("/a", b"2", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        assert_eq!(get(&mut *storage).await, b"2");
+        assert_eq!(mock.requests_of("GetObject").len(), 2);
+
+        // A write of the key invalidates the cached object.
+        storage
+            .put(mock_s3::key("a"), Value::from("3"), mock_s3::timestamp(2))
+            .await
+            .unwrap();
+        assert_eq!(get(&mut *storage).await, b"3");
+        assert_eq!(mock.requests_of("GetObject").len(), 3);
+    }
+
+    #[tokio::test]
+    async fn missing_buckets_are_reported_or_recreated() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+
+        mock.delete_bucket();
+        let error = storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("doesn't exist anymore"), "{error}");
+        assert_eq!(storage.get_admin_status()["bucket_missing"], true);
+        assert!(!mock.bucket_exists());
+
+        // A reused bucket is never recreated.
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "recreate_on_missing": true })).await;
+        mock.delete_bucket();
+        let error = storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("doesn't exist anymore"), "{error}");
+        assert!(!mock.bucket_exists());
+
+        // The bucket created by a storage is recreated, if configured.
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "recreate_on_missing": true })).await;
+        mock.delete_bucket();
+        let error = storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("has been recreated"), "{error}");
+        assert!(mock.bucket_exists());
+        assert!(storage.get_admin_status()["bucket_missing"].is_null());
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(mock.object("/a").unwrap().body, b"1");
+    }
+
+    #[tokio::test]
+    async fn heads_and_gets_are_sent_to_their_respective_endpoints() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let metadata_mock = mock.start_sharing().await;
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        let mut storage =
+            mock_s3::start_volume(&mock, json!({ "metadata_url": metadata_mock.url }))
+                .create_storage(mock_s3::storage_config(json!({})))
+                .await
+                .unwrap();
+
+        let entries = storage.get_all_entries().await.unwrap();
+        assert_eq!(entries, vec![(mock_s3::key("a"), mock_s3::timestamp(1))]);
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+
+        let ports = |operation| {
+            mock.requests_of(operation)
+                .into_iter()
+                .map(|x| x.port)
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(ports("HeadObject"), vec![metadata_mock.port]);
+        assert_eq!(ports("GetObject"), vec![mock.port]);
+    }
+
+    #[tokio::test]
+    async fn objects_encrypted_with_a_customer_key_are_read_with_it_only() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "private": {
+                    "access_key": mock_s3::ACCESS_KEY,
+                    "secret_key": mock_s3::SECRET_KEY,
+                    "sse_customer_key": "a2tra2tra2tra2tra2tra2tra2tra2tra2tra2tra2s=",
+                    "sse_customer_key_md5": "mT2HRsMGJ5IX5C+0rreZ8Q==",
+                },
+            }),
+        )
+        .await;
+
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let request = &mock.requests_of("PutObject")[0];
+        assert_eq!(
+            request.header("x-amz-server-side-encryption-customer-algorithm"),
+            Some("AES256")
+        );
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        assert!(storage.get(mock_s3::key("a"), "").await.is_err());
+    }
+
+    #[tokio::test]
+    async fn values_of_isolated_origins_are_stored_under_distinct_prefixes() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({ "isolate_origins": true })).await;
+        let first = mock_s3::timestamp(1);
+        let second = zenoh::time::Timestamp::new(
+            uhlc::NTP64::from(Duration::from_secs(2)),
+            uhlc::ID::try_from(&[2u8][..]).unwrap(),
+        );
+
+        for (value, timestamp) in [("1", first), ("2", second)] {
+            storage
+                .put(mock_s3::key("a"), Value::from(value), timestamp)
+                .await
+                .unwrap();
+        }
+        let mut expected = vec![
+            format!("{}/a", first.get_id()),
+            format!("{}/a", second.get_id()),
+        ];
+        expected.sort();
+        assert_eq!(mock.keys(), expected);
+
+        // The most recent value of the key among the origins is returned.
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"2");
+        let entries = storage.get_all_entries().await.unwrap();
+        assert_eq!(entries, vec![(mock_s3::key("a"), second)]);
+    }
+
+    #[tokio::test]
+    async fn requests_are_attempted_up_to_the_sdk_max_attempts() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        let mut storage = mock_s3::create_storage(&mock, json!({ "sdk_max_attempts": 2 })).await;
+
+        mock.fail("GetObject", 2, 503, "SlowDown");
+        assert!(storage.get(mock_s3::key("a"), "").await.is_err());
+        assert_eq!(mock.requests_of("GetObject").len(), 2);
+
+        // A single retry of the SDK succeeds once the throttling stops.
+        mock.clear_requests();
+        mock.fail("GetObject", 1, 503, "SlowDown");
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+        assert_eq!(mock.requests_of("GetObject").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn wildcard_deletes_delete_the_matching_keys() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        for key in ["/a", "/a/b", "/a/b/c", "/ab", "/x"] {
+            mock.insert_object(key, b"1", &[]);
+        }
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+
+        for (key, remaining) in [
+            ("a/*", vec!["/a", "/a/b/c", "/ab", "/x"]),
+            ("a/**", vec!["/ab", "/x"]),
+            ("x", vec!["/ab"]),
+        ] {
+            let result = storage
+                .delete(mock_s3::key(key), mock_s3::timestamp(1))
+                .await;
+            assert!(matches!(result, Ok(StorageInsertionResult::Deleted)));
+            assert_eq!(mock.keys(), remaining, "{key}");
+        }
+    }
+
+    #[tokio::test]
+    #[allow(clippy::await_holding_lock)]
+    async fn missing_credentials_are_reported_with_the_attempted_sources() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        // The environment is kept unchanged until the credentials are resolved.
+        let _lock = ENV_LOCK.lock().unwrap_or_else(|e| e.into_inner());
+        for name in [
+            "AWS_ACCESS_KEY_ID",
+            "AWS_SECRET_ACCESS_KEY",
+            "AWS_PROFILE",
+            "AWS_WEB_IDENTITY_TOKEN_FILE",
+            "AWS_CONTAINER_CREDENTIALS_RELATIVE_URI",
+            "AWS_CONTAINER_CREDENTIALS_FULL_URI",
+        ] {
+            std::env::remove_var(name);
+        }
+        let variables = [
+            ("AWS_CONFIG_FILE", "/nonexistent/config"),
+            ("AWS_SHARED_CREDENTIALS_FILE", "/nonexistent/credentials"),
+            ("AWS_EC2_METADATA_DISABLED", "true"),
+        ];
+        for (name, value) in variables {
+            std::env::set_var(name, value);
+        }
+        let result = mock_s3::start_volume(&mock, json!({}))
+            .create_storage(mock_s3::storage_config(json!({ "private": {} })))
+            .await;
+        for (name, _) in variables {
+            std::env::remove_var(name);
+        }
+
+        let error = result.err().expect("Storage created without credentials");
+        let error = error.to_string();
+        assert!(error.contains("default provider chain"), "{error}");
+        assert!(error.contains("AWS_ACCESS_KEY_ID"), "{error}");
+        assert!(error.contains("instance metadata"), "{error}");
+    }
+
+    #[tokio::test]
+    async fn objects_are_stored_with_the_configured_cache_control() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "cache_control": "max-age=3600" })).await;
+
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let request = &mock.requests_of("PutObject")[0];
+        assert_eq!(request.header("cache-control"), Some("max-age=3600"));
+        let object = mock.object("/a").unwrap();
+        assert_eq!(object.headers.get("cache-control").unwrap(), "max-age=3600");
+    }
+
+    #[tokio::test]
+    async fn effective_configuration_is_exposed_without_the_secrets() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let storage = mock_s3::create_storage(&mock, json!({ "on_closure": "do_nothing" })).await;
+
+        let admin_status = storage.get_admin_status();
+        let effective_config = &admin_status["effective_config"];
+        assert_eq!(effective_config["endpoint"], mock.url);
+        assert_eq!(effective_config["region"], "us-east-1");
+        assert_eq!(effective_config["bucket"], mock_s3::BUCKET);
+        assert_eq!(effective_config["read_only"], false);
+        assert_eq!(effective_config["on_closure"], "do_nothing");
+        assert_eq!(effective_config["credentials"], "<redacted>");
+        let admin_status = admin_status.to_string();
+        assert!(
+            !admin_status.contains(mock_s3::ACCESS_KEY),
+            "{admin_status}"
+        );
+        assert!(
+            !admin_status.contains(mock_s3::SECRET_KEY),
+            "{admin_status}"
+        );
+    }
+
+    #[tokio::test]
+    async fn stale_multipart_uploads_are_aborted_at_startup() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_upload("/stale", Duration::from_secs(2 * 86400));
+        let recent = mock.insert_upload("/recent", Duration::from_secs(60));
+
+        let _storage =
+            mock_s3::create_storage(&mock, json!({ "abort_stale_uploads_after_ms": 86_400_000 }))
+                .await;
+        wait_until(|| mock.uploads().len() == 1).await;
+        assert_eq!(mock.uploads()[0].key, "/recent");
+        let aborts = mock.requests_of("AbortMultipartUpload");
+        assert_eq!(aborts.len(), 1);
+        assert_ne!(aborts[0].query("uploadId"), Some(recent.as_str()));
+    }
+
+    #[tokio::test]
+    async fn oversized_metadata_is_rejected_or_spilled() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let encoding = format!("application/x-{}", "a".repeat(3000));
+        let value = Value::from("1").encoding(Encoding::try_from(encoding.to_owned()).unwrap());
+
+        let error = storage
+            .put(mock_s3::key("a"), value.clone(), mock_s3::timestamp(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("exceeding the 2048 bytes"), "{error}");
+        assert!(mock.requests_of("PutObject").is_empty());
+
+        // The metadata is stored in the object body instead, if configured.
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "spill_oversized_metadata": true })).await;
+        storage
+            .put(mock_s3::key("a"), value, mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+        assert_eq!(replies[0].value.encoding.to_string(), encoding);
+    }
+
+    #[tokio::test]
+    async fn timestamps_are_stored_under_the_configured_metadata_key() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "timestamp_metadata_key": "ts" })).await;
+
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let metadata = mock.object("/a").unwrap().metadata();
+        assert_eq!(metadata["ts"], mock_s3::timestamp(1).to_string());
+        assert!(!metadata.contains_key(TIMESTAMP_METADATA_KEY));
+
+        // The objects written by other systems with the same convention are read as well.
+        let timestamp = mock_s3::timestamp(2).to_string();
+        mock.insert_object("/b", b"2", &[("ts", &timestamp)]);
+        let replies = storage.get(mock_s3::key("b"), "").await.unwrap();
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(2));
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort_by_key(|(key, _)| key.clone());
+        assert_eq!(
+            entries,
+            vec![
+                (mock_s3::key("a"), mock_s3::timestamp(1)),
+                (mock_s3::key("b"), mock_s3::timestamp(2)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn payloads_are_signed_unless_configured_over_tls() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        // The SHA-256 digest of the payload.
+        let request = &mock.requests_of("PutObject")[0];
+        assert_eq!(
+            request.header("x-amz-content-sha256"),
+            Some("6b86b273ff34fce19d6b804eff5a3f5747ada4eaa22f1d49c01e52ddb7875b4b")
+        );
+
+        let result = mock_s3::start_volume(&mock, json!({}))
+            .create_storage(mock_s3::storage_config(
+                json!({ "payload_signing": "unsigned" }),
+            ))
+            .await;
+        let error = result.err().expect("Unsigned payloads allowed without TLS");
+        assert!(
+            error.to_string().contains("only allowed over TLS"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_exceeding_the_maximum_length_round_trip() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let key = format!("a/{}", "b".repeat(1100));
+
+        storage
+            .put(mock_s3::key(&key), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let object_keys = mock.keys();
+        assert_eq!(object_keys.len(), 1);
+        assert_eq!(object_keys[0].len(), 1024);
+        assert!(object_keys[0].starts_with("/a/bbb"));
+
+        let replies = storage.get(mock_s3::key(&key), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+        let entries = storage.get_all_entries().await.unwrap();
+        assert_eq!(entries, vec![(mock_s3::key(&key), mock_s3::timestamp(1))]);
+    }
+
+    #[tokio::test]
+    async fn writes_silently_dropped_are_detected() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "verify_after_write": true })).await;
+        // The gateway acknowledges the write without storing the object.
+        let headers = vec![("etag".to_string(), "\"0\"".to_string())];
+        mock.inject(1, 200, headers, vec![], |x| x.operation() == "PutObject");
+
+        let error = storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("the object is missing"), "{error}");
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(mock.object("/a").unwrap().body, b"1");
+    }
+
+    #[tokio::test]
+    async fn writes_are_verified_from_the_attributes_of_the_objects() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "verify_after_write": true })).await;
+
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(mock.requests_of("GetObjectAttributes").len(), 1);
+        assert!(mock.requests_of("HeadObject").is_empty());
+
+        // The stored object is shorter than the written value, while carrying its timestamp.
+        let body = "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<GetObjectAttributesResponse>\
+            <ETag>0</ETag><ObjectSize>1</ObjectSize></GetObjectAttributesResponse>";
+        let headers = vec![("content-type".to_string(), "application/xml".to_string())];
+        mock.inject(1, 200, headers, body.into(), |x| {
+            x.operation() == "GetObjectAttributes"
+        });
+        mock.clear_requests();
+        let error = storage
+            .put(mock_s3::key("a"), Value::from("22"), mock_s3::timestamp(2))
+            .await
+            .unwrap_err()
+            .to_string();
+        assert!(error.contains("is 1 bytes long instead of 2"), "{error}");
+        assert_eq!(mock.requests_of("GetObjectAttributes").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn untyped_objects_are_retrieved_with_the_default_encoding() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", &[0xff, 0x00], &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "default_encoding": "application/octet-stream" }),
+        )
+        .await;
+
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), [0xff, 0x00]);
+        assert_eq!(
+            replies[0].value.encoding.to_string(),
+            "application/octet-stream"
+        );
+    }
+
+    #[tokio::test]
+    async fn mismatched_bucket_regions_are_reported_or_corrected() {
+        // Returns the authorization of a put, which includes the region it is signed for.
+        async fn put_authorization(mock: &MockS3, storage: &mut dyn Storage) -> String {
+            mock.clear_requests();
+            storage
+                .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+                .await
+                .unwrap();
+            let request = &mock.requests_of("PutObject")[0];
+            request.header("authorization").unwrap().to_string()
+        }
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.set_bucket_region("eu-west-1");
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        assert_eq!(storage.get_admin_status()["bucket_region"], "eu-west-1");
+        let authorization = put_authorization(&mock, &mut *storage).await;
+        assert!(authorization.contains("/us-east-1/s3/"), "{authorization}");
+
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "region_mismatch": "correct" })).await;
+        assert_eq!(storage.get_admin_status()["bucket_region"], "eu-west-1");
+        let authorization = put_authorization(&mock, &mut *storage).await;
+        assert!(authorization.contains("/eu-west-1/s3/"), "{authorization}");
+    }
+
+    #[tokio::test]
+    async fn keys_just_deleted_are_absent_during_the_grace_period() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "delete_grace_period_ms": 300 })).await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        // An eventually consistent gateway acknowledges the delete but still serves the object.
+        mock.inject(1, 204, vec![], vec![], |x| x.operation() == "DeleteObject");
+
+        storage
+            .delete(mock_s3::key("a"), mock_s3::timestamp(2))
+            .await
+            .unwrap();
+        assert!(mock.object("/a").is_some());
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert!(replies.is_empty());
+
+        tokio::time::sleep(Duration::from_millis(400)).await;
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn reserved_objects_are_excluded_from_the_entries() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "reserved_prefix": "internal/" })).await;
+        storage
+            .put(None, Value::from("0"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(2))
+            .await
+            .unwrap();
+        mock.insert_object(
+            "internal/index",
+            b"{}",
+            &[(TIMESTAMP_METADATA_KEY, &mock_s3::timestamp(3).to_string())],
+        );
+        assert_eq!(
+            mock.keys(),
+            vec![
+                "/a".to_string(),
+                format!("internal/{NONE_KEY}"),
+                "internal/index".to_string()
+            ]
+        );
+
+        let entries = storage.get_all_entries().await.unwrap();
+        assert_eq!(entries, vec![(mock_s3::key("a"), mock_s3::timestamp(2))]);
+        let replies = storage.get(None, "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"0");
+    }
+
+    #[test]
+    fn http2_is_only_preferred_over_tls() {
+        let volume_config = |rest: serde_json::Value| VolumeConfig {
+            name: "s3".to_string(),
+            backend: None,
+            paths: None,
+            required: false,
+            rest: serde_json::from_value(rest).unwrap(),
+        };
+        let tls_config = |rest| load_tls_config(&volume_config(rest), &ConnectionConfig::default());
+
+        let http_version = |rest| tls_config(rest).unwrap().map(|x| x.http_version);
+        assert!(http_version(json!({ "tls": {} })) == Some(HttpVersion::Http1));
+        assert!(
+            http_version(json!({ "tls": {}, "http_version": "http2" })) == Some(HttpVersion::Http2)
+        );
+        assert!(http_version(json!({ "http_version": "http1" })).is_none());
+
+        let error = tls_config(json!({ "http_version": "http2" }))
+            .err()
+            .expect("HTTP/2 shouldn't be allowed without TLS");
+        assert!(
+            error.to_string().contains("can only be \"http2\""),
+            "{error}"
+        );
+        assert!(tls_config(json!({ "tls": {}, "http_version": "h2" })).is_err());
+    }
+
+    #[tokio::test]
+    async fn requests_are_capped_at_the_configured_rate() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "rate_limit": { "ops_per_second": 10, "max_wait_ms": 10000 } }),
+        )
+        .await;
+        mock.clear_requests();
+
+        // Up to one second worth of requests is let through in a burst, the others wait.
+        let start = std::time::Instant::now();
+        for i in 0..25 {
+            storage
+                .put(
+                    mock_s3::key(&format!("a{i}")),
+                    Value::from("1"),
+                    mock_s3::timestamp(1),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(mock.requests_of("PutObject").len(), 25);
+        assert!(
+            start.elapsed() >= Duration::from_millis(1400),
+            "{:?}",
+            start.elapsed()
+        );
+        let rate = storage.get_admin_status()["current_ops_per_second"]
+            .as_u64()
+            .unwrap();
+        assert!((1..=11).contains(&rate), "{rate}");
+
+        // The requests that would wait longer than the maximum wait fail.
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "rate_limit": { "ops_per_second": 1, "max_wait_ms": 0 } }),
+        )
+        .await;
+        let mut error = None;
+        for i in 0..3 {
+            if let Err(e) = storage
+                .put(
+                    mock_s3::key(&format!("b{i}")),
+                    Value::from("1"),
+                    mock_s3::timestamp(1),
+                )
+                .await
+            {
+                error = Some(e);
+                break;
+            }
+        }
+        let error = error.expect("The rate limit should have been exceeded");
+        assert!(
+            error.to_string().contains("Rate limit of 1 operations"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn original_lengths_of_the_values_are_preserved() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "preserve_original_length": true, "spill_oversized_metadata": true }),
+        )
+        .await;
+        let encoding = format!("application/x-{}", "a".repeat(3000));
+        let value = Value::from("hello").encoding(Encoding::try_from(encoding).unwrap());
+        storage
+            .put(mock_s3::key("a"), value, mock_s3::timestamp(1))
+            .await
+            .unwrap();
+
+        // The stored body is larger than the value, which carries its spilled metadata.
+        let object = mock.object("/a").unwrap();
+        assert!(object.body.len() > 3000);
+        assert_eq!(
+            object
+                .headers
+                .get("x-amz-meta-zenoh-original-length")
+                .unwrap(),
+            "5"
+        );
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"hello");
+
+        // A value whose length differs from the original one is rejected.
+        mock.insert_object(
+            "/b",
+            b"hel",
+            &[
+                (TIMESTAMP_METADATA_KEY, &mock_s3::timestamp(1).to_string()),
+                (ORIGINAL_LENGTH_METADATA_KEY, "5"),
+            ],
+        );
+        let error = storage
+            .get(mock_s3::key("b"), "")
+            .await
+            .err()
+            .expect("The truncated value should be rejected");
+        assert!(
+            error.to_string().contains("original length of 5"),
+            "{error}"
+        );
+    }
+
+    #[tokio::test]
+    async fn requests_are_signed_with_the_configured_service_name() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::start_volume(&mock, json!({ "signing_name": "gateway" }))
+            .create_storage(mock_s3::storage_config(json!({})))
+            .await
+            .unwrap();
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        let request = &mock.requests_of("PutObject")[0];
+        let authorization = request.header("authorization").unwrap();
+        assert!(
+            authorization.contains("/us-east-1/gateway/aws4_request"),
+            "{authorization}"
+        );
+
+        // The requests are signed as `s3` by default.
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        storage.get(mock_s3::key("a"), "").await.unwrap();
+        let request = &mock.requests_of("GetObject")[0];
+        let authorization = request.header("authorization").unwrap();
+        assert!(
+            authorization.contains("/us-east-1/s3/aws4_request"),
+            "{authorization}"
+        );
+    }
+
+    #[tokio::test]
+    async fn entries_are_returned_in_the_configured_order() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        for (key, time) in [("c", 1), ("a", 3), ("b/d", 2)] {
+            storage
+                .put(
+                    mock_s3::key(key),
+                    Value::from("1"),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+
+        let storage = mock_s3::create_storage(&mock, json!({ "entries_order": "key" })).await;
+        for _ in 0..3 {
+            assert_eq!(
+                storage.get_all_entries().await.unwrap(),
+                vec![
+                    (mock_s3::key("a"), mock_s3::timestamp(3)),
+                    (mock_s3::key("b/d"), mock_s3::timestamp(2)),
+                    (mock_s3::key("c"), mock_s3::timestamp(1)),
+                ]
+            );
+        }
+
+        let storage = mock_s3::create_storage(&mock, json!({ "entries_order": "timestamp" })).await;
+        assert_eq!(
+            storage.get_all_entries().await.unwrap(),
+            vec![
+                (mock_s3::key("c"), mock_s3::timestamp(1)),
+                (mock_s3::key("b/d"), mock_s3::timestamp(2)),
+                (mock_s3::key("a"), mock_s3::timestamp(3)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn keys_round_trip_with_a_custom_prefix_separator() {
+        for (layout, object_key) in [(None, ":a/b"), (Some("full_key"), "test:a/b")] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let mut volume_cfg = json!({ "prefix_separator": ":" });
+            if let Some(layout) = layout {
+                volume_cfg["legacy_layout"] = layout.into();
+            }
+            let mut storage = mock_s3::create_storage(&mock, volume_cfg).await;
+
+            storage
+                .put(mock_s3::key("a/b"), Value::from("1"), mock_s3::timestamp(1))
+                .await
+                .unwrap();
+            assert_eq!(mock.keys(), vec![object_key.to_string()], "{layout:?}");
+            let replies = storage.get(mock_s3::key("a/b"), "").await.unwrap();
+            assert_eq!(replies.len(), 1, "{layout:?}");
+            assert_eq!(
+                storage.get_all_entries().await.unwrap(),
+                vec![(mock_s3::key("a/b"), mock_s3::timestamp(1))],
+                "{layout:?}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn buffered_puts_are_flushed_as_segments_and_read_back() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let volume_cfg = json!({
+            "log_structured": { "max_batch_entries": 3, "flush_interval_ms": 60000 }
+        });
+        let mut storage = mock_s3::create_storage(&mock, volume_cfg.clone()).await;
+        for (key, value, time) in [("a", "1", 1), ("b", "2", 2), ("a", "3", 3)] {
+            storage
+                .put(
+                    mock_s3::key(key),
+                    Value::from(value),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+        // The values are served from the buffer until it is flushed.
+        assert!(mock.requests_of("PutObject").is_empty());
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"3");
+
+        storage
+            .put(mock_s3::key("c"), Value::from("4"), mock_s3::timestamp(4))
+            .await
+            .unwrap();
+        let keys = mock.keys();
+        assert_eq!(keys.len(), 1);
+        assert!(keys[0].starts_with("__zenoh__/"), "{keys:?}");
+
+        // The latest values are reconstructed from the segments by another storage.
+        let mut storage = mock_s3::create_storage(&mock, volume_cfg).await;
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"3");
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(3));
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (mock_s3::key("a"), mock_s3::timestamp(3)),
+                (mock_s3::key("b"), mock_s3::timestamp(2)),
+                (mock_s3::key("c"), mock_s3::timestamp(4)),
+            ]
+        );
+    }
+
+    #[tokio::test]
+    async fn puts_are_acknowledged_according_to_the_durability() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.set_delay(Some(Duration::from_millis(300)));
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let start = std::time::Instant::now();
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert!(start.elapsed() >= Duration::from_millis(300));
+        assert!(mock.object("/a").is_some());
+
+        // The asynchronous puts return before the object is written.
+        let mut storage = mock_s3::create_storage(&mock, json!({ "durability": "async" })).await;
+        let start = std::time::Instant::now();
+        storage
+            .put(mock_s3::key("b"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert!(start.elapsed() < Duration::from_millis(300));
+        assert!(mock.object("/b").is_none());
+        wait_until(|| mock.object("/b").is_some()).await;
+
+        // Their failures are reported in the admin status.
+        mock.set_delay(None);
+        mock.fail("PutObject", 1, 409, "OperationAborted");
+        storage
+            .put(mock_s3::key("c"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        wait_until(|| storage.get_admin_status()["failed_async_puts"] == 1).await;
+        assert!(mock.object("/c").is_none());
+    }
+
+    #[tokio::test]
+    async fn metadata_values_unsafe_in_headers_round_trip() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let encoding = Encoding::try_from("application/x-données".to_string()).unwrap();
+        let value = Value::from("1").encoding(encoding.clone());
+        storage
+            .put(mock_s3::key("a"), value, mock_s3::timestamp(1))
+            .await
+            .unwrap();
+
+        let object = mock.object("/a").unwrap();
+        let metadata = object.metadata();
+        let stored = &metadata[ENCODING_METADATA_KEY];
+        assert!(stored.starts_with("=?UTF-8?B?"), "{stored}");
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.encoding, encoding);
+
+        // Such values can't be sent as they are.
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "metadata_encoding": "raw" })).await;
+        mock.clear_requests();
+        let value = Value::from("1").encoding(encoding);
+        assert!(storage
+            .put(mock_s3::key("b"), value, mock_s3::timestamp(1))
+            .await
+            .is_err());
+        assert!(mock.requests_of("PutObject").is_empty());
+    }
+
+    #[tokio::test]
+    async fn indexed_entries_are_listed_without_head_requests() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        for (key, time) in [("/a", 1), ("/b", 2)] {
+            let timestamp = mock_s3::timestamp(time).to_string();
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let storage = mock_s3::create_storage(&mock, json!({ "entries_index": true })).await;
+        let mut expected = vec![
+            (mock_s3::key("a"), mock_s3::timestamp(1)),
+            (mock_s3::key("b"), mock_s3::timestamp(2)),
+        ];
+
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(entries, expected);
+        assert_eq!(mock.requests_of("HeadObject").len(), 2);
+
+        // The index is fresh, the entries are taken from it.
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(entries, expected);
+        assert!(mock.requests_of("HeadObject").is_empty());
+
+        // Only the objects modified since are retrieved again, the deleted ones are dropped.
+        let timestamp = mock_s3::timestamp(3).to_string();
+        mock.insert_object("/b", b"2", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.remove_object("/a");
+        mock.clear_requests();
+        expected = vec![(mock_s3::key("b"), mock_s3::timestamp(3))];
+        assert_eq!(storage.get_all_entries().await.unwrap(), expected);
+        assert_eq!(mock.requests_of("HeadObject").len(), 1);
+    }
+
+    #[tokio::test]
+    async fn idle_connections_are_closed_after_the_idle_timeout() {
+        // Returns the client address of the GetObject requests of 2 gets separated by [pause].
+        async fn peers(mock: &MockS3, storage: &mut dyn Storage, pause: Duration) -> Vec<u16> {
+            mock.clear_requests();
+            storage.get(mock_s3::key("a"), "").await.unwrap();
+            storage.get(mock_s3::key("a"), "").await.unwrap();
+            tokio::time::sleep(pause).await;
+            storage.get(mock_s3::key("a"), "").await.unwrap();
+            mock.requests_of("GetObject")
+                .iter()
+                .map(|x| x.peer.port())
+                .collect()
+        }
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        let ports = peers(&mock, &mut *storage, Duration::from_millis(300)).await;
+        assert!(ports[0] == ports[1] && ports[1] == ports[2], "{ports:?}");
+
+        let mut storage = mock_s3::start_volume(
+            &mock,
+            json!({ "idle_timeout_ms": 100, "tcp_keepalive_ms": 1000 }),
+        )
+        .create_storage(mock_s3::storage_config(json!({})))
+        .await
+        .unwrap();
+        let ports = peers(&mock, &mut *storage, Duration::from_millis(300)).await;
+        assert!(ports[0] == ports[1] && ports[1] != ports[2], "{ports:?}");
+    }
+
+    #[tokio::test]
+    async fn keys_round_trip_with_either_leading_slash_policy() {
+        for (policy, object_key, other_key) in
+            [("preserve", "/a/b", "x/y"), ("strip", "a/b", "/x/y")]
+        {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let mut storage =
+                mock_s3::create_storage(&mock, json!({ "leading_slash": policy })).await;
+
+            storage
+                .put(mock_s3::key("a/b"), Value::from("1"), mock_s3::timestamp(1))
+                .await
+                .unwrap();
+            assert_eq!(mock.keys(), vec![object_key.to_string()], "{policy}");
+            let replies = storage.get(mock_s3::key("a/b"), "").await.unwrap();
+            assert_eq!(replies.len(), 1, "{policy}");
+
+            // The objects not complying with the policy are ignored by the listings.
+            let timestamp = mock_s3::timestamp(2).to_string();
+            mock.insert_object(other_key, b"2", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+            assert_eq!(
+                storage.get_all_entries().await.unwrap(),
+                vec![(mock_s3::key("a/b"), mock_s3::timestamp(1))],
+                "{policy}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn objects_are_encrypted_with_the_configured_kms_encryption_context() {
+        use base64::engine::general_purpose;
+        use base64::Engine;
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "sse_kms_key_id": "alias/zenoh",
+                "sse_kms_encryption_context": { "tenant": "t1" }
+            }),
+        )
+        .await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        storage.get(mock_s3::key("a"), "").await.unwrap();
+
+        let request = &mock.requests_of("PutObject")[0];
+        assert_eq!(
+            request.header("x-amz-server-side-encryption"),
+            Some("aws:kms")
+        );
+        assert_eq!(
+            request.header("x-amz-server-side-encryption-aws-kms-key-id"),
+            Some("alias/zenoh")
+        );
+        let context = request
+            .header("x-amz-server-side-encryption-context")
+            .unwrap();
+        let context: serde_json::Value =
+            serde_json::from_slice(&general_purpose::STANDARD.decode(context).unwrap()).unwrap();
+        assert_eq!(context, json!({ "tenant": "t1" }));
+        // S3 supplies the context to KMS upon the reads.
+        let request = &mock.requests_of("GetObject")[0];
+        assert!(request
+            .header("x-amz-server-side-encryption-context")
+            .is_none());
+
+        let config = mock_s3::storage_config(json!({ "sse_kms_encryption_context": { "a": 1 } }));
+        assert!(S3Config::new(&config).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn metadata_missing_from_head_responses_is_retrieved_with_a_ranged_get() {
+        // A gateway not returning the user metadata on HEAD.
+        fn inject_head_without_metadata(mock: &MockS3) {
+            let headers = vec![("content-length".to_string(), "1".to_string())];
+            mock.inject(1, 200, headers, vec![], |x| x.operation() == "HeadObject");
+        }
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/a", b"12", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+
+        let storage = mock_s3::create_storage(&mock, json!({})).await;
+        inject_head_without_metadata(&mock);
+        let error = storage.get_all_entries().await.err().unwrap();
+        assert!(
+            error.to_string().contains("Unable to retrieve timestamp"),
+            "{error}"
+        );
+
+        let storage =
+            mock_s3::create_storage(&mock, json!({ "head_metadata_fallback": "ranged_get" })).await;
+        inject_head_without_metadata(&mock);
+        mock.clear_requests();
+        assert_eq!(
+            storage.get_all_entries().await.unwrap(),
+            vec![(mock_s3::key("a"), mock_s3::timestamp(1))]
+        );
+        let gets = mock.requests_of("GetObject");
+        assert_eq!(gets.len(), 1);
+        assert_eq!(gets[0].header("range"), Some("bytes=0-0"));
+    }
+
+    #[tokio::test]
+    async fn entries_are_enumerated_by_the_parallel_listing() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "legacy_layout": "no_leading_slash", "listing_concurrency": 2 }),
+        )
+        .await;
+        let keys = ["a/1", "a/2", "b/1", "b/c/1", "c"];
+        for (time, key) in keys.iter().enumerate() {
+            storage
+                .put(
+                    mock_s3::key(key),
+                    Value::from("1"),
+                    mock_s3::timestamp(time as u64 + 1),
+                )
+                .await
+                .unwrap();
+        }
+
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        let expected: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .map(|(time, key)| (mock_s3::key(key), mock_s3::timestamp(time as u64 + 1)))
+            .collect();
+        assert_eq!(entries, expected);
+        // The top-level prefixes are listed, then the objects under each of them.
+        let lists = mock.requests_of("ListObjectsV2");
+        assert_eq!(lists.len(), 3);
+        assert_eq!(lists[0].query("delimiter"), Some("/"));
+        let mut prefixes: Vec<_> = lists[1..].iter().map(|x| x.query("prefix")).collect();
+        prefixes.sort();
+        assert_eq!(prefixes, vec![Some("a/"), Some("b/")]);
+    }
+
+    #[tokio::test]
+    async fn unchanged_values_only_refresh_the_metadata_of_their_objects() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "skip_unchanged_puts": true })).await;
+        for (value, time) in [("1", 1), ("1", 2)] {
+            storage
+                .put(
+                    mock_s3::key("a"),
+                    Value::from(value),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+        assert_eq!(mock.requests_of("PutObject").len(), 1);
+        let copies = mock.requests_of("CopyObject");
+        assert_eq!(copies.len(), 1);
+        assert_eq!(
+            copies[0].header("x-amz-metadata-directive"),
+            Some("REPLACE")
+        );
+        let object = mock.object("/a").unwrap();
+        assert_eq!(object.body, b"1");
+        assert_eq!(
+            object.metadata()[TIMESTAMP_METADATA_KEY],
+            mock_s3::timestamp(2).to_string()
+        );
+
+        // A changed value is uploaded.
+        storage
+            .put(mock_s3::key("a"), Value::from("2"), mock_s3::timestamp(3))
+            .await
+            .unwrap();
+        assert_eq!(mock.requests_of("PutObject").len(), 2);
+        assert_eq!(mock.object("/a").unwrap().body, b"2");
+    }
+
+    #[tokio::test]
+    async fn corrupted_segments_are_skipped_when_rebuilding_the_index() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let volume_cfg = json!({ "log_structured": { "max_batch_entries": 1 } });
+        let mut storage = mock_s3::create_storage(&mock, volume_cfg.clone()).await;
+        for (value, time) in [("1", 1), ("2", 2)] {
+            storage
+                .put(
+                    mock_s3::key("a"),
+                    Value::from(value),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+        // The last segment is written partially.
+        let segments = mock.keys();
+        assert_eq!(segments.len(), 2);
+        let body = mock.object(&segments[1]).unwrap().body;
+        mock.insert_object(&segments[1], &body[..body.len() / 2], &[]);
+
+        // The index points to the previous entry of the key.
+        let mut storage = mock_s3::create_storage(&mock, volume_cfg).await;
+        assert_eq!(storage.get_admin_status()["corrupted_log_segments"], 1);
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+        assert_eq!(
+            storage.get_all_entries().await.unwrap(),
+            vec![(mock_s3::key("a"), mock_s3::timestamp(1))]
+        );
+
+        // The rebuilt index keeps indexing the new entries.
+        storage
+            .put(mock_s3::key("a"), Value::from("3"), mock_s3::timestamp(3))
+            .await
+            .unwrap();
+        assert_eq!(mock.keys().len(), 3);
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"3");
+    }
+
+    #[tokio::test]
+    async fn indexed_entries_are_kept_beyond_truncated_listings() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a", "/b"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let storage = mock_s3::create_storage(
+            &mock,
+            json!({ "entries_index": true, "max_entries": 2, "max_entries_policy": "truncate" }),
+        )
+        .await;
+        assert_eq!(storage.get_all_entries().await.unwrap().len(), 2);
+
+        // The listing is truncated before `b`, which remains indexed.
+        mock.insert_object("/0", b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        assert_eq!(storage.get_all_entries().await.unwrap().len(), 2);
+        mock.remove_object("/0");
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(
+            entries,
+            vec![
+                (mock_s3::key("a"), mock_s3::timestamp(1)),
+                (mock_s3::key("b"), mock_s3::timestamp(1)),
+            ]
+        );
+        assert!(mock.requests_of("HeadObject").is_empty());
+    }
+
+    #[tokio::test]
+    async fn listings_beyond_max_entries_are_stopped_at_the_exceeding_page() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for i in 0..2500 {
+            mock.insert_object(
+                &format!("/k{i:04}"),
+                b"1",
+                &[(TIMESTAMP_METADATA_KEY, &timestamp)],
+            );
+        }
+
+        let storage = mock_s3::create_storage(&mock, json!({ "max_entries": 1200 })).await;
+        mock.clear_requests();
+        let err = storage.get_all_entries().await.err().unwrap();
+        assert!(err.to_string().contains("`max_entries`"), "{err}");
+        // The third page isn't listed, nor are the objects of the exceeding page requested.
+        assert_eq!(mock.requests_of("ListObjectsV2").len(), 2);
+        assert_eq!(mock.requests_of("HeadObject").len(), 1000);
+
+        let storage = mock_s3::create_storage(
+            &mock,
+            json!({ "max_entries": 1200, "max_entries_policy": "truncate" }),
+        )
+        .await;
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        assert_eq!(entries.len(), 1200);
+        assert_eq!(entries[1199].0, mock_s3::key("k1199"));
+        assert_eq!(mock.requests_of("ListObjectsV2").len(), 2);
+        assert_eq!(mock.requests_of("HeadObject").len(), 1200);
+    }
+
+    #[tokio::test]
+    async fn encodings_of_untyped_objects_are_sniffed_from_their_first_bytes() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        let objects: [(&str, &[u8]); 3] = [
+            ("/json", b"  {\"a\": 1}"),
+            ("/png", b"\x89PNG\r\n\x1a\n\x00\x00"),
+            // The opening brace lies beyond the sniffed bytes.
+            ("/far", b"         {\"a\": 1}"),
+        ];
+        for (key, body) in objects {
+            mock.insert_object(key, body, &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "content_sniffing_bytes": 8,
+                "default_encoding": "application/octet-stream",
+            }),
+        )
+        .await;
+
+        for (key, encoding) in [
+            ("json", "application/json"),
+            ("png", "image/png"),
+            ("far", "application/octet-stream"),
+        ] {
+            let replies = storage.get(mock_s3::key(key), "").await.unwrap();
+            assert_eq!(replies[0].value.encoding.to_string(), encoding, "{key}");
+        }
+    }
+
+    #[tokio::test]
+    async fn reads_are_sent_with_the_read_credentials() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "private": {
+                    "access_key": mock_s3::ACCESS_KEY,
+                    "secret_key": mock_s3::SECRET_KEY,
+                    "read_access_key": "AKIAMOCKREAD",
+                    "read_secret_key": "mock-read-secret",
+                },
+            }),
+        )
+        .await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        storage.get(mock_s3::key("a"), "").await.unwrap();
+        storage.get_all_entries().await.unwrap();
+        storage
+            .delete(mock_s3::key("a"), mock_s3::timestamp(2))
+            .await
+            .unwrap();
+
+        let access_key = |operation: &str| {
+            let requests = mock.requests_of(operation);
+            assert!(!requests.is_empty(), "{operation}");
+            let access_keys: std::collections::HashSet<_> = requests
+                .iter()
+                .map(|request| {
+                    let authorization = request.header("authorization").unwrap();
+                    let credential = authorization.split("Credential=").nth(1).unwrap();
+                    credential.split('/').next().unwrap().to_string()
+                })
+                .collect();
+            assert_eq!(access_keys.len(), 1, "{operation}");
+            access_keys.into_iter().next().unwrap()
+        };
+        for operation in ["PutObject", "DeleteObject"] {
+            assert_eq!(access_key(operation), mock_s3::ACCESS_KEY, "{operation}");
+        }
+        for operation in ["GetObject", "HeadObject", "ListObjectsV2"] {
+            assert_eq!(access_key(operation), "AKIAMOCKREAD", "{operation}");
+        }
+    }
+
+    #[tokio::test]
+    async fn operation_metrics_are_refreshed_in_the_admin_status() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "metrics_interval_ms": 50 })).await;
+        assert!(storage.get_admin_status()["operation_metrics"].is_null());
+
+        for time in [1, 2] {
+            storage
+                .put(
+                    mock_s3::key("a"),
+                    Value::from("1"),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+        wait_until(|| storage.get_admin_status()["operation_metrics"]["put"]["count"] == 2).await;
+        let metrics = &storage.get_admin_status()["operation_metrics"]["put"];
+        assert_eq!(metrics["errors"], 0);
+        for percentile in ["p50_ms", "p90_ms", "p99_ms"] {
+            assert!(metrics[percentile].is_number(), "{metrics}");
+        }
+
+        mock.fail("GetObject", 1, 403, "AccessDenied");
+        assert!(storage.get(mock_s3::key("a"), "").await.is_err());
+        storage.get(mock_s3::key("a"), "").await.unwrap();
+        wait_until(|| storage.get_admin_status()["operation_metrics"]["get"]["count"] == 2).await;
+        assert_eq!(
+            storage.get_admin_status()["operation_metrics"]["get"]["errors"],
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn accessible_buckets_are_reused_when_their_creation_is_denied() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.fail("CreateBucket", 1, 403, "AccessDenied");
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+
+        mock.fail("CreateBucket", 1, 403, "AccessDenied");
+        let result = mock_s3::start_volume(&mock, json!({}))
+            .create_storage(mock_s3::storage_config(
+                json!({ "probe_bucket_on_denied": false }),
+            ))
+            .await;
+        assert!(result.is_err());
+
+        // A missing bucket isn't accessible either.
+        let mock = MockS3::start().await;
+        mock.fail("CreateBucket", 1, 403, "AccessDenied");
+        let result = mock_s3::start_volume(&mock, json!({}))
+            .create_storage(mock_s3::storage_config(json!({})))
+            .await;
+        assert!(result.is_err());
+    }
+
+    #[tokio::test]
+    async fn keys_of_the_segments_are_compressed_by_their_shared_prefixes() {
+        // Returns the size of the segment of values sharing a long prefix, after checking that
+        // they are read back.
+        async fn segment_size(compress_keys: bool) -> usize {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let volume_cfg = json!({
+                "log_structured": {
+                    "max_batch_entries": 4,
+                    "flush_interval_ms": 60000,
+                    "compress_keys": compress_keys,
+                }
+            });
+            let mut storage = mock_s3::create_storage(&mock, volume_cfg.clone()).await;
+            for room in 1..=4 {
+                storage
+                    .put(
+                        mock_s3::key(&format!("sensors/building-1/floor-2/room-{room}")),
+                        Value::from("1"),
+                        mock_s3::timestamp(room),
+                    )
+                    .await
+                    .unwrap();
+            }
+            let keys = mock.keys();
+            assert_eq!(keys.len(), 1);
+
+            let mut storage = mock_s3::create_storage(&mock, volume_cfg).await;
+            let replies = storage
+                .get(mock_s3::key("sensors/building-1/floor-2/room-3"), "")
+                .await
+                .unwrap();
+            assert_eq!(replies[0].timestamp, mock_s3::timestamp(3));
+            assert_eq!(storage.get_all_entries().await.unwrap().len(), 4);
+            mock.object(&keys[0]).unwrap().body.len()
+        }
+
+        let compressed = segment_size(true).await;
+        let uncompressed = segment_size(false).await;
+        // The 3 last keys only store the room instead of the 30 bytes of their shared prefix.
+        assert!(
+            compressed + 3 * 25 < uncompressed,
+            "{compressed} vs {uncompressed}"
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_values_are_stored_or_deleted_according_to_the_configuration() {
+        for empty_values in ["store", "delete"] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let mut storage =
+                mock_s3::create_storage(&mock, json!({ "empty_values": empty_values })).await;
+            for (value, time) in [("1", 1), ("", 2)] {
+                storage
+                    .put(
+                        mock_s3::key("a"),
+                        Value::from(value),
+                        mock_s3::timestamp(time),
+                    )
+                    .await
+                    .unwrap();
+            }
+
+            let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+            let entries = storage.get_all_entries().await.unwrap();
+            if empty_values == "store" {
+                assert_eq!(replies.len(), 1);
+                assert!(replies[0].value.payload.contiguous().is_empty());
+                assert_eq!(entries, vec![(mock_s3::key("a"), mock_s3::timestamp(2))]);
+            } else {
+                assert!(replies.is_empty());
+                assert!(entries.is_empty());
+                assert!(mock.keys().is_empty());
+            }
+        }
+    }
+
+    #[tokio::test]
+    async fn entries_of_each_listed_page_are_collected_before_the_next_page() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for i in 0..2500 {
+            mock.insert_object(
+                &format!("/k{i:04}"),
+                b"1",
+                &[(TIMESTAMP_METADATA_KEY, &timestamp)],
+            );
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "entries_spill": {
+                    "max_memory_bytes": 16384,
+                    "dir": dir.path().to_str().unwrap(),
+                }
+            }),
+        )
+        .await;
+
+        mock.clear_requests();
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        let expected: Vec<_> = (0..2500)
+            .map(|i| (mock_s3::key(&format!("k{i:04}")), mock_s3::timestamp(1)))
+            .collect();
+        assert_eq!(entries, expected);
+        // The objects of a page are all requested before the next page is listed.
+        let requests = mock.requests();
+        let operations: Vec<_> = requests.iter().map(|x| x.operation()).collect();
+        let lists: Vec<_> = operations
+            .iter()
+            .enumerate()
+            .filter(|(_, operation)| **operation == "ListObjectsV2")
+            .map(|(index, _)| index)
+            .collect();
+        assert_eq!(lists.len(), 3);
+        for (page, index) in lists.iter().enumerate() {
+            let heads = operations[..*index]
+                .iter()
+                .filter(|operation| **operation == "HeadObject")
+                .count();
+            assert_eq!(heads, page * 1000);
+        }
+    }
+
+    #[tokio::test]
+    async fn updates_of_the_read_only_subtrees_are_rejected() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object(
+            "/reference/a",
+            b"1",
+            &[(TIMESTAMP_METADATA_KEY, &timestamp)],
+        );
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "read_only_key_exprs": ["test/reference/**"] }),
+        )
+        .await;
+
+        let result = storage
+            .put(
+                mock_s3::key("reference/b"),
+                Value::from("2"),
+                mock_s3::timestamp(2),
+            )
+            .await;
+        assert!(result.is_err());
+        let result = storage
+            .delete(mock_s3::key("reference/a"), mock_s3::timestamp(2))
+            .await;
+        assert!(result.is_err());
+        storage
+            .put(
+                mock_s3::key("data/a"),
+                Value::from("3"),
+                mock_s3::timestamp(2),
+            )
+            .await
+            .unwrap();
+        storage
+            .delete(mock_s3::key("data/a"), mock_s3::timestamp(3))
+            .await
+            .unwrap();
+        assert_eq!(mock.keys(), vec!["/reference/a".to_string()]);
+        // The read-only subtrees are still served.
+        let replies = storage.get(mock_s3::key("reference/a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+    }
+
+    #[tokio::test]
+    async fn encodings_round_trip_through_the_configured_content_types() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({ "content_types": { "application/x-example": "application/vnd.example+json" } }),
+        )
+        .await;
+        for (key, encoding) in [("a", "application/x-example"), ("b", "x-unknown/thing")] {
+            let value = Value::from("{}").encoding(Encoding::try_from(encoding).unwrap());
+            storage
+                .put(mock_s3::key(key), value, mock_s3::timestamp(1))
+                .await
+                .unwrap();
+            let replies = storage.get(mock_s3::key(key), "").await.unwrap();
+            assert_eq!(replies[0].value.encoding.to_string(), encoding);
+        }
+        let object = mock.object("/a").unwrap();
+        assert_eq!(
+            object.headers.get("content-type").unwrap(),
+            "application/vnd.example+json"
+        );
+
+        // The objects written by other systems are returned with the encoding of their type.
+        let timestamp = mock_s3::timestamp(1).to_string();
+        mock.insert_object("/c", b"{}", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.set_header("/c", "content-type", "application/vnd.example+json");
+        let replies = storage.get(mock_s3::key("c"), "").await.unwrap();
+        assert_eq!(
+            replies[0].value.encoding.to_string(),
+            "application/x-example"
+        );
+    }
+
+    #[tokio::test]
+    async fn custom_retryable_errors_are_retried() {
+        // Returns whether the creation of the bucket succeeds despite 2 failures with a
+        // nonstandard error code, along with the amount of creation requests.
+        async fn create_bucket(volume_cfg: serde_json::Value) -> (bool, usize) {
+            let mock = MockS3::start().await;
+            mock.fail("CreateBucket", 2, 409, "XMinioServerNotInitialized");
+            let mut cfg = json!({ "bucket_retry_backoff_ms": 1 });
+            if let (Some(cfg), serde_json::Value::Object(volume_cfg)) =
+                (cfg.as_object_mut(), volume_cfg)
+            {
+                cfg.extend(volume_cfg);
+            }
+            let result = mock_s3::start_volume(&mock, json!({}))
+                .create_storage(mock_s3::storage_config(cfg))
+                .await;
+            (result.is_ok(), mock.requests_of("CreateBucket").len())
+        }
+
+        assert_eq!(create_bucket(json!({})).await, (false, 1));
+        assert_eq!(
+            create_bucket(json!({ "retryable_errors": ["XMinioServerNotInitialized"] })).await,
+            (true, 3)
+        );
+        // The non-retryable patterns take precedence.
+        assert_eq!(
+            create_bucket(json!({
+                "retryable_errors": ["XMinioServerNotInitialized"],
+                "non_retryable_errors": ["409"],
+            }))
+            .await,
+            (false, 1)
+        );
+    }
+
+    #[tokio::test]
+    async fn hashed_key_prefixes_are_stripped_from_the_entries() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "key_hash_prefix_length": 4 })).await;
+        let keys = ["a/b", "sensor/1", "sensor/2"];
+        for (time, key) in keys.iter().enumerate() {
+            storage
+                .put(
+                    mock_s3::key(key),
+                    Value::from("1"),
+                    mock_s3::timestamp(time as u64 + 1),
+                )
+                .await
+                .unwrap();
+        }
+        let object_keys = mock.keys();
+        assert_eq!(object_keys.len(), 3);
+        for object_key in &object_keys {
+            let (segment, key) = object_key.split_once('/').unwrap();
+            assert!(
+                segment.chars().all(|x| x.is_ascii_hexdigit()),
+                "{object_key}"
+            );
+            assert_eq!(segment.len(), 4, "{object_key}");
+            assert!(keys.contains(&key), "{object_key}");
+        }
+
+        let replies = storage.get(mock_s3::key("sensor/2"), "").await.unwrap();
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(3));
+        let mut entries = storage.get_all_entries().await.unwrap();
+        entries.sort();
+        let expected: Vec<_> = keys
+            .iter()
+            .enumerate()
+            .map(|(time, key)| (mock_s3::key(key), mock_s3::timestamp(time as u64 + 1)))
+            .collect();
+        assert_eq!(entries, expected);
+    }
+
+    #[tokio::test]
+    async fn objects_without_timestamp_are_stamped_from_their_last_modification() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        mock.insert_object("/a", b"1", &[]);
+        let last_modified = mock.object("/a").unwrap().last_modified;
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        assert!(storage.get(mock_s3::key("a"), "").await.is_err());
+
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "missing_timestamps": "last_modified" })).await;
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"1");
+        assert_eq!(
+            *replies[0].timestamp.get_time(),
+            uhlc::NTP64::from(Duration::from_secs(last_modified))
+        );
+    }
+
+    #[tokio::test]
+    async fn empty_and_prefix_only_keys_are_skipped_or_mapped_to_the_none_key() {
+        let full_key = json!({ "legacy_layout": "full_key" });
+        for (mut volume_cfg, empty_key, object_key) in [
+            (json!({}), "/", "/a"),
+            (full_key.clone(), "test", "test/a"),
+            (full_key, "test/", "test/a"),
+        ] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let timestamp = mock_s3::timestamp(1).to_string();
+            for key in [empty_key, object_key] {
+                mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+            }
+
+            let storage = mock_s3::create_storage(&mock, volume_cfg.clone()).await;
+            assert_eq!(
+                storage.get_all_entries().await.unwrap(),
+                vec![(mock_s3::key("a"), mock_s3::timestamp(1))],
+                "{empty_key}"
+            );
+
+            volume_cfg["empty_keys"] = json!("none_key");
+            let storage = mock_s3::create_storage(&mock, volume_cfg).await;
+            let mut entries = storage.get_all_entries().await.unwrap();
+            entries.sort();
+            assert_eq!(
+                entries,
+                vec![
+                    (None, mock_s3::timestamp(1)),
+                    (mock_s3::key("a"), mock_s3::timestamp(1)),
+                ],
+                "{empty_key}"
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn format_versions_are_written_and_checked() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        storage
+            .put(mock_s3::key("a"), Value::from("1"), mock_s3::timestamp(1))
+            .await
+            .unwrap();
+        assert_eq!(
+            mock.object("/a")
+                .unwrap()
+                .metadata()
+                .get(FORMAT_VERSION_METADATA_KEY),
+            Some(&FORMAT_VERSION.to_string())
+        );
+        assert_eq!(storage.get(mock_s3::key("a"), "").await.unwrap().len(), 1);
+
+        // The objects without format version are read as of the first one, and those of an
+        // unknown version are rejected.
+        let timestamp = mock_s3::timestamp(2).to_string();
+        mock.insert_object("/b", b"2", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        assert_eq!(storage.get(mock_s3::key("b"), "").await.unwrap().len(), 1);
+        mock.insert_object(
+            "/c",
+            b"3",
+            &[
+                (TIMESTAMP_METADATA_KEY, &timestamp),
+                (FORMAT_VERSION_METADATA_KEY, "2"),
+            ],
+        );
+        let error = storage.get(mock_s3::key("c"), "").await.err().unwrap();
+        assert!(error.to_string().contains("format version 2"), "{error}");
+
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "write_format_version": false })).await;
+        storage
+            .put(mock_s3::key("d"), Value::from("4"), mock_s3::timestamp(3))
+            .await
+            .unwrap();
+        let metadata = mock.object("/d").unwrap().metadata();
+        assert!(!metadata.contains_key(FORMAT_VERSION_METADATA_KEY));
+    }
+
+    #[tokio::test]
+    async fn latest_versions_of_the_timestamp_suffixed_keys_are_resolved() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "timestamp_key_suffix": true })).await;
+        // The times wouldn't sort lexicographically if they weren't encoded with a fixed width.
+        let times = [9, 10, 300];
+        for time in times {
+            storage
+                .put(
+                    mock_s3::key("a"),
+                    Value::from(time.to_string()),
+                    mock_s3::timestamp(time),
+                )
+                .await
+                .unwrap();
+        }
+        let versions: Vec<_> = times
+            .iter()
+            .map(|time| utils::timestamp_suffixed_key("/a", &mock_s3::timestamp(*time)))
+            .collect();
+        assert_eq!(mock.keys(), versions);
+
+        let replies = storage.get(mock_s3::key("a"), "").await.unwrap();
+        assert_eq!(replies.len(), 1);
+        assert_eq!(replies[0].timestamp, mock_s3::timestamp(300));
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"300");
+
+        storage
+            .delete(mock_s3::key("a"), mock_s3::timestamp(400))
+            .await
+            .unwrap();
+        assert!(mock.keys().is_empty());
+        assert!(storage.get(mock_s3::key("a"), "").await.unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn cancelled_multipart_uploads_leave_no_parts_behind() {
+        for abort_on_error in [true, false] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let client = mock_s3::create_client(
+                &mock,
+                json!({
+                    "multipart": {
+                        "threshold": 16,
+                        "part_size": 8,
+                        "min_part_size": 8,
+                        "abort_on_error": abort_on_error,
+                    },
+                }),
+            )
+            .await;
+            let delay = Duration::from_millis(100);
+            mock.set_delay(Some(delay));
+
+            // The put is dropped while its parts are being uploaded.
+            let value: Vec<u8> = (0..40).collect();
+            let put = client.put_object("/a".to_string(), Value::from(value), None);
+            tokio::select! {
+                _ = put => panic!("The upload completed before being cancelled."),
+                _ = wait_until(|| !mock.requests_of("UploadPart").is_empty()) => (),
+            }
+            if abort_on_error {
+                wait_until(|| mock.uploads().is_empty()).await;
+                assert_eq!(mock.requests_of("AbortMultipartUpload").len(), 1);
+            } else {
+                tokio::time::sleep(delay * 3).await;
+                assert_eq!(mock.uploads().len(), 1);
+                assert!(mock.requests_of("AbortMultipartUpload").is_empty());
+            }
+            assert!(mock.keys().is_empty());
+        }
+    }
+
+    #[tokio::test]
+    async fn composed_and_decomposed_keys_map_to_the_same_object() {
+        let composed = "sensors/caf\u{e9}";
+        let decomposed = "sensors/cafe\u{301}";
+        for (normalization, object_key) in [("nfc", composed), ("nfd", decomposed)] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let mut storage =
+                mock_s3::create_storage(&mock, json!({ "key_normalization": normalization })).await;
+            storage
+                .put(
+                    mock_s3::key(decomposed),
+                    Value::from("1"),
+                    mock_s3::timestamp(1),
+                )
+                .await
+                .unwrap();
+            storage
+                .put(
+                    mock_s3::key(composed),
+                    Value::from("2"),
+                    mock_s3::timestamp(2),
+                )
+                .await
+                .unwrap();
+            assert_eq!(
+                mock.keys(),
+                vec![format!("/{object_key}")],
+                "{normalization}"
+            );
+            for key in [composed, decomposed] {
+                let replies = storage.get(mock_s3::key(key), "").await.unwrap();
+                assert_eq!(replies.len(), 1, "{normalization}");
+                assert_eq!(
+                    replies[0].timestamp,
+                    mock_s3::timestamp(2),
+                    "{normalization}"
+                );
+            }
+        }
+
+        // Without normalization, the keys are distinct objects.
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        for key in [composed, decomposed] {
+            storage
+                .put(mock_s3::key(key), Value::from("1"), mock_s3::timestamp(1))
+                .await
+                .unwrap();
+        }
+        assert_eq!(mock.keys().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn corrupted_bodies_are_rejected_against_their_checksums() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        // The CRC32 checksums of `1` and `2`, the former not matching the corrupted body.
+        mock.insert_object("/a", b"corrupted", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.set_header("/a", "x-amz-checksum-crc32", "g9zvtw==");
+        mock.insert_object("/b", b"2", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        mock.set_header("/b", "x-amz-checksum-crc32", "GtW+DQ==");
+
+        let mut storage = mock_s3::create_storage(&mock, json!({})).await;
+        assert_eq!(storage.get(mock_s3::key("a"), "").await.unwrap().len(), 1);
+
+        let mut storage =
+            mock_s3::create_storage(&mock, json!({ "validate_response_checksum": true })).await;
+        assert!(storage.get(mock_s3::key("a"), "").await.is_err());
+        let replies = storage.get(mock_s3::key("b"), "").await.unwrap();
+        assert_eq!(replies[0].value.payload.contiguous().to_vec(), b"2");
+        let request = mock.requests_of("GetObject").pop().unwrap();
+        assert_eq!(request.header("x-amz-checksum-mode"), Some("ENABLED"));
+    }
+
+    #[tokio::test]
+    async fn large_values_are_uploaded_from_temporary_files() {
+        let dir = tempfile::tempdir().unwrap();
+        let spilled_files = || std::fs::read_dir(dir.path()).unwrap().count();
+        let spill = json!({ "threshold": 16, "dir": dir.path().to_str().unwrap() });
+        let multipart = json!({ "threshold": 16, "part_size": 8, "min_part_size": 8 });
+        for volume_cfg in [
+            json!({ "upload_spill": spill }),
+            json!({ "upload_spill": spill, "multipart": multipart }),
+        ] {
+            let mock = MockS3::start().await;
+            mock.create_bucket(false);
+            let client = mock_s3::create_client(&mock, volume_cfg).await;
+            mock.set_delay(Some(Duration::from_millis(100)));
+
+            // The temporary file exists while the value is being uploaded.
+            let value: Vec<u8> = (0..40).collect();
+            let (result, _) = tokio::join!(
+                client.put_object("/a".to_string(), Value::from(value.clone()), None),
+                wait_until(|| spilled_files() == 1),
+            );
+            result.unwrap();
+            assert_eq!(spilled_files(), 0);
+            assert_eq!(mock.object("/a").unwrap().body, value);
+        }
+    }
+
+    #[tokio::test]
+    async fn objects_deleted_since_the_listing_are_skipped() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a", "/b"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let storage =
+            mock_s3::create_storage(&mock, json!({ "listing_consistency": "snapshot" })).await;
+        // The object `b` is deleted once listed, before its HEAD request.
+        mock.inject(1, 404, vec![], vec![], |request| {
+            request.operation() == "HeadObject" && request.key.as_deref() == Some("/b")
+        });
+
+        assert_eq!(
+            storage.get_all_entries().await.unwrap(),
+            vec![(mock_s3::key("a"), mock_s3::timestamp(1))]
+        );
+        assert_eq!(mock.requests_of("HeadObject").len(), 2);
+    }
+
+    #[tokio::test]
+    async fn export_downloads_the_objects_matching_its_key_expression() {
+        let mock = MockS3::start().await;
+        mock.create_bucket(false);
+        let timestamp = mock_s3::timestamp(1).to_string();
+        for key in ["/a/b", "/c"] {
+            mock.insert_object(key, b"1", &[(TIMESTAMP_METADATA_KEY, &timestamp)]);
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let mut storage = mock_s3::create_storage(
+            &mock,
+            json!({
+                "allow_maintenance_queries": true,
+                "export": { "dir": dir.path().to_str().unwrap(), "key_expr": "test/a/**" },
+            }),
+        )
+        .await;
+        // Nothing is exported until the export is triggered.
+        assert_eq!(std::fs::read_dir(dir.path()).unwrap().count(), 0);
+
+        for (exported, skipped) in [(1, 0), (0, 1)] {
+            storage
+                .get(mock_s3::key("a"), "s3_operation=export")
+                .await
+                .unwrap();
+            assert_eq!(
+                operation_report(&*storage, "export").await,
+                json!({
+                    "state": "completed",
+                    "exported_objects": exported,
+                    "skipped_objects": skipped,
+                })
+            );
+        }
+        assert_eq!(std::fs::read(dir.path().join("a/b")).unwrap(), b"1");
+        assert!(!dir.path().join("c").exists());
+    }
+}
@@ -0,0 +1,434 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+use base64::engine::general_purpose;
+use base64::Engine;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::str::FromStr;
+use std::sync::{Arc, Mutex};
+use std::time::{SystemTime, UNIX_EPOCH};
+use zenoh::prelude::Encoding;
+use zenoh::time::Timestamp;
+use zenoh::value::Value;
+use zenoh::Result as ZResult;
+use zenoh_backend_traits::StorageInsertionResult;
+use zenoh_buffers::buffer::SplitBuffer;
+use zenoh_core::zerror;
+
+use crate::client::S3Client;
+use crate::config::LogStructuredConfig;
+use crate::value_with_encoding;
+
+/// Store of the log-structured mode: the puts and deletes are buffered, then flushed in batches
+/// as immutable segment objects, named after the time of their flush so that they are listed in
+/// order.
+///
+/// The latest entry of each key is tracked by an in-memory index pointing to the segment holding
+/// it, which is rebuilt from the segments upon the creation of the storage (see [LogStore::load]).
+/// The corrupted segments are skipped by the rebuild, hence never serve their entries.
+pub(crate) struct LogStore {
+    client: Arc<S3Client>,
+    config: LogStructuredConfig,
+    segment_prefix: String,
+    default_encoding: Option<Encoding>,
+    state: Mutex<LogState>,
+    // Serializes the flushes, so that the segments are written in order.
+    flush_lock: tokio::sync::Mutex<()>,
+}
+
+#[derive(Default)]
+struct LogState {
+    // Latest buffered entry of each key.
+    buffer: HashMap<String, BufferedEntry>,
+    buffered_bytes: usize,
+    index: HashMap<String, IndexEntry>,
+    // Sequence number of the last flushed segment.
+    sequence: u128,
+    // Amount of segments skipped upon the rebuild of the index as they couldn't be parsed.
+    corrupted_segments: usize,
+}
+
+struct BufferedEntry {
+    timestamp: Timestamp,
+    // None for a delete.
+    value: Option<Value>,
+}
+
+struct IndexEntry {
+    timestamp: Timestamp,
+    // Key of the segment holding the value, None for a delete.
+    segment: Option<Arc<str>>,
+}
+
+/// Entry of a segment, as serialized in JSON.
+#[derive(Serialize, Deserialize)]
+struct SegmentEntry {
+    // Length of the prefix the key shares with the key of the previous entry, omitted from the
+    // serialized key when the keys are compressed.
+    #[serde(default, skip_serializing_if = "is_zero")]
+    shared: usize,
+    key: String,
+    timestamp: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    encoding: Option<String>,
+    // Base64-encoded payload, None for a delete.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    payload: Option<String>,
+}
+
+impl BufferedEntry {
+    fn size(&self, key: &str) -> usize {
+        key.len() + self.value.as_ref().map_or(0, |value| value.payload.len())
+    }
+}
+
+impl LogState {
+    fn latest_timestamp(&self, key: &str) -> Option<&Timestamp> {
+        self.buffer
+            .get(key)
+            .map(|entry| &entry.timestamp)
+            .or_else(|| self.index.get(key).map(|entry| &entry.timestamp))
+    }
+
+    /// Indexes the entry of the [key] held by the [segment], unless a more recent one is indexed.
+    fn index(&mut self, key: String, timestamp: Timestamp, segment: Option<Arc<str>>) {
+        match self.index.get(&key) {
+            Some(indexed) if indexed.timestamp >= timestamp => {}
+            _ => {
+                self.index.insert(key, IndexEntry { timestamp, segment });
+            }
+        }
+    }
+}
+
+impl LogStore {
+    /// Creates a new empty instance of [LogStore] whose segments are stored under the
+    /// [segment_prefix].
+    pub fn new(
+        client: Arc<S3Client>,
+        config: LogStructuredConfig,
+        segment_prefix: String,
+        default_encoding: Option<Encoding>,
+    ) -> Self {
+        LogStore {
+            client,
+            config,
+            segment_prefix,
+            default_encoding,
+            state: Mutex::new(LogState::default()),
+            flush_lock: tokio::sync::Mutex::new(()),
+        }
+    }
+
+    /// Returns the amount of segments skipped upon the rebuild of the index as they were corrupted.
+    pub fn corrupted_segments(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.corrupted_segments
+    }
+
+    /// Returns the interval at which the buffer must be flushed.
+    pub fn flush_interval(&self) -> std::time::Duration {
+        self.config.flush_interval
+    }
+
+    /// Rebuilds the index from the segments stored in the bucket, returning the amount of
+    /// segments read.
+    ///
+    /// A segment which can't be parsed (e.g. written partially by a faulty gateway) is skipped
+    /// rather than failing the storage, and its entries are lost: the index then points to the
+    /// previous entries of its keys, if any. The failures to retrieve a segment still fail the
+    /// rebuild, as they may be transient.
+    pub async fn load(&self) -> ZResult<usize> {
+        let mut segments = vec![];
+        let mut continuation_token = None;
+        loop {
+            let (objects, next_continuation_token) = self
+                .client
+                .list_objects_page(Some(&self.segment_prefix), continuation_token)
+                .await?;
+            segments.extend(
+                objects
+                    .iter()
+                    .filter_map(|object| object.key().map(Arc::from)),
+            );
+            match next_continuation_token {
+                Some(token) => continuation_token = Some(token),
+                None => break,
+            }
+        }
+        for segment in &segments {
+            let object = self
+                .client
+                .get_object(segment)
+                .await?
+                .ok_or_else(|| zerror!("Segment '{segment}' not found."))?;
+            let entries = parse_segment(segment, &object.body).and_then(|entries| {
+                entries
+                    .into_iter()
+                    .map(|entry| Ok((parse_timestamp(&entry.key, &entry.timestamp)?, entry)))
+                    .collect::<ZResult<Vec<_>>>()
+            });
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            match entries {
+                Ok(entries) => {
+                    for (timestamp, entry) in entries {
+                        let segment = entry.payload.as_ref().map(|_| segment.clone());
+                        state.index(entry.key, timestamp, segment);
+                    }
+                }
+                Err(e) => {
+                    tracing::error!("{e}. Skipping the segment, whose entries are lost.");
+                    state.corrupted_segments += 1;
+                }
+            }
+            if let Some(sequence) = segment
+                .strip_prefix(self.segment_prefix.as_str())
+                .and_then(|sequence| sequence.parse::<u128>().ok())
+            {
+                state.sequence = state.sequence.max(sequence);
+            }
+        }
+        Ok(segments.len())
+    }
+
+    /// Buffers the [value] of the [key], or its deletion if None, flushing the buffer once it
+    /// exceeds the configured size. The entry is ignored if a more recent one exists.
+    pub async fn append(
+        &self,
+        key: String,
+        timestamp: Timestamp,
+        value: Option<Value>,
+    ) -> ZResult<StorageInsertionResult> {
+        let is_delete = value.is_none();
+        let must_flush = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if state
+                .latest_timestamp(&key)
+                .map_or(false, |latest| *latest > timestamp)
+            {
+                return Ok(StorageInsertionResult::Outdated);
+            }
+            let entry = BufferedEntry { timestamp, value };
+            state.buffered_bytes += entry.size(&key);
+            if let Some(replaced) = state.buffer.insert(key.to_owned(), entry) {
+                state.buffered_bytes -= replaced.size(&key);
+            }
+            state.buffer.len() >= self.config.max_batch_entries
+                || state.buffered_bytes >= self.config.max_batch_bytes
+        };
+        if must_flush {
+            self.flush().await?;
+        }
+        Ok(if is_delete {
+            StorageInsertionResult::Deleted
+        } else {
+            StorageInsertionResult::Inserted
+        })
+    }
+
+    /// Writes the buffered entries as a new segment and indexes them, returning the amount of
+    /// flushed entries. Upon failure, the entries are buffered again.
+    pub async fn flush(&self) -> ZResult<usize> {
+        let _flush_guard = self.flush_lock.lock().await;
+        let (entries, segment) = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if state.buffer.is_empty() {
+                return Ok(0);
+            }
+            let entries: Vec<(String, BufferedEntry)> = state.buffer.drain().collect();
+            state.buffered_bytes = 0;
+            // The segments are named after the time of their flush, strictly increasing.
+            let now = SystemTime::now()
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |now| now.as_nanos());
+            state.sequence = now.max(state.sequence + 1);
+            let segment: Arc<str> =
+                Arc::from(format!("{}{:039}", self.segment_prefix, state.sequence));
+            (entries, segment)
+        };
+
+        let mut segment_entries: Vec<SegmentEntry> = entries
+            .iter()
+            .map(|(key, entry)| SegmentEntry {
+                shared: 0,
+                key: key.to_owned(),
+                timestamp: entry.timestamp.to_string(),
+                encoding: entry.value.as_ref().map(|value| value.encoding.to_string()),
+                payload: entry
+                    .value
+                    .as_ref()
+                    .map(|value| general_purpose::STANDARD.encode(value.payload.contiguous())),
+            })
+            .collect();
+        if self.config.compress_keys {
+            compress_keys(&mut segment_entries);
+        }
+        let result = match serde_json::to_vec(&segment_entries) {
+            Ok(body) => {
+                self.client
+                    .put_object(segment.to_string(), Value::from(body), None)
+                    .await
+            }
+            Err(e) => Err(zerror!("Couldn't serialize segment '{segment}': {e}").into()),
+        };
+
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        match result {
+            Ok(()) => {
+                let flushed = entries.len();
+                for (key, entry) in entries {
+                    let segment = entry.value.as_ref().map(|_| segment.clone());
+                    state.index(key, entry.timestamp, segment);
+                }
+                Ok(flushed)
+            }
+            Err(e) => {
+                // The entries superseded meanwhile by more recent ones are dropped.
+                for (key, entry) in entries {
+                    if !state
+                        .buffer
+                        .get(&key)
+                        .map_or(false, |buffered| buffered.timestamp >= entry.timestamp)
+                    {
+                        state.buffered_bytes += entry.size(&key);
+                        if let Some(replaced) = state.buffer.insert(key.to_owned(), entry) {
+                            state.buffered_bytes -= replaced.size(&key);
+                        }
+                    }
+                }
+                Err(zerror!("Couldn't flush segment '{segment}': {e}").into())
+            }
+        }
+    }
+
+    /// Retrieves the latest value of the [key], from the buffer or from the segment holding it,
+    /// returning None if there is no value or if it was deleted.
+    pub async fn get(&self, key: &str) -> ZResult<Option<(Timestamp, Value)>> {
+        let (timestamp, segment) = {
+            let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            if let Some(entry) = state.buffer.get(key) {
+                return Ok(entry
+                    .value
+                    .as_ref()
+                    .map(|value| (entry.timestamp, value.clone())));
+            }
+            match state.index.get(key) {
+                Some(IndexEntry {
+                    timestamp,
+                    segment: Some(segment),
+                }) => (*timestamp, segment.clone()),
+                _ => return Ok(None),
+            }
+        };
+        let timestamp_str = timestamp.to_string();
+        let entry = self
+            .read_segment(&segment)
+            .await?
+            .into_iter()
+            .find(|entry| entry.key == key && entry.timestamp == timestamp_str)
+            .ok_or_else(|| zerror!("Key '{key}' not found in segment '{segment}'."))?;
+        let payload = general_purpose::STANDARD
+            .decode(entry.payload.unwrap_or_default())
+            .map_err(|e| zerror!("Invalid payload of '{key}' in segment '{segment}': {e}"))?;
+        Ok(Some((
+            timestamp,
+            value_with_encoding(payload, entry.encoding, self.default_encoding.as_ref()),
+        )))
+    }
+
+    /// Returns the keys which currently have a value, with the timestamp of their value.
+    pub fn entries(&self) -> Vec<(String, Timestamp)> {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let indexed = state
+            .index
+            .iter()
+            .filter(|(key, _)| !state.buffer.contains_key(*key))
+            .filter(|(_, entry)| entry.segment.is_some())
+            .map(|(key, entry)| (key.to_owned(), entry.timestamp));
+        let buffered = state
+            .buffer
+            .iter()
+            .filter(|(_, entry)| entry.value.is_some())
+            .map(|(key, entry)| (key.to_owned(), entry.timestamp));
+        indexed.chain(buffered).collect()
+    }
+
+    async fn read_segment(&self, segment: &str) -> ZResult<Vec<SegmentEntry>> {
+        let object = self
+            .client
+            .get_object(segment)
+            .await?
+            .ok_or_else(|| zerror!("Segment '{segment}' not found."))?;
+        parse_segment(segment, &object.body)
+    }
+}
+
+fn parse_segment(segment: &str, body: &[u8]) -> ZResult<Vec<SegmentEntry>> {
+    let mut entries: Vec<SegmentEntry> =
+        serde_json::from_slice(body).map_err(|e| zerror!("Invalid segment '{segment}': {e}"))?;
+    decompress_keys(segment, &mut entries)?;
+    Ok(entries)
+}
+
+/// Sorts the [entries] by key and strips from each key the prefix it shares with the previous
+/// one, which only stays in the `shared` length of the entry.
+fn compress_keys(entries: &mut [SegmentEntry]) {
+    entries.sort_unstable_by(|a, b| a.key.cmp(&b.key));
+    let mut previous = String::new();
+    for entry in entries.iter_mut() {
+        let mut shared = previous
+            .bytes()
+            .zip(entry.key.bytes())
+            .take_while(|(a, b)| a == b)
+            .count();
+        while !entry.key.is_char_boundary(shared) {
+            shared -= 1;
+        }
+        let key = std::mem::take(&mut entry.key);
+        entry.shared = shared;
+        entry.key = key[shared..].to_string();
+        previous = key;
+    }
+}
+
+/// Restores the complete keys of the [entries] of the [segment], prepending to each key the
+/// prefix it shares with the previous one. The keys of uncompressed segments are left unchanged.
+fn decompress_keys(segment: &str, entries: &mut [SegmentEntry]) -> ZResult<()> {
+    let mut previous = String::new();
+    for entry in entries.iter_mut() {
+        if entry.shared > 0 {
+            let prefix = previous.get(..entry.shared).ok_or_else(|| {
+                zerror!(
+                    "Invalid segment '{segment}': key '{}' shares {} bytes with the previous key \
+                    '{previous}'",
+                    entry.key,
+                    entry.shared
+                )
+            })?;
+            entry.key.insert_str(0, prefix);
+            entry.shared = 0;
+        }
+        previous.clone_from(&entry.key);
+    }
+    Ok(())
+}
+
+fn is_zero(value: &usize) -> bool {
+    *value == 0
+}
+
+fn parse_timestamp(key: &str, timestamp: &str) -> ZResult<Timestamp> {
+    Timestamp::from_str(timestamp)
+        .map_err(|e| zerror!("Invalid timestamp of '{key}' in segment: {e:?}").into())
+}
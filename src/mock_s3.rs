@@ -0,0 +1,1423 @@
+//
+// Copyright (c) 2022 ZettaScale Technology
+//
+// This program and the accompanying materials are made available under the
+// terms of the Eclipse Public License 2.0 which is available at
+// http://www.eclipse.org/legal/epl-2.0, or the Apache License, Version 2.0
+// which is available at https://www.apache.org/licenses/LICENSE-2.0.
+//
+// SPDX-License-Identifier: EPL-2.0 OR Apache-2.0
+//
+// Contributors:
+//   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
+//
+
+//! In-memory S3 server answering the requests of the tests over HTTP, with the path-style
+//! addressing used by the SDK for the custom endpoints. It records the requests it receives and
+//! answers the matching ones with injected responses, to simulate the failures.
+
+use hyper::server::conn::AddrStream;
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Response, Server};
+use serde_json::json;
+use sha2::{Digest, Sha256};
+use std::collections::{BTreeMap, HashMap};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use zenoh::prelude::*;
+use zenoh_backend_traits::config::{StorageConfig, VolumeConfig};
+use zenoh_backend_traits::{Storage, Volume};
+use zenoh_plugin_trait::Plugin;
+
+use crate::client::S3Client;
+use crate::config::{ConnectionConfig, S3Config};
+use crate::S3Backend;
+
+pub(crate) const BUCKET: &str = "zenoh-test-bucket";
+pub(crate) const ACCESS_KEY: &str = "AKIAMOCKWRITE";
+pub(crate) const SECRET_KEY: &str = "mock-write-secret";
+
+/// Request received by the server.
+#[derive(Clone, Debug)]
+pub(crate) struct Request {
+    /// Port of the server which received the request, to tell the endpoints apart.
+    pub port: u16,
+    /// Address of the client, to tell its connections apart.
+    pub peer: SocketAddr,
+    pub method: Method,
+    pub path: String,
+    pub bucket: String,
+    pub key: Option<String>,
+    pub query: Vec<(String, String)>,
+    pub headers: HashMap<String, String>,
+    pub body: Vec<u8>,
+}
+
+impl Request {
+    pub fn has_query(&self, name: &str) -> bool {
+        self.query.iter().any(|(key, _)| key == name)
+    }
+
+    pub fn query(&self, name: &str) -> Option<&str> {
+        self.query
+            .iter()
+            .find_map(|(key, value)| (key == name).then_some(value.as_str()))
+    }
+
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(name).map(|x| x.as_str())
+    }
+
+    /// Name of the S3 operation of the request, as sent by the SDK in the `x-id` parameter or
+    /// deduced from the other parameters.
+    pub fn operation(&self) -> &str {
+        if let Some(operation) = self.query("x-id") {
+            return operation;
+        }
+        match (&self.method, self.key.is_some()) {
+            (&Method::GET, false) if self.has_query("location") => "GetBucketLocation",
+            (&Method::GET, false) if self.has_query("versions") => "ListObjectVersions",
+            (&Method::GET, false) if self.has_query("uploads") => "ListMultipartUploads",
+            (&Method::GET, false) if self.has_query("tagging") => "GetBucketTagging",
+            (&Method::GET, false) => "ListObjectsV2",
+            (&Method::PUT, false) if self.has_query("tagging") => "PutBucketTagging",
+            (&Method::PUT, false) => "CreateBucket",
+            (&Method::HEAD, false) => "HeadBucket",
+            (&Method::DELETE, false) => "DeleteBucket",
+            (&Method::POST, false) => "DeleteObjects",
+            (&Method::HEAD, true) => "HeadObject",
+            (&Method::GET, true) if self.has_query("attributes") => "GetObjectAttributes",
+            (&Method::GET, true) => "GetObject",
+            (&Method::PUT, true) if self.has_query("partNumber") => {
+                match self.headers.contains_key("x-amz-copy-source") {
+                    true => "UploadPartCopy",
+                    false => "UploadPart",
+                }
+            }
+            (&Method::PUT, true) if self.headers.contains_key("x-amz-copy-source") => "CopyObject",
+            (&Method::PUT, true) => "PutObject",
+            (&Method::POST, true) if self.has_query("uploads") => "CreateMultipartUpload",
+            (&Method::POST, true) if self.has_query("select") => "SelectObjectContent",
+            (&Method::POST, true) if self.has_query("restore") => "RestoreObject",
+            (&Method::POST, true) => "CompleteMultipartUpload",
+            (&Method::DELETE, true) if self.has_query("uploadId") => "AbortMultipartUpload",
+            (&Method::DELETE, true) => "DeleteObject",
+            _ => "Unknown",
+        }
+    }
+}
+
+/// Version of an object stored by the server.
+#[derive(Clone, Debug)]
+pub(crate) struct StoredObject {
+    pub version_id: String,
+    pub body: Vec<u8>,
+    /// The metadata (`x-amz-meta-*`), content and encryption headers the object was stored with.
+    pub headers: BTreeMap<String, String>,
+    pub last_modified: u64,
+    pub e_tag: String,
+    pub delete_marker: bool,
+}
+
+impl StoredObject {
+    /// Returns the user metadata of the object, without the `x-amz-meta-` prefix.
+    pub fn metadata(&self) -> HashMap<String, String> {
+        self.headers
+            .iter()
+            .filter_map(|(name, value)| {
+                name.strip_prefix("x-amz-meta-")
+                    .map(|name| (name.to_string(), value.to_owned()))
+            })
+            .collect()
+    }
+}
+
+#[derive(Clone, Debug)]
+pub(crate) struct Upload {
+    pub key: String,
+    pub initiated: u64,
+    pub headers: BTreeMap<String, String>,
+    pub parts: BTreeMap<u32, Vec<u8>>,
+}
+
+#[derive(Default)]
+pub(crate) struct Bucket {
+    pub versioning: bool,
+    pub region: Option<String>,
+    /// The versions of each key, the latest last.
+    pub objects: BTreeMap<String, Vec<StoredObject>>,
+    pub uploads: BTreeMap<String, Upload>,
+    pub tagging: Option<String>,
+}
+
+/// Response injected in place of the answer of the server to the matching requests.
+pub(crate) struct Injection {
+    matches: Box<dyn Fn(&Request) -> bool + Send>,
+    remaining: usize,
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+#[derive(Default)]
+struct State {
+    buckets: BTreeMap<String, Bucket>,
+    requests: Vec<Request>,
+    injections: Vec<Injection>,
+    delay: Option<Duration>,
+    /// The amount of requests of each operation being answered, and its maximum so far.
+    in_flight: HashMap<String, (usize, usize)>,
+    next_id: u64,
+}
+
+/// Handle of a running server, whose state may be shared by several endpoints.
+#[derive(Clone)]
+pub(crate) struct MockS3 {
+    pub url: String,
+    pub port: u16,
+    state: Arc<Mutex<State>>,
+}
+
+impl MockS3 {
+    /// Starts a server on a free local port.
+    pub async fn start() -> MockS3 {
+        MockS3::serve(Arc::new(Mutex::new(State::default()))).await
+    }
+
+    /// Starts another endpoint serving the same buckets as this server.
+    pub async fn start_sharing(&self) -> MockS3 {
+        MockS3::serve(self.state.clone()).await
+    }
+
+    async fn serve(state: Arc<Mutex<State>>) -> MockS3 {
+        let listener = std::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+            .expect("Couldn't bind the mock server");
+        let port = listener.local_addr().unwrap().port();
+        let c_state = state.clone();
+        let make_service = make_service_fn(move |connection: &AddrStream| {
+            let state = c_state.clone();
+            let peer = connection.remote_addr();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let state = state.clone();
+                    async move { Ok::<_, Infallible>(handle(state, port, peer, request).await) }
+                }))
+            }
+        });
+        let server = Server::from_tcp(listener).unwrap().serve(make_service);
+        tokio::task::spawn(server);
+        MockS3 {
+            url: format!("http://127.0.0.1:{port}"),
+            port,
+            state,
+        }
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state.lock().unwrap_or_else(|e| e.into_inner())
+    }
+
+    /// Creates the bucket of the tests, with the versioning enabled if [versioning].
+    pub fn create_bucket(&self, versioning: bool) {
+        self.state().buckets.insert(
+            BUCKET.to_string(),
+            Bucket {
+                versioning,
+                ..Default::default()
+            },
+        );
+    }
+
+    pub fn delete_bucket(&self) {
+        self.state().buckets.remove(BUCKET);
+    }
+
+    pub fn bucket_exists(&self) -> bool {
+        self.state().buckets.contains_key(BUCKET)
+    }
+
+    pub fn set_bucket_region(&self, region: &str) {
+        if let Some(bucket) = self.state().buckets.get_mut(BUCKET) {
+            bucket.region = Some(region.to_string());
+        }
+    }
+
+    /// Stores an object in the bucket of the tests as written by another client, with the
+    /// user [metadata].
+    pub fn insert_object(&self, key: &str, body: &[u8], metadata: &[(&str, &str)]) {
+        let headers = metadata
+            .iter()
+            .map(|(name, value)| (format!("x-amz-meta-{name}"), value.to_string()))
+            .collect();
+        let mut state = self.state();
+        let id = state.next_id();
+        let bucket = state.buckets.entry(BUCKET.to_string()).or_default();
+        store(bucket, key, body.to_vec(), headers, id);
+    }
+
+    /// Returns the latest version of the object [key], if it exists.
+    pub fn object(&self, key: &str) -> Option<StoredObject> {
+        self.state()
+            .buckets
+            .get(BUCKET)?
+            .objects
+            .get(key)?
+            .last()
+            .filter(|x| !x.delete_marker)
+            .cloned()
+    }
+
+    /// Returns the versions of the object [key], the latest last.
+    pub fn versions(&self, key: &str) -> Vec<StoredObject> {
+        self.state()
+            .buckets
+            .get(BUCKET)
+            .and_then(|bucket| bucket.objects.get(key).cloned())
+            .unwrap_or_default()
+    }
+
+    /// Returns the keys of the existing objects of the bucket, in order.
+    pub fn keys(&self) -> Vec<String> {
+        self.state()
+            .buckets
+            .get(BUCKET)
+            .map(|bucket| {
+                bucket
+                    .objects
+                    .iter()
+                    .filter(|(_, versions)| versions.last().map_or(false, |x| !x.delete_marker))
+                    .map(|(key, _)| key.to_owned())
+                    .collect()
+            })
+            .unwrap_or_default()
+    }
+
+    pub fn remove_object(&self, key: &str) {
+        if let Some(bucket) = self.state().buckets.get_mut(BUCKET) {
+            bucket.objects.remove(key);
+        }
+    }
+
+    /// Sets the header [name] of the latest version of the object [key], as another client would
+    /// (e.g. the Content-Encoding of an object it compressed).
+    pub fn set_header(&self, key: &str, name: &str, value: &str) {
+        if let Some(object) = self
+            .state()
+            .buckets
+            .get_mut(BUCKET)
+            .and_then(|bucket| bucket.objects.get_mut(key))
+            .and_then(|versions| versions.last_mut())
+        {
+            object.headers.insert(name.to_string(), value.to_string());
+        }
+    }
+
+    /// Adds a multipart upload of [key] initiated [age] ago.
+    pub fn insert_upload(&self, key: &str, age: Duration) -> String {
+        let mut state = self.state();
+        let upload_id = format!("upload-{}", state.next_id());
+        let initiated = now() - age.as_secs();
+        state
+            .buckets
+            .entry(BUCKET.to_string())
+            .or_default()
+            .uploads
+            .insert(
+                upload_id.to_owned(),
+                Upload {
+                    key: key.to_string(),
+                    initiated,
+                    headers: BTreeMap::new(),
+                    parts: BTreeMap::from([(1, b"part".to_vec())]),
+                },
+            );
+        upload_id
+    }
+
+    pub fn uploads(&self) -> Vec<Upload> {
+        self.state()
+            .buckets
+            .get(BUCKET)
+            .map(|bucket| bucket.uploads.values().cloned().collect())
+            .unwrap_or_default()
+    }
+
+    pub fn bucket_tagging(&self) -> Option<String> {
+        self.state().buckets.get(BUCKET)?.tagging.clone()
+    }
+
+    /// Returns the requests received so far.
+    pub fn requests(&self) -> Vec<Request> {
+        self.state().requests.clone()
+    }
+
+    /// Returns the requests of the S3 [operation] received so far.
+    pub fn requests_of(&self, operation: &str) -> Vec<Request> {
+        self.requests()
+            .into_iter()
+            .filter(|x| x.operation() == operation)
+            .collect()
+    }
+
+    pub fn clear_requests(&self) {
+        self.state().requests.clear();
+    }
+
+    /// Returns the maximum amount of requests of the S3 [operation] answered at once so far.
+    pub fn max_in_flight(&self, operation: &str) -> usize {
+        self.state()
+            .in_flight
+            .get(operation)
+            .map_or(0, |(_, max)| *max)
+    }
+
+    /// Delays the answers of the server, so that the requests overlap.
+    pub fn set_delay(&self, delay: Option<Duration>) {
+        self.state().delay = delay;
+    }
+
+    /// Answers the next [times] requests of the S3 [operation] with the error [code].
+    pub fn fail(&self, operation: &'static str, times: usize, status: u16, code: &str) {
+        self.inject(
+            times,
+            status,
+            vec![],
+            error_body(code, "Injected failure"),
+            move |x| x.operation() == operation,
+        );
+    }
+
+    /// Answers the next [times] requests matching [matches] with the given response.
+    pub fn inject(
+        &self,
+        times: usize,
+        status: u16,
+        headers: Vec<(String, String)>,
+        body: Vec<u8>,
+        matches: impl Fn(&Request) -> bool + Send + 'static,
+    ) {
+        self.state().injections.push(Injection {
+            matches: Box::new(matches),
+            remaining: times,
+            status,
+            headers,
+            body,
+        });
+    }
+}
+
+impl State {
+    fn next_id(&mut self) -> u64 {
+        self.next_id += 1;
+        self.next_id
+    }
+}
+
+fn now() -> u64 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .unwrap()
+        .as_secs()
+}
+
+/// Stores a new version of the object [key], replacing the previous one unless the versioning of
+/// the [bucket] is enabled.
+fn store(
+    bucket: &mut Bucket,
+    key: &str,
+    body: Vec<u8>,
+    headers: BTreeMap<String, String>,
+    id: u64,
+) -> StoredObject {
+    let e_tag = format!("\"{}\"", &hex(&Sha256::digest(&body))[..32]);
+    let object = StoredObject {
+        version_id: format!("v{id}"),
+        body,
+        headers,
+        last_modified: now(),
+        e_tag,
+        delete_marker: false,
+    };
+    let versions = bucket.objects.entry(key.to_string()).or_default();
+    if !bucket.versioning {
+        versions.clear();
+    }
+    versions.push(object.clone());
+    object
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|x| format!("{x:02x}")).collect()
+}
+
+/// Decodes the percent-encoded [value], as well as the `+` of the query values if [query].
+fn percent_decode(value: &str, query: bool) -> String {
+    let bytes = value.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        match bytes[i] {
+            b'%' if i + 2 < bytes.len() => {
+                let byte = std::str::from_utf8(&bytes[i + 1..i + 3])
+                    .ok()
+                    .and_then(|x| u8::from_str_radix(x, 16).ok());
+                match byte {
+                    Some(byte) => {
+                        decoded.push(byte);
+                        i += 3;
+                        continue;
+                    }
+                    None => decoded.push(b'%'),
+                }
+            }
+            b'+' if query => decoded.push(b' '),
+            byte => decoded.push(byte),
+        }
+        i += 1;
+    }
+    String::from_utf8_lossy(&decoded).into_owned()
+}
+
+fn xml_escape(value: &str) -> String {
+    value
+        .replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// Formats the [secs] since the epoch as `1970-01-01T00:00:00.000Z` if [iso], or else as
+/// `Thu, 01 Jan 1970 00:00:00 GMT`.
+fn format_date(secs: u64, iso: bool) -> String {
+    const DAYS: [&str; 7] = ["Thu", "Fri", "Sat", "Sun", "Mon", "Tue", "Wed"];
+    const MONTHS: [&str; 12] = [
+        "Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec",
+    ];
+    let days = (secs / 86400) as i64;
+    let (hour, minute, second) = ((secs % 86400) / 3600, (secs % 3600) / 60, secs % 60);
+    // Civil date of the days since the epoch.
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    match iso {
+        true => format!("{year:04}-{month:02}-{day:02}T{hour:02}:{minute:02}:{second:02}.000Z"),
+        false => format!(
+            "{}, {day:02} {} {year:04} {hour:02}:{minute:02}:{second:02} GMT",
+            DAYS[(days % 7) as usize],
+            MONTHS[(month - 1) as usize]
+        ),
+    }
+}
+
+pub(crate) fn error_body(code: &str, message: &str) -> Vec<u8> {
+    format!(
+        "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n<Error><Code>{code}</Code>\
+        <Message>{message}</Message><RequestId>mock-request-id</RequestId></Error>"
+    )
+    .into_bytes()
+}
+
+fn error(status: u16, code: &str) -> Response<Body> {
+    Response::builder()
+        .status(status)
+        .header("x-amz-request-id", "mock-request-id")
+        .header("content-type", "application/xml")
+        .body(Body::from(error_body(code, code)))
+        .unwrap()
+}
+
+fn xml(content: String) -> Response<Body> {
+    Response::builder()
+        .status(200)
+        .header("x-amz-request-id", "mock-request-id")
+        .header("content-type", "application/xml")
+        .body(Body::from(format!(
+            "<?xml version=\"1.0\" encoding=\"UTF-8\"?>\n{content}"
+        )))
+        .unwrap()
+}
+
+fn empty(status: u16, headers: &[(&str, String)]) -> Response<Body> {
+    let mut response = Response::builder()
+        .status(status)
+        .header("x-amz-request-id", "mock-request-id");
+    for (name, value) in headers {
+        response = response.header(*name, value);
+    }
+    response.body(Body::empty()).unwrap()
+}
+
+/// Returns the headers of a request stored along with an object.
+fn stored_headers(headers: &HashMap<String, String>) -> BTreeMap<String, String> {
+    headers
+        .iter()
+        .filter(|(name, _)| {
+            name.starts_with("x-amz-meta-")
+                || name.starts_with("x-amz-server-side-encryption")
+                || name.starts_with("x-amz-checksum-")
+                || matches!(
+                    name.as_str(),
+                    "content-type"
+                        | "content-encoding"
+                        | "content-disposition"
+                        | "content-language"
+                        | "cache-control"
+                        | "expires"
+                        | "x-amz-storage-class"
+                )
+        })
+        .map(|(name, value)| (name.to_owned(), value.to_owned()))
+        .collect()
+}
+
+async fn handle(
+    state: Arc<Mutex<State>>,
+    port: u16,
+    peer: SocketAddr,
+    request: hyper::Request<Body>,
+) -> Response<Body> {
+    let (parts, body) = request.into_parts();
+    let body = hyper::body::to_bytes(body)
+        .await
+        .map(|x| x.to_vec())
+        .unwrap_or_default();
+    let path = percent_decode(parts.uri.path(), false);
+    let (bucket, key) = match path.trim_start_matches('/').split_once('/') {
+        Some((bucket, key)) if !key.is_empty() => (bucket.to_string(), Some(key.to_string())),
+        Some((bucket, _)) => (bucket.to_string(), None),
+        None => (path.trim_start_matches('/').to_string(), None),
+    };
+    let query = parts
+        .uri
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|x| !x.is_empty())
+        .map(|x| match x.split_once('=') {
+            Some((name, value)) => (percent_decode(name, true), percent_decode(value, true)),
+            None => (percent_decode(x, true), String::new()),
+        })
+        .collect();
+    let headers = parts
+        .headers
+        .iter()
+        .map(|(name, value)| {
+            (
+                name.as_str().to_lowercase(),
+                String::from_utf8_lossy(value.as_bytes()).into_owned(),
+            )
+        })
+        .collect();
+    let request = Request {
+        port,
+        peer,
+        method: parts.method,
+        path,
+        bucket,
+        key,
+        query,
+        headers,
+        body,
+    };
+    let delay = {
+        let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+        state.requests.push(request.clone());
+        let (in_flight, max) = state
+            .in_flight
+            .entry(request.operation().to_string())
+            .or_default();
+        *in_flight += 1;
+        *max = (*max).max(*in_flight);
+        state.delay
+    };
+    if let Some(delay) = delay {
+        tokio::time::sleep(delay).await;
+    }
+    let mut state = state.lock().unwrap_or_else(|e| e.into_inner());
+    if let Some((in_flight, _)) = state.in_flight.get_mut(request.operation()) {
+        *in_flight -= 1;
+    }
+    if let Some(injection) = state
+        .injections
+        .iter_mut()
+        .find(|x| x.remaining > 0 && (x.matches)(&request))
+    {
+        injection.remaining -= 1;
+        let mut response = Response::builder()
+            .status(injection.status)
+            .header("x-amz-request-id", "mock-request-id");
+        for (name, value) in &injection.headers {
+            response = response.header(name, value);
+        }
+        let body = match request.method {
+            Method::HEAD => Body::empty(),
+            _ => Body::from(injection.body.clone()),
+        };
+        return response.body(body).unwrap();
+    }
+    let id = state.next_id();
+    match request.key.clone() {
+        None => handle_bucket(&mut state, &request),
+        Some(key) => match state.buckets.get_mut(&request.bucket) {
+            Some(bucket) => handle_object(bucket, &request, &key, id),
+            None => error(404, "NoSuchBucket"),
+        },
+    }
+}
+
+fn handle_bucket(state: &mut State, request: &Request) -> Response<Body> {
+    let name = request.bucket.to_owned();
+    match (request.operation(), state.buckets.get_mut(&name)) {
+        ("CreateBucket", Some(_)) => error(409, "BucketAlreadyOwnedByYou"),
+        ("CreateBucket", None) => {
+            state.buckets.insert(name, Bucket::default());
+            empty(200, &[])
+        }
+        (_, None) => error(404, "NoSuchBucket"),
+        ("HeadBucket", Some(_)) => empty(200, &[]),
+        ("DeleteBucket", Some(bucket)) => match bucket.objects.is_empty() {
+            true => {
+                state.buckets.remove(&name);
+                empty(204, &[])
+            }
+            false => error(409, "BucketNotEmpty"),
+        },
+        ("PutBucketTagging", Some(bucket)) => {
+            bucket.tagging = Some(String::from_utf8_lossy(&request.body).into_owned());
+            empty(200, &[])
+        }
+        ("GetBucketLocation", Some(bucket)) => xml(format!(
+            "<LocationConstraint xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">{}\
+            </LocationConstraint>",
+            bucket.region.clone().unwrap_or_default()
+        )),
+        ("ListObjectsV2", Some(bucket)) => list_objects(bucket, request),
+        ("ListObjectVersions", Some(bucket)) => list_versions(bucket, request),
+        ("ListMultipartUploads", Some(bucket)) => {
+            let prefix = request.query("prefix").unwrap_or_default();
+            let uploads: String = bucket
+                .uploads
+                .iter()
+                .filter(|(_, upload)| upload.key.starts_with(prefix))
+                .map(|(upload_id, upload)| {
+                    format!(
+                        "<Upload><Key>{}</Key><UploadId>{upload_id}</UploadId>\
+                        <Initiated>{}</Initiated></Upload>",
+                        xml_escape(&upload.key),
+                        format_date(upload.initiated, true)
+                    )
+                })
+                .collect();
+            xml(format!(
+                "<ListMultipartUploadsResult><Bucket>{name}</Bucket>\
+                <IsTruncated>false</IsTruncated>{uploads}</ListMultipartUploadsResult>"
+            ))
+        }
+        ("DeleteObjects", Some(bucket)) => delete_objects(bucket, request),
+        _ => error(400, "NotImplemented"),
+    }
+}
+
+fn list_objects(bucket: &Bucket, request: &Request) -> Response<Body> {
+    let prefix = request.query("prefix").unwrap_or_default();
+    let delimiter = request.query("delimiter").filter(|x| !x.is_empty());
+    let max_keys = request
+        .query("max-keys")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(1000usize);
+    let start_after = request
+        .query("continuation-token")
+        .or_else(|| request.query("start-after"))
+        .unwrap_or_default();
+    let mut contents = String::new();
+    let mut common_prefixes: Vec<String> = vec![];
+    let mut count = 0;
+    let mut next = None;
+    for (key, versions) in bucket.objects.range(start_after.to_string()..) {
+        if key.as_str() == start_after && !start_after.is_empty() {
+            continue;
+        }
+        if !key.starts_with(prefix) || versions.last().map_or(true, |x| x.delete_marker) {
+            continue;
+        }
+        if count == max_keys {
+            next = Some(key.to_owned());
+            break;
+        }
+        if let Some(delimiter) = delimiter {
+            if let Some(index) = key[prefix.len()..].find(delimiter) {
+                let common_prefix = key[..prefix.len() + index + delimiter.len()].to_string();
+                if !common_prefixes.contains(&common_prefix) {
+                    common_prefixes.push(common_prefix);
+                    count += 1;
+                }
+                continue;
+            }
+        }
+        let object = versions.last().unwrap();
+        contents.push_str(&format!(
+            "<Contents><Key>{}</Key><LastModified>{}</LastModified><ETag>{}</ETag>\
+            <Size>{}</Size><StorageClass>STANDARD</StorageClass></Contents>",
+            xml_escape(key),
+            format_date(object.last_modified, true),
+            xml_escape(&object.e_tag),
+            object.body.len()
+        ));
+        count += 1;
+    }
+    // The continuation token is the key preceding the next page.
+    let next = next.map(|next| {
+        let previous = bucket
+            .objects
+            .range(..next)
+            .next_back()
+            .map(|(key, _)| key.to_owned())
+            .unwrap_or_default();
+        format!(
+            "<NextContinuationToken>{}</NextContinuationToken>",
+            xml_escape(&previous)
+        )
+    });
+    let common_prefixes: String = common_prefixes
+        .iter()
+        .map(|x| {
+            format!(
+                "<CommonPrefixes><Prefix>{}</Prefix></CommonPrefixes>",
+                xml_escape(x)
+            )
+        })
+        .collect();
+    xml(format!(
+        "<ListBucketResult xmlns=\"http://s3.amazonaws.com/doc/2006-03-01/\">\
+        <Name>{}</Name><Prefix>{}</Prefix><KeyCount>{count}</KeyCount><MaxKeys>{max_keys}</MaxKeys>\
+        <IsTruncated>{}</IsTruncated>{contents}{common_prefixes}{}</ListBucketResult>",
+        request.bucket,
+        xml_escape(prefix),
+        next.is_some(),
+        next.unwrap_or_default()
+    ))
+}
+
+fn list_versions(bucket: &Bucket, request: &Request) -> Response<Body> {
+    let prefix = request.query("prefix").unwrap_or_default();
+    let max_keys = request
+        .query("max-keys")
+        .and_then(|x| x.parse().ok())
+        .unwrap_or(1000usize);
+    // The versions of each key, from the latest to the oldest, along with whether they're latest.
+    let listed: Vec<(&String, &StoredObject, bool)> = bucket
+        .objects
+        .iter()
+        .filter(|(x, _)| x.starts_with(prefix))
+        .flat_map(|(key, objects)| {
+            objects
+                .iter()
+                .enumerate()
+                .rev()
+                .map(move |(index, object)| (key, object, index + 1 == objects.len()))
+        })
+        .collect();
+    // The page starts after the version designated by the markers.
+    let start = match request.query("key-marker").filter(|x| !x.is_empty()) {
+        Some(key_marker) => {
+            let version_id_marker = request.query("version-id-marker").unwrap_or_default();
+            match listed.iter().position(|(key, object, _)| {
+                key.as_str() == key_marker && object.version_id == version_id_marker
+            }) {
+                Some(position) => position + 1,
+                None => listed
+                    .iter()
+                    .position(|(key, _, _)| key.as_str() > key_marker)
+                    .unwrap_or(listed.len()),
+            }
+        }
+        None => 0,
+    };
+    let page = &listed[start..listed.len().min(start + max_keys)];
+    let mut versions = String::new();
+    for (key, object, is_latest) in page {
+        let tag = match object.delete_marker {
+            true => "DeleteMarker",
+            false => "Version",
+        };
+        versions.push_str(&format!(
+            "<{tag}><Key>{}</Key><VersionId>{}</VersionId><IsLatest>{is_latest}</IsLatest>\
+            <LastModified>{}</LastModified><ETag>{}</ETag><Size>{}</Size></{tag}>",
+            xml_escape(key),
+            object.version_id,
+            format_date(object.last_modified, true),
+            xml_escape(&object.e_tag),
+            object.body.len()
+        ));
+    }
+    let next = match (start + page.len() < listed.len(), page.last()) {
+        (true, Some((key, object, _))) => format!(
+            "<NextKeyMarker>{}</NextKeyMarker><NextVersionIdMarker>{}</NextVersionIdMarker>",
+            xml_escape(key),
+            object.version_id
+        ),
+        _ => String::new(),
+    };
+    xml(format!(
+        "<ListVersionsResult><Name>{}</Name><IsTruncated>{}</IsTruncated>{versions}{next}\
+        </ListVersionsResult>",
+        request.bucket,
+        !next.is_empty()
+    ))
+}
+
+/// Returns the values of the [tag] elements in the [xml] document.
+fn xml_values<'a>(xml: &'a str, tag: &str) -> Vec<&'a str> {
+    let (open, close) = (format!("<{tag}>"), format!("</{tag}>"));
+    xml.split(open.as_str())
+        .skip(1)
+        .filter_map(|x| x.split_once(close.as_str()).map(|(value, _)| value))
+        .collect()
+}
+
+fn xml_unescape(value: &str) -> String {
+    value
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&")
+}
+
+fn delete_objects(bucket: &mut Bucket, request: &Request) -> Response<Body> {
+    let body = String::from_utf8_lossy(&request.body).into_owned();
+    let mut deleted = String::new();
+    for object in xml_values(&body, "Object") {
+        let key = xml_unescape(
+            xml_values(object, "Key")
+                .first()
+                .copied()
+                .unwrap_or_default(),
+        );
+        match xml_values(object, "VersionId").first() {
+            Some(version_id) => {
+                if let Some(versions) = bucket.objects.get_mut(&key) {
+                    versions.retain(|x| x.version_id != *version_id);
+                    if versions.is_empty() {
+                        bucket.objects.remove(&key);
+                    }
+                }
+            }
+            None => {
+                bucket.objects.remove(&key);
+            }
+        }
+        deleted.push_str(&format!(
+            "<Deleted><Key>{}</Key></Deleted>",
+            xml_escape(&key)
+        ));
+    }
+    xml(format!("<DeleteResult>{deleted}</DeleteResult>"))
+}
+
+/// Answers a SelectObjectContent request on a CSV object with a header line. Only the expressions
+/// selecting all the columns are supported, optionally of the rows where a column is equal to a
+/// string (e.g. `SELECT * FROM s3object s WHERE s.city = 'Paris'`).
+fn select(object: &StoredObject, request: &Request) -> Response<Body> {
+    let body = String::from_utf8_lossy(&request.body);
+    let expression = xml_values(&body, "Expression")
+        .first()
+        .map(|x| xml_unescape(x))
+        .unwrap_or_default();
+    let condition = expression
+        .split_once(" WHERE s.")
+        .and_then(|(_, condition)| condition.split_once(" = "))
+        .map(|(column, value)| (column.to_string(), value.trim_matches('\'').to_string()));
+    let content = String::from_utf8_lossy(&object.body);
+    let mut lines = content.lines();
+    let columns: Vec<&str> = lines.next().unwrap_or_default().split(',').collect();
+    let records: String = lines
+        .filter(|line| match &condition {
+            Some((column, value)) => {
+                let index = columns.iter().position(|x| x == column);
+                index.and_then(|i| line.split(',').nth(i)) == Some(value.as_str())
+            }
+            None => true,
+        })
+        .map(|line| format!("{line}\n"))
+        .collect();
+    let mut body = select_event("Records", records.as_bytes());
+    body.extend(select_event("End", &[]));
+    Response::builder()
+        .status(200)
+        .header("x-amz-request-id", "mock-request-id")
+        .body(Body::from(body))
+        .unwrap()
+}
+
+/// Encodes an event of the event stream answering a SelectObjectContent request.
+fn select_event(event_type: &str, payload: &[u8]) -> Vec<u8> {
+    let mut headers = vec![];
+    for (name, value) in [
+        (":message-type", "event"),
+        (":event-type", event_type),
+        (":content-type", "application/octet-stream"),
+    ] {
+        headers.push(name.len() as u8);
+        headers.extend_from_slice(name.as_bytes());
+        // String value.
+        headers.push(7);
+        headers.extend_from_slice(&(value.len() as u16).to_be_bytes());
+        headers.extend_from_slice(value.as_bytes());
+    }
+    let mut message = vec![];
+    message.extend_from_slice(&((16 + headers.len() + payload.len()) as u32).to_be_bytes());
+    message.extend_from_slice(&(headers.len() as u32).to_be_bytes());
+    message.extend_from_slice(&crc32(&message).to_be_bytes());
+    message.extend(headers);
+    message.extend_from_slice(payload);
+    message.extend_from_slice(&crc32(&message).to_be_bytes());
+    message
+}
+
+fn crc32(bytes: &[u8]) -> u32 {
+    let mut crc = !0u32;
+    for byte in bytes {
+        crc ^= *byte as u32;
+        for _ in 0..8 {
+            crc = (crc >> 1) ^ (0xEDB8_8320 & (crc & 1).wrapping_neg());
+        }
+    }
+    !crc
+}
+
+fn object_headers(object: &StoredObject) -> Vec<(&str, String)> {
+    let mut headers = vec![
+        ("etag", object.e_tag.to_owned()),
+        ("last-modified", format_date(object.last_modified, false)),
+        ("x-amz-version-id", object.version_id.to_owned()),
+    ];
+    for (name, value) in &object.headers {
+        // The customer-provided key is never returned.
+        if name != "x-amz-server-side-encryption-customer-key" {
+            headers.push((name.as_str(), value.to_owned()));
+        }
+    }
+    headers
+}
+
+fn handle_object(bucket: &mut Bucket, request: &Request, key: &str, id: u64) -> Response<Body> {
+    match request.operation() {
+        "PutObject" => {
+            let object = store(
+                bucket,
+                key,
+                request.body.clone(),
+                stored_headers(&request.headers),
+                id,
+            );
+            empty(
+                200,
+                &[
+                    ("etag", object.e_tag),
+                    ("x-amz-version-id", object.version_id),
+                ],
+            )
+        }
+        "CopyObject" => {
+            let source = request.header("x-amz-copy-source").unwrap_or_default();
+            let source = percent_decode(source.split('?').next().unwrap_or_default(), false);
+            let source_key = source
+                .trim_start_matches('/')
+                .split_once('/')
+                .map(|(_, key)| key.to_string())
+                .unwrap_or_default();
+            let source = match bucket
+                .objects
+                .get(&source_key)
+                .and_then(|x| x.last())
+                .filter(|x| !x.delete_marker)
+            {
+                Some(source) => source.clone(),
+                None => return error(404, "NoSuchKey"),
+            };
+            if let Some(e_tag) = request.header("x-amz-copy-source-if-match") {
+                if e_tag != source.e_tag {
+                    return error(412, "PreconditionFailed");
+                }
+            }
+            let headers = match request.header("x-amz-metadata-directive") {
+                Some("REPLACE") => stored_headers(&request.headers),
+                _ => {
+                    let mut headers = source.headers.clone();
+                    headers.retain(|name, _| !name.starts_with("x-amz-server-side-encryption"));
+                    headers.extend(
+                        stored_headers(&request.headers)
+                            .into_iter()
+                            .filter(|(name, _)| name.starts_with("x-amz-server-side-encryption")),
+                    );
+                    headers
+                }
+            };
+            let object = store(bucket, key, source.body, headers, id);
+            xml(format!(
+                "<CopyObjectResult><ETag>{}</ETag><LastModified>{}</LastModified>\
+                </CopyObjectResult>",
+                xml_escape(&object.e_tag),
+                format_date(object.last_modified, true)
+            ))
+        }
+        "CreateMultipartUpload" => {
+            let upload_id = format!("upload-{id}");
+            bucket.uploads.insert(
+                upload_id.to_owned(),
+                Upload {
+                    key: key.to_string(),
+                    initiated: now(),
+                    headers: stored_headers(&request.headers),
+                    parts: BTreeMap::new(),
+                },
+            );
+            xml(format!(
+                "<InitiateMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key>\
+                <UploadId>{upload_id}</UploadId></InitiateMultipartUploadResult>",
+                request.bucket,
+                xml_escape(key)
+            ))
+        }
+        "UploadPart" | "UploadPartCopy" => {
+            let upload_id = request.query("uploadId").unwrap_or_default().to_string();
+            let part_number = request
+                .query("partNumber")
+                .and_then(|x| x.parse().ok())
+                .unwrap_or_default();
+            let body = match request.operation() {
+                "UploadPart" => request.body.clone(),
+                _ => {
+                    let source = request.header("x-amz-copy-source").unwrap_or_default();
+                    let source = percent_decode(source, false);
+                    let source_key = source
+                        .trim_start_matches('/')
+                        .split_once('/')
+                        .map(|(_, key)| key.to_string())
+                        .unwrap_or_default();
+                    let body = match bucket.objects.get(&source_key).and_then(|x| x.last()) {
+                        Some(source) => source.body.clone(),
+                        None => return error(404, "NoSuchKey"),
+                    };
+                    match request
+                        .header("x-amz-copy-source-range")
+                        .and_then(|x| x.strip_prefix("bytes="))
+                        .and_then(|x| x.split_once('-'))
+                        .and_then(|(start, end)| Some((start.parse().ok()?, end.parse().ok()?)))
+                    {
+                        Some((start, end)) => body[start..=end.min(body.len() - 1)].to_vec(),
+                        None => body,
+                    }
+                }
+            };
+            let e_tag = format!("\"{}\"", &hex(&Sha256::digest(&body))[..32]);
+            match bucket.uploads.get_mut(&upload_id) {
+                Some(upload) => {
+                    upload.parts.insert(part_number, body);
+                    match request.operation() {
+                        "UploadPart" => empty(200, &[("etag", e_tag)]),
+                        _ => xml(format!(
+                            "<CopyPartResult><ETag>{}</ETag><LastModified>{}</LastModified>\
+                            </CopyPartResult>",
+                            xml_escape(&e_tag),
+                            format_date(now(), true)
+                        )),
+                    }
+                }
+                None => error(404, "NoSuchUpload"),
+            }
+        }
+        "CompleteMultipartUpload" => {
+            let upload_id = request.query("uploadId").unwrap_or_default();
+            match bucket.uploads.remove(upload_id) {
+                Some(upload) => {
+                    let body = upload.parts.into_values().flatten().collect();
+                    let object = store(bucket, key, body, upload.headers, id);
+                    xml(format!(
+                        "<CompleteMultipartUploadResult><Bucket>{}</Bucket><Key>{}</Key>\
+                        <ETag>{}</ETag></CompleteMultipartUploadResult>",
+                        request.bucket,
+                        xml_escape(key),
+                        xml_escape(&object.e_tag)
+                    ))
+                }
+                None => error(404, "NoSuchUpload"),
+            }
+        }
+        "AbortMultipartUpload" => {
+            let upload_id = request.query("uploadId").unwrap_or_default();
+            match bucket.uploads.remove(upload_id) {
+                Some(_) => empty(204, &[]),
+                None => error(404, "NoSuchUpload"),
+            }
+        }
+        "DeleteObject" => {
+            match request.query("versionId") {
+                Some(version_id) => {
+                    if let Some(versions) = bucket.objects.get_mut(key) {
+                        versions.retain(|x| x.version_id != version_id);
+                    }
+                }
+                None if bucket.versioning => {
+                    let versions = bucket.objects.entry(key.to_string()).or_default();
+                    versions.push(StoredObject {
+                        version_id: format!("v{id}"),
+                        body: vec![],
+                        headers: BTreeMap::new(),
+                        last_modified: now(),
+                        e_tag: String::new(),
+                        delete_marker: true,
+                    });
+                }
+                None => {
+                    bucket.objects.remove(key);
+                }
+            }
+            empty(204, &[])
+        }
+        "RestoreObject" => match bucket
+            .objects
+            .get_mut(key)
+            .and_then(|versions| versions.last_mut())
+        {
+            // The restoration of an object already restored only extends its availability.
+            Some(object)
+                if object.headers.get("x-amz-restore").map(|x| x.as_str())
+                    == Some("ongoing-request=\"false\"") =>
+            {
+                empty(200, &[])
+            }
+            Some(object) => {
+                object
+                    .headers
+                    .insert("x-amz-restore".into(), "ongoing-request=\"true\"".into());
+                empty(202, &[])
+            }
+            None => error(404, "NoSuchKey"),
+        },
+        "SelectObjectContent" => match bucket
+            .objects
+            .get(key)
+            .and_then(|x| x.last())
+            .filter(|x| !x.delete_marker)
+        {
+            Some(object) => select(object, request),
+            None => error(404, "NoSuchKey"),
+        },
+        "GetObject" | "HeadObject" | "GetObjectAttributes" => {
+            let versions = bucket.objects.get(key);
+            let object = match request.query("versionId") {
+                Some(version_id) => {
+                    versions.and_then(|x| x.iter().find(|x| x.version_id == version_id))
+                }
+                None => versions.and_then(|x| x.last()),
+            };
+            let object = match object.filter(|x| !x.delete_marker) {
+                Some(object) => object,
+                None if request.method == Method::HEAD => return empty(404, &[]),
+                None => return error(404, "NoSuchKey"),
+            };
+            // The objects encrypted with a customer-provided key can't be read without it.
+            let customer_key = object
+                .headers
+                .get("x-amz-server-side-encryption-customer-key-md5");
+            if customer_key.is_some()
+                && customer_key.map(|x| x.as_str())
+                    != request.header("x-amz-server-side-encryption-customer-key-md5")
+            {
+                return match request.method {
+                    Method::HEAD => empty(400, &[]),
+                    _ => error(400, "InvalidRequest"),
+                };
+            }
+            // The archived objects can't be read until restored.
+            if request.operation() == "GetObject"
+                && object
+                    .headers
+                    .get("x-amz-storage-class")
+                    .map(|x| x.as_str())
+                    == Some("GLACIER")
+                && object.headers.get("x-amz-restore").map(|x| x.as_str())
+                    != Some("ongoing-request=\"false\"")
+            {
+                return error(403, "InvalidObjectState");
+            }
+            if request.operation() == "GetObjectAttributes" {
+                return xml(format!(
+                    "<GetObjectAttributesResponse><ETag>{}</ETag><ObjectSize>{}</ObjectSize>\
+                    <StorageClass>STANDARD</StorageClass></GetObjectAttributesResponse>",
+                    object.e_tag.trim_matches('"'),
+                    object.body.len()
+                ));
+            }
+            let mut headers = object_headers(object);
+            let (status, body) = match request
+                .header("range")
+                .and_then(|x| x.strip_prefix("bytes="))
+                .and_then(|x| x.split_once('-'))
+                .and_then(|(start, end)| Some((start.parse::<usize>().ok()?, end.parse().ok())))
+            {
+                Some((start, end)) if start < object.body.len() => {
+                    let end: usize = end
+                        .unwrap_or(object.body.len() - 1)
+                        .min(object.body.len() - 1);
+                    headers.push((
+                        "content-range",
+                        format!("bytes {start}-{end}/{}", object.body.len()),
+                    ));
+                    (206, object.body[start..=end].to_vec())
+                }
+                _ => (200, object.body.clone()),
+            };
+            headers.push(("content-length", body.len().to_string()));
+            let mut response = Response::builder()
+                .status(status)
+                .header("x-amz-request-id", "mock-request-id");
+            for (name, value) in headers {
+                response = response.header(name, value);
+            }
+            let body = match request.method {
+                Method::HEAD => Body::empty(),
+                _ => Body::from(body),
+            };
+            response.body(body).unwrap()
+        }
+        _ => error(400, "NotImplemented"),
+    }
+}
+
+/// Starts a server answering every request with the raw HTTP [response], to simulate the
+/// malformed responses of some gateways that hyper can't send. Returns the URL of the server.
+pub(crate) async fn start_raw(response: &'static [u8]) -> String {
+    let listener = tokio::net::TcpListener::bind(SocketAddr::from(([127, 0, 0, 1], 0)))
+        .await
+        .expect("Couldn't bind the raw server");
+    let url = format!("http://{}", listener.local_addr().unwrap());
+    tokio::task::spawn(async move {
+        while let Ok((mut stream, _)) = listener.accept().await {
+            tokio::task::spawn(async move {
+                // Only the requests without a body are supported.
+                let mut request = vec![];
+                let mut buffer = [0; 4096];
+                while !request.ends_with(b"\r\n\r\n") {
+                    match stream.read(&mut buffer).await {
+                        Ok(0) | Err(_) => return,
+                        Ok(n) => request.extend_from_slice(&buffer[..n]),
+                    }
+                }
+                let _ = stream.write_all(response).await;
+            });
+        }
+    });
+    url
+}
+
+/// Starts the S3 backend with a volume communicating with the [mock], with the additional
+/// properties of the [volume].
+pub(crate) fn start_volume(mock: &MockS3, volume: serde_json::Value) -> Box<dyn Volume> {
+    let mut rest = serde_json::Map::new();
+    rest.insert("url".into(), mock.url.to_owned().into());
+    rest.insert("region".into(), "us-east-1".into());
+    if let serde_json::Value::Object(volume) = volume {
+        rest.extend(volume);
+    }
+    let config = VolumeConfig {
+        name: "s3".to_string(),
+        backend: None,
+        paths: None,
+        required: false,
+        rest,
+    };
+    S3Backend::start("s3", &config).expect("Couldn't start the volume")
+}
+
+/// Returns the configuration of a storage of `test/**` stripping `test`, storing its values in
+/// the bucket of the tests with the additional properties of the [volume_cfg].
+pub(crate) fn storage_config(volume_cfg: serde_json::Value) -> StorageConfig {
+    let mut cfg = json!({
+        "bucket": BUCKET,
+        "reuse_bucket": true,
+        "private": {
+            "access_key": ACCESS_KEY,
+            "secret_key": SECRET_KEY,
+        },
+    });
+    if let (Some(cfg), serde_json::Value::Object(volume_cfg)) = (cfg.as_object_mut(), volume_cfg) {
+        cfg.extend(volume_cfg);
+    }
+    StorageConfig {
+        name: "test".to_string(),
+        key_expr: OwnedKeyExpr::from_str("test/**").unwrap(),
+        complete: false,
+        strip_prefix: Some(OwnedKeyExpr::from_str("test").unwrap()),
+        volume_id: "s3".to_string(),
+        volume_cfg: cfg,
+        garbage_collection_config: Default::default(),
+        replica_config: None,
+    }
+}
+
+/// Creates a storage of `test/**` in the bucket of the [mock] (see [storage_config]).
+pub(crate) async fn create_storage(
+    mock: &MockS3,
+    volume_cfg: serde_json::Value,
+) -> Box<dyn Storage> {
+    start_volume(mock, json!({}))
+        .create_storage(storage_config(volume_cfg))
+        .await
+        .expect("Couldn't create the storage")
+}
+
+/// Creates the client of a storage of `test/**` in the bucket of the [mock] (see
+/// [storage_config]), to test the operations of the client which the storage doesn't expose.
+pub(crate) async fn create_client(mock: &MockS3, volume_cfg: serde_json::Value) -> S3Client {
+    create_client_at(&mock.url, volume_cfg).await
+}
+
+/// Returns a client of the bucket of the tests on the server at [url], configured by the storage
+/// properties of the [volume_cfg].
+pub(crate) async fn create_client_at(url: &str, volume_cfg: serde_json::Value) -> S3Client {
+    let config = S3Config::new(&storage_config(volume_cfg))
+        .await
+        .expect("Invalid storage configuration");
+    S3Client::new(
+        &config,
+        Some("us-east-1".to_string()),
+        vec![url.to_owned()],
+        None,
+        None,
+        None,
+        &ConnectionConfig::default(),
+        None,
+    )
+    .await
+    .expect("Couldn't create the client")
+}
+
+/// Returns a timestamp of the [time] in seconds since the epoch, from a fixed clock.
+pub(crate) fn timestamp(time: u64) -> zenoh::time::Timestamp {
+    timestamp_at(Duration::from_secs(time))
+}
+
+/// Returns a timestamp of the [time] since the epoch, from a fixed clock.
+pub(crate) fn timestamp_at(time: Duration) -> zenoh::time::Timestamp {
+    zenoh::time::Timestamp::new(
+        uhlc::NTP64::from(time),
+        uhlc::ID::try_from(&[1u8][..]).unwrap(),
+    )
+}
+
+/// Returns the key expression of the [key] within the storage of the tests.
+pub(crate) fn key(key: &str) -> Option<OwnedKeyExpr> {
+    Some(OwnedKeyExpr::from_str(key).unwrap())
+}
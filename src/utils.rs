@@ -11,46 +11,767 @@
 // Contributors:
 //   ZettaScale Zenoh Team, <zenoh@zettascale.tech>
 //
+use base64::engine::general_purpose;
+use base64::Engine;
 use core::fmt;
+use sha2::{Digest, Sha256};
+use std::borrow::Cow;
+use std::cmp::Reverse;
+use std::collections::hash_map::RandomState;
+use std::collections::{BTreeMap, BinaryHeap, HashMap, HashSet, VecDeque};
 use std::convert::TryFrom;
+use std::fs::File;
+use std::future::Future;
+use std::hash::{BuildHasher, Hasher};
+use std::io::{BufRead, BufReader, BufWriter, Lines, Read, Seek, SeekFrom, Write};
+use std::ops::Range;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+use tempfile::NamedTempFile;
+use tokio::sync::{Semaphore, SemaphorePermit};
+use unicode_normalization::{is_nfc, is_nfd, UnicodeNormalization};
+use zenoh::time::Timestamp;
 use zenoh::Result as ZResult;
+use zenoh_core::zerror;
 use zenoh_keyexpr::OwnedKeyExpr;
 
+use crate::client::ObjectContent;
+use crate::config::{
+    EntriesSpillConfig, GetCacheConfig, LogKeys, RateLimitConfig, RetryConfig, TimestampSource,
+    UploadSpillConfig,
+};
+
+/// Layout of the object keys in the bucket.
+///
+/// Besides the layout of this backend, some legacy layouts are supported so that the storage can
+/// be pointed at data copied from other stores (such as the filesystem or Redis backends):
+/// * [KeyLayout::NoLeadingSlash] (`no_leading_slash`): the object keys are the zenoh keys
+///     stripped from the `strip_prefix`, without leading slash (e.g. `a/b`).
+/// * [KeyLayout::FullKey] (`full_key`): the object keys are the complete zenoh keys, including the
+///     `strip_prefix` (e.g. `s3/example/a/b`).
+///
+/// The same layout is used both to read and to write the objects.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum KeyLayout {
+    /// Layout of this backend: the object keys are the zenoh keys stripped from the
+    /// `strip_prefix`, keeping the leading slash when a `strip_prefix` is set (e.g. `/a/b`).
+    #[default]
+    S3,
+    NoLeadingSlash,
+    FullKey,
+}
+
+/// Handling of the case of the keys, for S3 implementations which are case-insensitive and where
+/// keys only differing by their case would collide.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum KeyCase {
+    /// The keys are stored as they are.
+    #[default]
+    Preserve,
+    /// The keys are lowercased before being stored, hence `A/b` and `a/b` refer to the same object.
+    Lowercase,
+    /// Keys containing uppercase characters are rejected.
+    RejectUppercase,
+}
+
+/// Unicode normalization of the keys, so that the keys written with distinct but equivalent
+/// sequences of code points (e.g. `é` composed or as `e` followed by a combining accent) refer to
+/// the same object.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum KeyNormalization {
+    /// The keys are stored as they are.
+    #[default]
+    None,
+    /// The keys are normalized to their canonical composition (NFC).
+    Nfc,
+    /// The keys are normalized to their canonical decomposition (NFD).
+    Nfd,
+}
+
+/// Policy regarding the leading slash of the object keys, applied consistently when writing the
+/// objects and when listing them, so that the keys round-trip exactly.
+#[derive(Clone, Copy, Default, Debug, PartialEq, Eq)]
+pub enum LeadingSlash {
+    /// The leading slash depends on the [KeyLayout], and the listings accept the object keys
+    /// with or without it.
+    #[default]
+    Layout,
+    /// The object keys always start with a slash (e.g. `/a/b`), the listings ignore the others.
+    Preserve,
+    /// The object keys never start with a slash (e.g. `a/b`), the listings ignore the others.
+    Strip,
+}
+
+/// Settings of the mapping between the zenoh keys and the object keys.
+#[derive(Clone, Debug)]
+pub struct KeyOptions {
+    pub layout: KeyLayout,
+    pub case: KeyCase,
+    pub normalization: KeyNormalization,
+    /// Maximum length in bytes of the object keys, beyond which they are hashed (see
+    /// [fit_object_key]).
+    pub max_length: usize,
+    /// Separator between the `strip_prefix` and the key in the object keys which include it
+    /// ([KeyLayout::FullKey]) or which start with it ([KeyLayout::S3]), `/` by default.
+    pub prefix_separator: String,
+    pub leading_slash: LeadingSlash,
+    /// Number of hexadecimal digits of the hash segment prepended to the object keys, if any
+    /// (see [KeyOptions::apply_hash_segment]).
+    pub hash_prefix_length: Option<usize>,
+}
+
+impl KeyOptions {
+    /// Applies the [LeadingSlash] policy to the [object_key].
+    fn apply_leading_slash(&self, object_key: String) -> String {
+        match self.leading_slash {
+            LeadingSlash::Layout => object_key,
+            LeadingSlash::Preserve if object_key.starts_with('/') => object_key,
+            LeadingSlash::Preserve => format!("/{object_key}"),
+            LeadingSlash::Strip => object_key.trim_start_matches('/').to_owned(),
+        }
+    }
+
+    /// Checks whether the [object_key] complies with the [LeadingSlash] policy, that is whether
+    /// it would be written under the same object key once read.
+    pub fn matches_leading_slash(&self, object_key: &str) -> bool {
+        let object_key = self
+            .strip_hash_segment(object_key)
+            .unwrap_or_else(|| object_key.to_owned());
+        match self.leading_slash {
+            LeadingSlash::Layout => true,
+            LeadingSlash::Preserve => object_key.starts_with('/'),
+            LeadingSlash::Strip => !object_key.starts_with('/'),
+        }
+    }
+
+    /// Prepends to the [object_key] a segment made of the first digits of its SHA-256 hash (e.g.
+    /// `3fa/a/b` for `/a/b`), if a `hash_prefix_length` is configured. S3 scaling the request
+    /// rate per prefix, sequential keys are then spread across prefixes instead of a single one.
+    ///
+    /// The leading slash of the object key is dropped, the hash telling whether it had one.
+    fn apply_hash_segment(&self, object_key: String) -> String {
+        match self.hash_prefix_length {
+            Some(length) => format!(
+                "{}/{}",
+                &key_hash(&object_key)[..length],
+                object_key.strip_prefix('/').unwrap_or(&object_key)
+            ),
+            None => object_key,
+        }
+    }
+
+    /// Strips the hash segment prepended by [KeyOptions::apply_hash_segment] from the
+    /// [object_key], recomputing the hash of the key with and without leading slash. Returns
+    /// None if the segment doesn't match the hash of the key, i.e. the object wasn't written by
+    /// the storage with the same settings.
+    pub fn strip_hash_segment(&self, object_key: &str) -> Option<String> {
+        let length = match self.hash_prefix_length {
+            Some(length) => length,
+            None => return Some(object_key.to_owned()),
+        };
+        let (hash, key) = object_key.split_once('/')?;
+        if hash.len() != length {
+            return None;
+        }
+        [key.to_owned(), format!("/{key}")]
+            .into_iter()
+            .find(|key| key_hash(key).starts_with(hash))
+    }
+}
+
+impl Default for KeyOptions {
+    fn default() -> Self {
+        KeyOptions {
+            layout: KeyLayout::default(),
+            case: KeyCase::default(),
+            normalization: KeyNormalization::default(),
+            max_length: MAX_OBJECT_KEY_LENGTH,
+            prefix_separator: DEFAULT_PREFIX_SEPARATOR.to_string(),
+            leading_slash: LeadingSlash::default(),
+            hash_prefix_length: None,
+        }
+    }
+}
+
+/// Default separator between the `strip_prefix` and the key in the object keys.
+pub const DEFAULT_PREFIX_SEPARATOR: &str = "/";
+
 pub struct S3Key<'a> {
     pub prefix: Option<&'a String>,
     pub key_expr: OwnedKeyExpr,
+    pub options: &'a KeyOptions,
 }
 
 impl<'a> S3Key<'a> {
-    pub fn from_key(prefix: Option<&'a String>, key: String) -> ZResult<Self> {
+    pub fn from_key(
+        prefix: Option<&'a String>,
+        key: String,
+        options: &'a KeyOptions,
+    ) -> ZResult<Self> {
+        let key = match prefix {
+            Some(prefix) => join_prefix_separator(prefix, &key, options),
+            None => key,
+        };
         let key_expr = match prefix {
-            Some(prefix) => {
-                OwnedKeyExpr::try_from(format!("{}/{}", prefix, key.trim_start_matches('/')))?
+            Some(_) if options.layout == KeyLayout::FullKey => {
+                OwnedKeyExpr::try_from(normalize_key(&key))?
             }
-            None => OwnedKeyExpr::try_from(key.trim_start_matches('/'))?,
+            Some(prefix) => OwnedKeyExpr::try_from(format!("{}/{}", prefix, normalize_key(&key)))?,
+            None => OwnedKeyExpr::try_from(normalize_key(&key))?,
         };
-        Ok(Self { prefix, key_expr })
+        Ok(Self {
+            prefix,
+            key_expr,
+            options,
+        })
     }
 
-    pub fn from_key_expr(prefix: Option<&'a String>, key_expr: OwnedKeyExpr) -> ZResult<Self> {
+    pub fn from_key_expr(
+        prefix: Option<&'a String>,
+        key_expr: OwnedKeyExpr,
+        options: &'a KeyOptions,
+    ) -> ZResult<Self> {
+        let key_expr = match options.normalization {
+            KeyNormalization::Nfc if !is_nfc(key_expr.as_str()) => {
+                OwnedKeyExpr::try_from(key_expr.as_str().nfc().collect::<String>())?
+            }
+            KeyNormalization::Nfd if !is_nfd(key_expr.as_str()) => {
+                OwnedKeyExpr::try_from(key_expr.as_str().nfd().collect::<String>())?
+            }
+            _ => key_expr,
+        };
+        let key_expr = match options.case {
+            KeyCase::Preserve => key_expr,
+            KeyCase::Lowercase => OwnedKeyExpr::try_from(key_expr.to_lowercase())?,
+            KeyCase::RejectUppercase if key_expr.chars().any(char::is_uppercase) => {
+                return Err(zerror!(
+                    "Key '{}' contains uppercase characters and may collide with other keys.",
+                    key_expr
+                )
+                .into())
+            }
+            KeyCase::RejectUppercase => key_expr,
+        };
         let key_expr = match prefix {
             Some(prefix) => {
                 OwnedKeyExpr::try_from(format!("{}/{}", prefix, key_expr.trim_start_matches('/')))?
             }
             None => key_expr,
         };
-        Ok(Self { prefix, key_expr })
+        Ok(Self {
+            prefix,
+            key_expr,
+            options,
+        })
+    }
+
+    /// Returns the key expression stripped from the prefix.
+    pub fn stripped_key_expr(&self) -> ZResult<OwnedKeyExpr> {
+        let key = self
+            .prefix
+            .and_then(|prefix| self.key_expr.as_str().strip_prefix(prefix.as_str()))
+            .unwrap_or(self.key_expr.as_str());
+        OwnedKeyExpr::try_from(key.trim_start_matches('/'))
+    }
+}
+
+/// Returns the prefix shared by the object keys matching the wildcard key expression of the
+/// [s3_key], that is the object key of its chunks preceding the first wildcard chunk (e.g. `/a/`
+/// for `s3/example/a/**` with the `s3/example` prefix).
+///
+/// The prefix is empty when the object keys start with their hash segment (see
+/// [KeyOptions::apply_hash_segment]), as the matching objects are then spread across prefixes.
+pub fn wildcard_object_prefix(s3_key: &S3Key) -> String {
+    if s3_key.options.hash_prefix_length.is_some() {
+        return String::new();
+    }
+    let literal: String = s3_key
+        .key_expr
+        .split('/')
+        .take_while(|chunk| !chunk.contains(['*', '$']))
+        .map(|chunk| format!("{chunk}/"))
+        .collect();
+    let stripped = match s3_key.prefix {
+        Some(prefix) if s3_key.options.layout != KeyLayout::FullKey => {
+            literal.strip_prefix(prefix.as_str()).unwrap_or(&literal)
+        }
+        _ => &literal,
+    };
+    let object_prefix = match (s3_key.options.layout, s3_key.prefix) {
+        (KeyLayout::S3, Some(_)) | (KeyLayout::FullKey, _) => stripped.to_owned(),
+        _ => stripped.trim_start_matches('/').to_owned(),
+    };
+    let object_prefix = s3_key.options.apply_leading_slash(object_prefix);
+    match s3_key.prefix {
+        Some(prefix) => split_prefix_separator(prefix, object_prefix, s3_key.options),
+        None => object_prefix,
+    }
+}
+
+/// Replaces the `/` between the [prefix] and the key of an [object_key] built with the slash
+/// separator by the configured prefix separator.
+fn split_prefix_separator(prefix: &str, object_key: String, options: &KeyOptions) -> String {
+    if options.prefix_separator == DEFAULT_PREFIX_SEPARATOR {
+        return object_key;
+    }
+    let head = match options.layout {
+        KeyLayout::S3 => "",
+        KeyLayout::FullKey => prefix,
+        KeyLayout::NoLeadingSlash => return object_key,
+    };
+    match object_key
+        .strip_prefix(head)
+        .and_then(|rest| rest.strip_prefix('/'))
+    {
+        Some(key) => format!("{head}{}{key}", options.prefix_separator),
+        None => object_key,
+    }
+}
+
+/// Replaces the configured prefix separator between the [prefix] and the key of an [object_key]
+/// by a `/`, reverting [split_prefix_separator].
+fn join_prefix_separator(prefix: &str, object_key: &str, options: &KeyOptions) -> String {
+    if options.prefix_separator == DEFAULT_PREFIX_SEPARATOR {
+        return object_key.to_owned();
+    }
+    let head = match options.layout {
+        KeyLayout::S3 => "",
+        KeyLayout::FullKey => prefix,
+        KeyLayout::NoLeadingSlash => return object_key.to_owned(),
+    };
+    match object_key
+        .strip_prefix(head)
+        .and_then(|rest| rest.strip_prefix(options.prefix_separator.as_str()))
+    {
+        Some(key) => format!("{head}/{key}"),
+        None => object_key.to_owned(),
+    }
+}
+
+/// Normalizes an object key by removing its empty segments, that is its leading, trailing and
+/// doubled slashes (e.g. `/a//b/` becomes `a/b`), which are not allowed in key expressions.
+pub fn normalize_key(key: &str) -> String {
+    key.split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect::<Vec<_>>()
+        .join("/")
+}
+
+/// Checks whether the [key] of a listed object is empty once stripped from the [prefix], that is
+/// whether it only consists of slashes or, with the [KeyLayout::FullKey] layout, of the prefix.
+pub fn is_empty_key(prefix: Option<&String>, key: &str, options: &KeyOptions) -> bool {
+    let key = match prefix {
+        Some(prefix) => normalize_key(&join_prefix_separator(prefix, key, options)),
+        None => normalize_key(key),
+    };
+    match prefix {
+        Some(prefix) if options.layout == KeyLayout::FullKey => {
+            key.is_empty() || key == normalize_key(prefix)
+        }
+        _ => key.is_empty(),
+    }
+}
+
+/// Checks whether the object key has empty segments other than a leading slash, that is
+/// trailing or doubled slashes.
+pub fn has_empty_segments(key: &str) -> bool {
+    let key = key.strip_prefix('/').unwrap_or(key);
+    key.is_empty() || key.ends_with('/') || key.contains("//")
+}
+
+/// Returns the value of the parameter [name] among the selector [parameters] (e.g.
+/// `a=1&b=2`), if specified.
+pub fn get_parameter<'a>(parameters: &'a str, name: &str) -> Option<&'a str> {
+    parameters
+        .split(['&', ';'])
+        .filter_map(|parameter| parameter.split_once('='))
+        .find_map(|(key, value)| (key == name).then_some(value))
+}
+
+/// Returns the object key of [key] within the prefix of the [origin], used when the origins of the
+/// values are isolated. It is hashed if it exceeds [max_length] bytes (see [fit_object_key]).
+pub fn origin_key(origin: &str, key: &str, max_length: usize) -> String {
+    fit_object_key(
+        format!("{origin}/{}", key.trim_start_matches('/')),
+        max_length,
+    )
+}
+
+/// Strips the prefix of the origin from the [object_key] of a value whose origin is isolated.
+pub fn strip_origin(object_key: &str) -> &str {
+    object_key
+        .split_once('/')
+        .map_or(object_key, |(_, key)| key)
+}
+
+// Separator of the timestamp suffix of the object keys, when the timestamps of the values are
+// stored in their keys. Zenoh keys can't contain it, so it can't be mistaken for a part of a key.
+pub const TIMESTAMP_SUFFIX_SEPARATOR: char = '#';
+
+// Length of the hex-encoded NTP64 time of a timestamp suffix.
+const TIMESTAMP_SUFFIX_TIME_LENGTH: usize = 16;
+
+// Maximum length of a timestamp suffix: the separator, the time, a dash and an id of up to 16
+// bytes hex-encoded.
+pub const MAX_TIMESTAMP_SUFFIX_LENGTH: usize = 1 + TIMESTAMP_SUFFIX_TIME_LENGTH + 1 + 32;
+
+/// Returns the object key of the version of [object_key] with the given [timestamp], suffixed
+/// with the timestamp, e.g. `a/b#<time>-<id>`. The time is hex-encoded with a fixed width, hence
+/// the versions of a key sort lexicographically in the order of their timestamps.
+pub fn timestamp_suffixed_key(object_key: &str, timestamp: &Timestamp) -> String {
+    format!(
+        "{object_key}{TIMESTAMP_SUFFIX_SEPARATOR}{:016x}-{}",
+        timestamp.get_time().as_u64(),
+        timestamp.get_id()
+    )
+}
+
+/// Strips the timestamp suffix added by [timestamp_suffixed_key] from the [object_key], returning
+/// None if it has none.
+pub fn strip_timestamp_suffix(object_key: &str) -> Option<&str> {
+    let (key, suffix) = object_key.rsplit_once(TIMESTAMP_SUFFIX_SEPARATOR)?;
+    let (time, id) = suffix.split_once('-')?;
+    (time.len() == TIMESTAMP_SUFFIX_TIME_LENGTH
+        && !id.is_empty()
+        && time
+            .bytes()
+            .chain(id.bytes())
+            .all(|byte| byte.is_ascii_hexdigit()))
+    .then_some(key)
+}
+
+/// Percent-encodes an object key to be used in a URL (e.g. as the source of a copy) or in the
+/// metadata of an object, keeping its slashes as they are.
+pub fn percent_encode_key(key: &str) -> String {
+    key.bytes()
+        .map(|byte| match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' | b'/' => {
+                (byte as char).to_string()
+            }
+            _ => format!("%{byte:02X}"),
+        })
+        .collect()
+}
+
+/// Returns the FIPS endpoint of S3 in the [region], which is only available in the US and Canada
+/// regions of the standard partition, and in the regions of the GovCloud partition (`us-gov-*`).
+/// Both share the `amazonaws.com` DNS suffix, unlike the China (`cn-*`) and ISO (`us-iso*-*`)
+/// partitions which have no FIPS endpoint for S3.
+pub fn fips_endpoint(region: &str) -> ZResult<String> {
+    let is_valid_region = !region.is_empty()
+        && region
+            .chars()
+            .all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-');
+    if !is_valid_region {
+        return Err(zerror!("Invalid region '{region}'.").into());
+    }
+    let has_fips_endpoint = region.starts_with("us-gov-")
+        || (region.starts_with("us-") && !region.starts_with("us-iso"))
+        || region.starts_with("ca-");
+    if !has_fips_endpoint {
+        return Err(zerror!("S3 has no FIPS endpoint in region '{region}'.").into());
+    }
+    Ok(format!("https://s3-fips.{region}.amazonaws.com"))
+}
+
+/// Returns the host and port of the [endpoint] (e.g. `https://minio:9000`), or of the default
+/// endpoint of S3 in the [region] if None.
+pub fn endpoint_authority(endpoint: Option<&str>, region: Option<&str>) -> ZResult<(String, u16)> {
+    let endpoint = match (endpoint, region) {
+        (Some(endpoint), _) => endpoint.to_owned(),
+        (None, Some(region)) if !region.is_empty() => format!("https://s3.{region}.amazonaws.com"),
+        (None, _) => "https://s3.amazonaws.com".to_string(),
+    };
+    let uri = http::Uri::from_str(&endpoint)
+        .map_err(|e| zerror!("Invalid endpoint '{endpoint}': {e}"))?;
+    let host = uri
+        .host()
+        .ok_or_else(|| zerror!("Endpoint '{endpoint}' has no host."))?;
+    let port = uri.port_u16().unwrap_or(match uri.scheme_str() {
+        Some("http") => 80,
+        _ => 443,
+    });
+    Ok((host.to_owned(), port))
+}
+
+/// Returns the endpoint of S3 configured for the [profile] in the [content] of an AWS shared
+/// config file, that is the `endpoint_url` of the `s3` service in the `services` section referred
+/// to by the profile, or else the `endpoint_url` of the profile itself.
+///
+/// Only the subset of the format required to locate these properties is parsed: sections,
+/// `key = value` properties and the indented properties of a sub-section (e.g. `s3 =`).
+pub fn shared_config_endpoint(content: &str, profile: &str) -> Option<String> {
+    // Properties by section, the nested ones being keyed as `<sub-section>.<key>`.
+    let mut sections: HashMap<String, HashMap<String, String>> = HashMap::new();
+    let mut section: Option<String> = None;
+    let mut sub_section: Option<String> = None;
+    for line in content.lines() {
+        let is_indented = line.starts_with(char::is_whitespace);
+        let line = line.trim();
+        if line.is_empty() || line.starts_with('#') || line.starts_with(';') {
+            continue;
+        }
+        if let Some(name) = line.strip_prefix('[').and_then(|l| l.strip_suffix(']')) {
+            section = Some(name.split_whitespace().collect::<Vec<_>>().join(" "));
+            sub_section = None;
+            continue;
+        }
+        let (section, (key, value)) = match (&section, line.split_once('=')) {
+            (Some(section), Some(property)) => (section, property),
+            _ => continue,
+        };
+        let (key, value) = (key.trim(), value.trim());
+        let properties = sections.entry(section.to_owned()).or_default();
+        match &sub_section {
+            Some(sub_section) if is_indented => {
+                properties.insert(format!("{sub_section}.{key}"), value.to_string());
+            }
+            _ if value.is_empty() => sub_section = Some(key.to_string()),
+            _ => {
+                sub_section = None;
+                properties.insert(key.to_string(), value.to_string());
+            }
+        }
+    }
+
+    let profile = match profile {
+        "default" => sections
+            .get("default")
+            .or_else(|| sections.get("profile default")),
+        profile => sections.get(&format!("profile {profile}")),
+    }?;
+    profile
+        .get("services")
+        .and_then(|services| sections.get(&format!("services {services}")))
+        .and_then(|services| services.get("s3.endpoint_url"))
+        .or_else(|| profile.get("endpoint_url"))
+        .filter(|endpoint| !endpoint.is_empty())
+        .cloned()
+}
+
+// Prefix and suffix of the metadata values encoded in base64, following the encoded-word syntax
+// of RFC 2047 which S3 itself uses to return the non-ASCII metadata values.
+const ENCODED_METADATA_PREFIX: &str = "=?UTF-8?B?";
+const ENCODED_METADATA_SUFFIX: &str = "?=";
+
+/// Encodes the metadata [value] in base64 if it can't be sent as-is in a header, that is if it
+/// contains non-printable or non-ASCII characters, or leading or trailing spaces (which would be
+/// trimmed). Values which would be mistaken for encoded ones are encoded as well.
+pub fn encode_metadata_value(value: String) -> String {
+    let is_header_safe = value.bytes().all(|byte| (b' '..=b'~').contains(&byte))
+        && value.trim() == value
+        && !(value.starts_with(ENCODED_METADATA_PREFIX)
+            && value.ends_with(ENCODED_METADATA_SUFFIX));
+    if is_header_safe {
+        return value;
+    }
+    format!(
+        "{ENCODED_METADATA_PREFIX}{}{ENCODED_METADATA_SUFFIX}",
+        general_purpose::STANDARD.encode(value)
+    )
+}
+
+/// Decodes the metadata [value] if it was encoded by [encode_metadata_value], returning it as-is
+/// otherwise.
+pub fn decode_metadata_value(value: String) -> ZResult<String> {
+    let encoded = match value
+        .strip_prefix(ENCODED_METADATA_PREFIX)
+        .and_then(|value| value.strip_suffix(ENCODED_METADATA_SUFFIX))
+    {
+        Some(encoded) => encoded,
+        None => return Ok(value),
+    };
+    let decoded = general_purpose::STANDARD
+        .decode(encoded)
+        .map_err(|e| zerror!("Invalid encoded metadata value '{value}': {e}"))?;
+    String::from_utf8(decoded)
+        .map_err(|e| zerror!("Invalid encoded metadata value '{value}': {e}").into())
+}
+
+// Maximum length in bytes of an object key allowed by S3.
+pub const MAX_OBJECT_KEY_LENGTH: usize = 1024;
+
+// Separator between the truncated object key and the hash of the complete key. It can't appear in
+// a key expression, hence in the object keys of the values stored by this backend.
+const HASHED_KEY_SEPARATOR: char = '#';
+
+// Length of the hex-encoded SHA-256 hash of the complete key.
+const KEY_HASH_LENGTH: usize = 64;
+
+// Minimum maximum length of the object keys, so that the hashed keys keep a part of the complete
+// key besides the hash.
+pub const MIN_OBJECT_KEY_LENGTH: usize = 128;
+
+/// Returns the [object_key] if it fits within [max_length] bytes (the length allowed by S3 by
+/// default), otherwise the object key truncated and suffixed with the hash of the complete key,
+/// e.g. `a/b/c...#<sha256>`.
+///
+/// The mapping is deterministic, hence the same key is always stored under the same object, but
+/// the complete key can't be recovered from the object key alone: it is stored in the metadata
+/// of the object (see [is_hashed_object_key]).
+pub fn fit_object_key(object_key: String, max_length: usize) -> String {
+    if object_key.len() <= max_length {
+        return object_key;
+    }
+    let mut end = max_length - KEY_HASH_LENGTH - HASHED_KEY_SEPARATOR.len_utf8();
+    while !object_key.is_char_boundary(end) {
+        end -= 1;
+    }
+    format!(
+        "{}{HASHED_KEY_SEPARATOR}{}",
+        &object_key[..end],
+        key_hash(&object_key)
+    )
+}
+
+/// Returns the hex-encoded SHA-256 hash of the [object_key].
+fn key_hash(object_key: &str) -> String {
+    format!("{:x}", Sha256::digest(object_key.as_bytes()))
+}
+
+// Length of the prefix of the hash, or of the key itself, logged for a redacted key.
+const REDACTED_KEY_LENGTH: usize = 16;
+
+/// Returns the [key] as logged according to the [LogKeys] redaction, e.g. `sha256:<prefix>` for
+/// [LogKeys::Hash] or `a/b/c...` for [LogKeys::Truncate].
+pub fn redact_key(key: &str, log_keys: LogKeys) -> Cow<'_, str> {
+    match log_keys {
+        LogKeys::Plain => Cow::Borrowed(key),
+        LogKeys::Hash => Cow::Owned(format!("sha256:{}", &key_hash(key)[..REDACTED_KEY_LENGTH])),
+        LogKeys::Truncate if key.len() <= REDACTED_KEY_LENGTH => Cow::Borrowed(key),
+        LogKeys::Truncate => {
+            let mut end = REDACTED_KEY_LENGTH;
+            while !key.is_char_boundary(end) {
+                end -= 1;
+            }
+            Cow::Owned(format!("{}...", &key[..end]))
+        }
+    }
+}
+
+// Content-Types assigned by S3 and the gateways to the objects stored without any.
+const UNTYPED_CONTENT_TYPES: [&str; 2] = ["binary/octet-stream", "application/octet-stream"];
+
+// Magic numbers of the sniffed formats, with their MIME type.
+const MAGIC_NUMBERS: [(&[u8], &str); 8] = [
+    (b"\x89PNG\r\n\x1a\n", "image/png"),
+    (b"\xff\xd8\xff", "image/jpeg"),
+    (b"GIF87a", "image/gif"),
+    (b"GIF89a", "image/gif"),
+    (b"%PDF-", "application/pdf"),
+    (b"\x1f\x8b", "application/gzip"),
+    (b"PK\x03\x04", "application/zip"),
+    (b"<?xml", "application/xml"),
+];
+
+/// Checks whether the [content_type] of an object is missing or assigned by default to the
+/// objects stored without any.
+pub fn is_untyped(content_type: Option<&str>) -> bool {
+    content_type.map_or(true, |content_type| {
+        UNTYPED_CONTENT_TYPES.contains(&content_type)
+    })
+}
+
+/// Infers the MIME type of the [body] of an object from its first [max_bytes] bytes: the magic
+/// numbers of common binary formats, or JSON for a UTF-8 text starting with `{` or `[`. Returns
+/// None if the type can't be inferred.
+pub fn sniff_content_type(body: &[u8], max_bytes: usize) -> Option<&'static str> {
+    let head = &body[..body.len().min(max_bytes)];
+    if let Some((_, content_type)) = MAGIC_NUMBERS
+        .iter()
+        .find(|(magic_number, _)| head.starts_with(magic_number))
+    {
+        return Some(content_type);
+    }
+    // The head may end in the middle of a character.
+    let text = match std::str::from_utf8(head) {
+        Ok(text) => text,
+        Err(e) if e.error_len().is_none() => std::str::from_utf8(&head[..e.valid_up_to()]).ok()?,
+        Err(_) => return None,
+    };
+    match text.trim_start().chars().next() {
+        Some('{') | Some('[') => Some("application/json"),
+        _ => None,
+    }
+}
+
+/// Returns the base64-encoded SHA-256 of the [body] of an object.
+pub fn content_checksum(body: &[u8]) -> String {
+    general_purpose::STANDARD.encode(Sha256::digest(body))
+}
+
+/// Returns the [content_checksum] of the body of an object made of the successive [chunks].
+pub fn content_checksum_of<'a>(chunks: impl IntoIterator<Item = &'a [u8]>) -> String {
+    let mut hasher = Sha256::new();
+    chunks.into_iter().for_each(|chunk| hasher.update(chunk));
+    general_purpose::STANDARD.encode(hasher.finalize())
+}
+
+/// Checks whether the [object_key] was truncated and hashed by [fit_object_key].
+pub fn is_hashed_object_key(object_key: &str) -> bool {
+    object_key
+        .rsplit_once(HASHED_KEY_SEPARATOR)
+        .map_or(false, |(_, hash)| {
+            hash.len() == KEY_HASH_LENGTH && hash.bytes().all(|byte| byte.is_ascii_hexdigit())
+        })
+}
+
+/// Decodes an object key encoded with [percent_encode_key].
+pub fn percent_decode_key(encoded: &str) -> ZResult<String> {
+    let mut bytes = Vec::with_capacity(encoded.len());
+    let mut chars = encoded.bytes();
+    while let Some(byte) = chars.next() {
+        match byte {
+            b'%' => {
+                let hex = [chars.next(), chars.next()];
+                let decoded = match hex {
+                    [Some(high), Some(low)] => std::str::from_utf8(&[high, low])
+                        .ok()
+                        .and_then(|hex| u8::from_str_radix(hex, 16).ok()),
+                    _ => None,
+                };
+                bytes.push(decoded.ok_or_else(|| zerror!("Invalid encoded key '{encoded}'"))?);
+            }
+            byte => bytes.push(byte),
+        }
+    }
+    String::from_utf8(bytes).map_err(|e| zerror!("Invalid encoded key '{encoded}': {e}").into())
+}
+
+impl S3Key<'_> {
+    /// Returns the object key of this key before it is fitted within the length allowed by S3
+    /// (see [fit_object_key]).
+    pub fn full_object_key(&self) -> String {
+        let object_key = match self.options.layout {
+            KeyLayout::S3 => self.prefix.map_or_else(
+                // For compatibility purposes between Amazon S3 and MinIO S3 implementations we
+                // trim the '/' character.
+                || self.key_expr.trim_start_matches('/').to_owned(),
+                |prefix| self.key_expr.trim_start_matches(prefix).to_owned(),
+            ),
+            KeyLayout::NoLeadingSlash => self
+                .prefix
+                .map_or(self.key_expr.as_str(), |prefix| {
+                    self.key_expr.trim_start_matches(prefix)
+                })
+                .trim_start_matches('/')
+                .to_owned(),
+            KeyLayout::FullKey => self.key_expr.to_string(),
+        };
+        let object_key = self.options.apply_leading_slash(object_key);
+        match self.prefix {
+            Some(prefix) => split_prefix_separator(prefix, object_key, self.options),
+            None => object_key,
+        }
     }
 }
 
 impl From<S3Key<'_>> for String {
     fn from(s3_key: S3Key) -> Self {
-        s3_key.prefix.map_or_else(
-            // For compatibility purposes between Amazon S3 and MinIO S3 implementations we trim
-            // the '/' character.
-            || s3_key.key_expr.trim_start_matches('/').to_owned(),
-            |prefix| s3_key.key_expr.trim_start_matches(prefix).to_owned(),
-        )
+        let object_key = s3_key.options.apply_hash_segment(s3_key.full_object_key());
+        fit_object_key(object_key, s3_key.options.max_length)
     }
 }
 
@@ -62,3 +783,1237 @@ impl std::fmt::Display for S3Key<'_> {
         }
     }
 }
+
+/// Runs the [operation], retrying it with an exponential backoff while it fails with an error
+/// considered retryable by [is_retryable], up to `retry.max_retries` times.
+///
+/// The backoff starts at `retry.initial_backoff` and is multiplied by `retry.multiplier` after
+/// each retry, up to `retry.max_backoff`. Each delay is extended by a random jitter of up to half
+/// of it, so that concurrent operations failing at once (e.g. several storages creating the same
+/// bucket upon startup) don't retry in lockstep.
+///
+/// When a [budget] is specified, each retry withdraws tokens from it and the operation fails fast
+/// once it is exhausted, while each success deposits a token back.
+///
+/// The [description] of the operation is used for logging purposes.
+pub(crate) async fn retry_with_backoff<T, E, F, Fut>(
+    retry: &RetryConfig,
+    budget: Option<&RetryBudget>,
+    description: &str,
+    is_retryable: impl Fn(&E) -> bool,
+    mut operation: F,
+) -> Result<T, E>
+where
+    E: fmt::Display,
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, E>>,
+{
+    let mut backoff = retry.initial_backoff;
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Err(err) if attempt < retry.max_retries && is_retryable(&err) => {
+                if budget.map_or(false, |budget| !budget.withdraw()) {
+                    tracing::debug!("{description} failed: {err}. Retry budget exhausted.");
+                    return Err(err);
+                }
+                attempt += 1;
+                let delay = backoff + jitter(backoff / 2);
+                tracing::debug!(
+                    "{description} failed ({attempt}/{}): {err}. Retrying in {delay:?}...",
+                    retry.max_retries
+                );
+                tokio::time::sleep(delay).await;
+                backoff = retry.next_backoff(backoff);
+            }
+            Ok(output) => {
+                if let Some(budget) = budget {
+                    budget.deposit();
+                }
+                return Ok(output);
+            }
+            result => return result,
+        }
+    }
+}
+
+// Amount of tokens withdrawn from a [RetryBudget] by a retry.
+const RETRY_COST: usize = 5;
+
+/// Token bucket bounding the retries of the operations of a client, to avoid retry storms under
+/// sustained throttling: each retry withdraws [RETRY_COST] tokens, each successful operation
+/// deposits one token back, and the operations fail fast without retrying while the bucket is
+/// empty.
+pub(crate) struct RetryBudget {
+    capacity: usize,
+    tokens: Mutex<usize>,
+}
+
+impl RetryBudget {
+    /// Creates a new instance of [RetryBudget] of [capacity] tokens, initially full.
+    pub fn new(capacity: usize) -> Self {
+        RetryBudget {
+            capacity,
+            tokens: Mutex::new(capacity),
+        }
+    }
+
+    /// Withdraws the cost of a retry from the budget, returning false if it is exhausted.
+    pub fn withdraw(&self) -> bool {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        match tokens.checked_sub(RETRY_COST) {
+            Some(remaining) => {
+                *tokens = remaining;
+                true
+            }
+            None => false,
+        }
+    }
+
+    /// Deposits a token back into the budget after a successful operation.
+    pub fn deposit(&self) {
+        let mut tokens = self.tokens.lock().unwrap_or_else(|e| e.into_inner());
+        *tokens = (*tokens + 1).min(self.capacity);
+    }
+
+    /// Returns the amount of tokens currently available.
+    pub fn level(&self) -> usize {
+        *self.tokens.lock().unwrap_or_else(|e| e.into_inner())
+    }
+}
+
+/// Returns a random duration between zero and [max].
+fn jitter(max: Duration) -> Duration {
+    // The hasher of each RandomState is randomly seeded, which is enough to spread the retries.
+    let random = RandomState::new().build_hasher().finish();
+    max.mul_f64(random as f64 / u64::MAX as f64)
+}
+
+/// Failover between the ordered endpoints of a client: the requests are sent to the active
+/// endpoint, initially the first one, which moves to the next endpoint (wrapping around) upon a
+/// failure to connect to it. The failovers stop after [max_failovers] consecutive ones without a
+/// successful connection, so that a client whose endpoints are all unreachable doesn't keep
+/// cycling through them.
+pub(crate) struct EndpointFailover {
+    endpoints: Vec<String>,
+    active: AtomicUsize,
+    consecutive_failovers: AtomicUsize,
+    max_failovers: usize,
+}
+
+impl EndpointFailover {
+    /// Creates a new instance of [EndpointFailover] between the [endpoints], in order of
+    /// preference.
+    pub fn new(endpoints: Vec<String>, max_failovers: usize) -> Self {
+        EndpointFailover {
+            endpoints,
+            active: AtomicUsize::new(0),
+            consecutive_failovers: AtomicUsize::new(0),
+            max_failovers,
+        }
+    }
+
+    /// Returns the index of the active endpoint.
+    pub fn active(&self) -> usize {
+        self.active.load(Ordering::Relaxed)
+    }
+
+    /// Returns the active endpoint.
+    pub fn active_endpoint(&self) -> &str {
+        &self.endpoints[self.active()]
+    }
+
+    /// Records a successful connection to the endpoint of index [index].
+    pub fn on_connected(&self, index: usize) {
+        if index == self.active() {
+            self.consecutive_failovers.store(0, Ordering::Relaxed);
+        }
+    }
+
+    /// Records a failure to connect to the endpoint of index [index], failing over to the next
+    /// endpoint if it's the active one. The concurrent failures of the same endpoint only fail
+    /// over once.
+    pub fn on_connection_failure(&self, index: usize, error: &dyn fmt::Display) {
+        if self.consecutive_failovers.load(Ordering::Relaxed) >= self.max_failovers {
+            tracing::debug!(
+                "Connection to endpoint '{}' failed: {error}. No failover left.",
+                self.endpoints[index]
+            );
+            return;
+        }
+        let next = (index + 1) % self.endpoints.len();
+        if self
+            .active
+            .compare_exchange(index, next, Ordering::Relaxed, Ordering::Relaxed)
+            .is_ok()
+        {
+            self.consecutive_failovers.fetch_add(1, Ordering::Relaxed);
+            tracing::warn!(
+                "Connection to endpoint '{}' failed: {error}. Failing over to '{}'.",
+                self.endpoints[index],
+                self.endpoints[next]
+            );
+        }
+    }
+}
+
+/// Body of an object to be uploaded, held in memory or, when it exceeds the threshold of the
+/// `upload_spill`, written to a temporary file which is deleted once the body is dropped.
+pub(crate) enum UploadBody {
+    Memory(Vec<u8>),
+    File { file: NamedTempFile, size: usize },
+}
+
+impl UploadBody {
+    /// Writes the body made of the successive [chunks] to a temporary file in the directory of
+    /// the [config].
+    pub fn spill<'a>(
+        chunks: impl IntoIterator<Item = &'a [u8]>,
+        config: &UploadSpillConfig,
+    ) -> ZResult<Self> {
+        let dir = config.dir.to_owned().unwrap_or_else(std::env::temp_dir);
+        let file = NamedTempFile::new_in(&dir).map_err(|e| {
+            zerror!(
+                "Couldn't create a temporary file in '{}': {e}",
+                dir.display()
+            )
+        })?;
+        let mut size = 0;
+        let mut writer = BufWriter::new(file.as_file());
+        for chunk in chunks {
+            writer.write_all(chunk).map_err(|e| {
+                zerror!(
+                    "Couldn't write to temporary file '{}': {e}",
+                    file.path().display()
+                )
+            })?;
+            size += chunk.len();
+        }
+        writer.flush().map_err(|e| {
+            zerror!(
+                "Couldn't write to temporary file '{}': {e}",
+                file.path().display()
+            )
+        })?;
+        drop(writer);
+        Ok(UploadBody::File { file, size })
+    }
+
+    /// Returns the size of the body in bytes.
+    pub fn size(&self) -> usize {
+        match self {
+            UploadBody::Memory(body) => body.len(),
+            UploadBody::File { size, .. } => *size,
+        }
+    }
+
+    /// Returns the bytes of the [range] of the body, read from the temporary file if spilled.
+    pub fn range(&self, range: Range<usize>) -> ZResult<Cow<'_, [u8]>> {
+        let file = match self {
+            UploadBody::Memory(body) => return Ok(Cow::Borrowed(&body[range])),
+            UploadBody::File { file, .. } => file,
+        };
+        // A distinct handle per read, so that the parts read concurrently don't share a cursor.
+        let read = |file: &NamedTempFile| -> std::io::Result<Vec<u8>> {
+            let mut reader = file.reopen()?;
+            reader.seek(SeekFrom::Start(range.start as u64))?;
+            let mut chunk = vec![0; range.len()];
+            reader.read_exact(&mut chunk)?;
+            Ok(chunk)
+        };
+        read(file).map(Cow::Owned).map_err(|e| {
+            zerror!(
+                "Couldn't read temporary file '{}': {e}",
+                file.path().display()
+            )
+            .into()
+        })
+    }
+}
+
+/// Budget of bytes that can be uploaded at once, shared among the storages of a volume so that
+/// many concurrent large puts don't exhaust the memory.
+#[derive(Clone)]
+pub(crate) struct UploadBudget {
+    semaphore: Arc<Semaphore>,
+    capacity: u32,
+}
+
+impl UploadBudget {
+    /// Creates a new instance of [UploadBudget] of [capacity] bytes.
+    pub fn new(capacity: u32) -> Self {
+        UploadBudget {
+            semaphore: Arc::new(Semaphore::new(capacity as usize)),
+            capacity,
+        }
+    }
+
+    /// Waits until [bytes] are available in the budget and reserves them until the returned
+    /// permit is dropped.
+    ///
+    /// A value larger than the whole budget reserves the whole budget, so it is uploaded alone.
+    pub async fn acquire(&self, bytes: usize) -> ZResult<SemaphorePermit<'_>> {
+        let permits = u32::try_from(bytes).map_or(self.capacity, |x| x.min(self.capacity));
+        self.semaphore
+            .acquire_many(permits)
+            .await
+            .map_err(|e| zerror!("Couldn't acquire {permits} bytes of upload budget: {e}").into())
+    }
+}
+
+// Window over which the current rate of a [RateLimiter] is measured.
+const RATE_WINDOW: Duration = Duration::from_secs(1);
+
+/// Token bucket limiting the rate of the requests of a client, refilled continuously at the
+/// configured rate and holding up to one second worth of tokens. A request beyond the rate waits
+/// until a token is available, unless that would take longer than the maximum wait.
+pub(crate) struct RateLimiter {
+    config: RateLimitConfig,
+    state: Mutex<RateLimiterState>,
+}
+
+struct RateLimiterState {
+    // May be negative, when tokens were reserved by the waiting requests.
+    tokens: f64,
+    refilled_at: Instant,
+    // Instants at which the requests of the last second were (or will be) let through.
+    admissions: VecDeque<Instant>,
+}
+
+impl RateLimiter {
+    /// Creates a new instance of [RateLimiter], initially full.
+    pub fn new(config: RateLimitConfig) -> Self {
+        let tokens = config.ops_per_second as f64;
+        RateLimiter {
+            config,
+            state: Mutex::new(RateLimiterState {
+                tokens,
+                refilled_at: Instant::now(),
+                admissions: VecDeque::new(),
+            }),
+        }
+    }
+
+    /// Waits until a request can be sent without exceeding the rate, failing if that would take
+    /// longer than the maximum wait.
+    pub async fn acquire(&self) -> ZResult<()> {
+        let wait = {
+            let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+            let rate = self.config.ops_per_second as f64;
+            let now = Instant::now();
+            let refill = now.duration_since(state.refilled_at).as_secs_f64() * rate;
+            state.tokens = (state.tokens + refill).min(rate);
+            state.refilled_at = now;
+            let wait = Duration::from_secs_f64((1.0 - state.tokens).max(0.0) / rate);
+            if wait > self.config.max_wait {
+                return Err(zerror!(
+                    "Rate limit of {} operations per second exceeded: the request would wait {:?}, \
+                    more than the maximum of {:?}.",
+                    self.config.ops_per_second,
+                    wait,
+                    self.config.max_wait
+                )
+                .into());
+            }
+            // The token is reserved now, so that the requests waiting concurrently queue up.
+            state.tokens -= 1.0;
+            state.admissions.push_back(now + wait);
+            while state.admissions.front().map_or(false, |admission| {
+                now.saturating_duration_since(*admission) >= RATE_WINDOW
+            }) {
+                state.admissions.pop_front();
+            }
+            wait
+        };
+        if !wait.is_zero() {
+            tokio::time::sleep(wait).await;
+        }
+        Ok(())
+    }
+
+    /// Returns the amount of requests let through during the last second.
+    pub fn current_rate(&self) -> usize {
+        let state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        let now = Instant::now();
+        state
+            .admissions
+            .iter()
+            .filter(|admission| **admission <= now && now.duration_since(**admission) < RATE_WINDOW)
+            .count()
+    }
+}
+
+// Upper bounds (in microseconds) of the buckets of the latency histograms of [OperationMetrics],
+// the latencies beyond the last one being counted in an extra overflow bucket.
+const LATENCY_BUCKETS_US: [u64; 16] = [
+    500, 1_000, 2_500, 5_000, 10_000, 25_000, 50_000, 100_000, 250_000, 500_000, 1_000_000,
+    2_500_000, 5_000_000, 10_000_000, 25_000_000, 60_000_000,
+];
+
+// Percentiles of the latencies reported by [OperationMetrics].
+const LATENCY_PERCENTILES: [(&str, f64); 3] = [("p50_ms", 0.5), ("p90_ms", 0.9), ("p99_ms", 0.99)];
+
+/// Cumulative metrics of the operations of a client, namely the amount of operations, of failed
+/// ones and the histogram of their latencies, from which the percentiles are estimated with a
+/// bounded memory. The metrics are exposed through a snapshot, only refreshed upon [publish].
+#[derive(Default)]
+pub(crate) struct OperationMetrics {
+    operations: Mutex<BTreeMap<String, OperationStats>>,
+    snapshot: Mutex<serde_json::Value>,
+}
+
+#[derive(Default)]
+struct OperationStats {
+    count: u64,
+    errors: u64,
+    total_latency: Duration,
+    buckets: [u64; LATENCY_BUCKETS_US.len() + 1],
+}
+
+impl OperationStats {
+    /// Estimates the [percentile] of the latencies as the upper bound of the bucket in which it
+    /// falls, in milliseconds, or None if it falls in the overflow bucket.
+    fn latency_percentile(&self, percentile: f64) -> Option<f64> {
+        let rank = (self.count as f64 * percentile).ceil().max(1.0) as u64;
+        let mut cumulated = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            cumulated += count;
+            if cumulated >= rank {
+                return LATENCY_BUCKETS_US
+                    .get(bucket)
+                    .map(|bound| *bound as f64 / 1000.0);
+            }
+        }
+        None
+    }
+
+    fn to_json_value(&self) -> serde_json::Value {
+        let mut value = serde_json::json!({
+            "count": self.count,
+            "errors": self.errors,
+            "mean_ms": self.total_latency.as_secs_f64() * 1000.0 / self.count.max(1) as f64,
+        });
+        if let Some(value) = value.as_object_mut() {
+            for (name, percentile) in LATENCY_PERCENTILES {
+                let latency = match self.latency_percentile(percentile) {
+                    Some(latency) => latency.into(),
+                    // Beyond the last bucket: reported as the bound it exceeds.
+                    None => format!(
+                        ">{}",
+                        LATENCY_BUCKETS_US[LATENCY_BUCKETS_US.len() - 1] / 1000
+                    )
+                    .into(),
+                };
+                value.insert(name.to_string(), latency);
+            }
+        }
+        value
+    }
+}
+
+impl OperationMetrics {
+    /// Records an [operation] which lasted [latency], failed unless [succeeded].
+    pub fn record(&self, operation: &str, latency: Duration, succeeded: bool) {
+        if let Ok(mut operations) = self.operations.lock() {
+            let stats = operations.entry(operation.to_lowercase()).or_default();
+            stats.count += 1;
+            if !succeeded {
+                stats.errors += 1;
+            }
+            stats.total_latency += latency;
+            let latency_us = u64::try_from(latency.as_micros()).unwrap_or(u64::MAX);
+            let bucket = LATENCY_BUCKETS_US
+                .iter()
+                .position(|bound| latency_us <= *bound)
+                .unwrap_or(LATENCY_BUCKETS_US.len());
+            stats.buckets[bucket] += 1;
+        }
+    }
+
+    /// Refreshes the snapshot of the metrics with those recorded so far.
+    pub fn publish(&self) {
+        let snapshot = match self.operations.lock() {
+            Ok(operations) => operations
+                .iter()
+                .map(|(operation, stats)| (operation.to_owned(), stats.to_json_value()))
+                .collect::<serde_json::Map<_, _>>()
+                .into(),
+            Err(_) => return,
+        };
+        if let Ok(mut published) = self.snapshot.lock() {
+            *published = snapshot;
+        }
+    }
+
+    /// Returns the last published snapshot of the metrics, null if none was published yet.
+    pub fn snapshot(&self) -> serde_json::Value {
+        self.snapshot
+            .lock()
+            .map_or(serde_json::Value::Null, |snapshot| snapshot.to_owned())
+    }
+}
+
+/// Generator of the timestamps synthesized for the objects lacking one, strictly increasing
+/// during the lifetime of the generator (i.e. within a run stamping the objects), so that the
+/// stamped objects are ordered consistently upon the alignment of the replicas.
+pub(crate) struct TimestampSynthesizer {
+    source: TimestampSource,
+    hlc: uhlc::HLC,
+    // Time of the last timestamp of the wall clock.
+    last_time: Mutex<uhlc::NTP64>,
+}
+
+impl TimestampSynthesizer {
+    /// Creates a new instance of [TimestampSynthesizer] from the clock [source], with a random id.
+    pub fn new(source: TimestampSource) -> Self {
+        TimestampSynthesizer {
+            source,
+            hlc: uhlc::HLC::default(),
+            last_time: Mutex::new(uhlc::NTP64(0)),
+        }
+    }
+
+    /// Returns a new timestamp, more recent than all the previous ones.
+    pub fn new_timestamp(&self) -> Timestamp {
+        match self.source {
+            TimestampSource::Hlc => self.hlc.new_timestamp(),
+            TimestampSource::WallClock => {
+                let now = uhlc::NTP64::from(
+                    SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default(),
+                );
+                let mut last_time = self.last_time.lock().unwrap_or_else(|e| e.into_inner());
+                *last_time = now.max(uhlc::NTP64(last_time.0 + 1));
+                Timestamp::new(*last_time, *self.hlc.get_id())
+            }
+        }
+    }
+
+    /// Returns the timestamp of the given [time] since the UNIX epoch (e.g. the last modification
+    /// date of an object), with the id of the generator.
+    pub fn timestamp_at(&self, time: Duration) -> Timestamp {
+        Timestamp::new(uhlc::NTP64::from(time), *self.hlc.get_id())
+    }
+}
+
+// Estimated memory (in bytes) taken by an entry of [EntriesSpill], besides its key.
+const SPILLED_ENTRY_OVERHEAD: usize = 96;
+
+/// Collector of the entries returned by `get_all_entries`, keeping the most recent timestamp of
+/// each key. When configured, the entries are spilled as runs sorted by key to temporary files
+/// once their estimated size exceeds the memory cap, and the runs are merged back at the end, so
+/// that the memory used to deduplicate the entries stays bounded.
+pub(crate) struct EntriesSpill {
+    config: Option<EntriesSpillConfig>,
+    entries: HashMap<Option<OwnedKeyExpr>, Timestamp>,
+    memory_bytes: usize,
+    // Temporary files of the spilled runs, deleted once dropped.
+    runs: Vec<File>,
+}
+
+impl EntriesSpill {
+    /// Creates a new empty instance of [EntriesSpill], spilling according to the [config].
+    pub fn new(config: Option<EntriesSpillConfig>) -> Self {
+        EntriesSpill {
+            config,
+            entries: HashMap::new(),
+            memory_bytes: 0,
+            runs: vec![],
+        }
+    }
+
+    /// Adds the entry of the [key_expr], unless a more recent one was added, spilling the entries
+    /// held in memory if they now exceed the memory cap.
+    pub fn insert(&mut self, key_expr: Option<OwnedKeyExpr>, timestamp: Timestamp) -> ZResult<()> {
+        let size = key_expr.as_ref().map_or(0, |key_expr| key_expr.len()) + SPILLED_ENTRY_OVERHEAD;
+        match self.entries.get_mut(&key_expr) {
+            Some(latest) => {
+                if *latest < timestamp {
+                    *latest = timestamp;
+                }
+            }
+            None => {
+                self.entries.insert(key_expr, timestamp);
+                self.memory_bytes += size;
+            }
+        }
+        match &self.config {
+            Some(config) if self.memory_bytes > config.max_memory_bytes => self.spill(),
+            _ => Ok(()),
+        }
+    }
+
+    /// Returns the entries, merging the spilled runs with those held in memory if any.
+    pub fn into_entries(mut self) -> ZResult<Vec<(Option<OwnedKeyExpr>, Timestamp)>> {
+        if self.runs.is_empty() {
+            return Ok(self.entries.into_iter().collect());
+        }
+        self.spill()?;
+        tracing::debug!("Merging {} spilled runs of entries.", self.runs.len());
+        let mut runs = self
+            .runs
+            .into_iter()
+            .map(|mut run| {
+                run.seek(SeekFrom::Start(0))
+                    .map_err(|e| zerror!("Couldn't read spilled entries: {e}"))?;
+                Ok(BufReader::new(run).lines())
+            })
+            .collect::<ZResult<Vec<_>>>()?;
+        let mut heads = BinaryHeap::new();
+        for (index, run) in runs.iter_mut().enumerate() {
+            if let Some((key, timestamp)) = read_spilled_entry(run)? {
+                heads.push(Reverse((key, timestamp, index)));
+            }
+        }
+        let mut entries: Vec<(Option<OwnedKeyExpr>, Timestamp)> = vec![];
+        while let Some(Reverse((key, timestamp, index))) = heads.pop() {
+            // The runs being sorted by key, the entries of a key are merged consecutively.
+            match entries.last_mut() {
+                Some((last, latest)) if last.as_ref().map(|x| x.as_str()) == key.as_deref() => {
+                    if *latest < timestamp {
+                        *latest = timestamp;
+                    }
+                }
+                _ => {
+                    let key_expr = key
+                        .map(OwnedKeyExpr::try_from)
+                        .transpose()
+                        .map_err(|e| zerror!("Invalid spilled key: {e}"))?;
+                    entries.push((key_expr, timestamp));
+                }
+            }
+            if let Some((key, timestamp)) = read_spilled_entry(&mut runs[index])? {
+                heads.push(Reverse((key, timestamp, index)));
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Writes the entries held in memory, sorted by key, as a new run in a temporary file.
+    fn spill(&mut self) -> ZResult<()> {
+        let dir = self
+            .config
+            .as_ref()
+            .and_then(|config| config.dir.to_owned())
+            .unwrap_or_else(std::env::temp_dir);
+        let file = tempfile::tempfile_in(&dir).map_err(|e| {
+            zerror!(
+                "Couldn't create a temporary file in '{}': {e}",
+                dir.display()
+            )
+        })?;
+        let mut entries: Vec<_> = self.entries.drain().collect();
+        entries.sort_unstable_by(|(a, _), (b, _)| {
+            a.as_ref()
+                .map(|x| x.as_str())
+                .cmp(&b.as_ref().map(|x| x.as_str()))
+        });
+        let mut writer = BufWriter::new(file);
+        for (key_expr, timestamp) in &entries {
+            let line = serde_json::to_string(&(
+                key_expr.as_ref().map(|x| x.as_str()),
+                timestamp.to_string(),
+            ))
+            .map_err(|e| zerror!("Couldn't serialize spilled entry: {e}"))?;
+            writeln!(writer, "{line}").map_err(|e| zerror!("Couldn't spill entries: {e}"))?;
+        }
+        let file = writer
+            .into_inner()
+            .map_err(|e| zerror!("Couldn't spill entries: {e}"))?;
+        tracing::debug!(
+            "Spilled {} entries ({} bytes in memory) to '{}'.",
+            entries.len(),
+            self.memory_bytes,
+            dir.display()
+        );
+        self.runs.push(file);
+        self.memory_bytes = 0;
+        Ok(())
+    }
+}
+
+/// Reads the next entry of a spilled run, None at the end of the run.
+fn read_spilled_entry(
+    run: &mut Lines<BufReader<File>>,
+) -> ZResult<Option<(Option<String>, Timestamp)>> {
+    let line = match run.next() {
+        Some(line) => line.map_err(|e| zerror!("Couldn't read spilled entries: {e}"))?,
+        None => return Ok(None),
+    };
+    let (key, timestamp): (Option<String>, String) =
+        serde_json::from_str(&line).map_err(|e| zerror!("Invalid spilled entry '{line}': {e}"))?;
+    let timestamp = Timestamp::from_str(&timestamp)
+        .map_err(|e| zerror!("Invalid spilled timestamp '{timestamp}': {e:?}"))?;
+    Ok(Some((key, timestamp)))
+}
+
+/// Keys deleted recently, considered absent during a grace period even if the S3 gateway still
+/// returns their objects, so that a get following a delete is consistent on eventually consistent
+/// gateways.
+pub(crate) struct DeletedKeys {
+    grace_period: Duration,
+    keys: Mutex<HashMap<String, Instant>>,
+}
+
+impl DeletedKeys {
+    /// Creates a new empty instance of [DeletedKeys] whose keys are tracked for [grace_period].
+    pub fn new(grace_period: Duration) -> Self {
+        DeletedKeys {
+            grace_period,
+            keys: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Tracks the [key] as just deleted, forgetting the keys whose grace period is over.
+    pub fn insert(&self, key: &str) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.retain(|_, deleted_at| deleted_at.elapsed() <= self.grace_period);
+            keys.insert(key.to_string(), Instant::now());
+        }
+    }
+
+    /// Stops tracking the [key], after it was written again.
+    pub fn remove(&self, key: &str) {
+        if let Ok(mut keys) = self.keys.lock() {
+            keys.remove(key);
+        }
+    }
+
+    /// Checks whether the [key] was deleted less than the grace period ago.
+    pub fn contains(&self, key: &str) -> bool {
+        self.keys.lock().map_or(false, |keys| {
+            keys.get(key).map_or(false, |deleted_at| {
+                deleted_at.elapsed() <= self.grace_period
+            })
+        })
+    }
+}
+
+/// Index of the entries returned by `get_all_entries`, mapping the key of each listed object to the
+/// key expression and the timestamp retrieved from its metadata, along with the ETag and the last
+/// modification date of the object when it was indexed. As long as the listing reports the same
+/// ETag and date for the object, its entry is taken from the index rather than from a HEAD request.
+pub(crate) struct EntriesIndex {
+    entries: Mutex<HashMap<String, IndexedEntry>>,
+}
+
+struct IndexedEntry {
+    etag: String,
+    last_modified: i128,
+    key_expr: OwnedKeyExpr,
+    timestamp: Timestamp,
+}
+
+impl EntriesIndex {
+    /// Creates a new empty instance of [EntriesIndex].
+    pub fn new() -> Self {
+        EntriesIndex {
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Returns the indexed entry of the [object_key], if its object wasn't modified since, i.e. if
+    /// it still has the same [etag] and [last_modified] date.
+    pub fn get(
+        &self,
+        object_key: &str,
+        etag: &str,
+        last_modified: i128,
+    ) -> Option<(OwnedKeyExpr, Timestamp)> {
+        let entries = self.entries.lock().ok()?;
+        entries
+            .get(object_key)
+            .filter(|entry| entry.etag == etag && entry.last_modified == last_modified)
+            .map(|entry| (entry.key_expr.to_owned(), entry.timestamp))
+    }
+
+    /// Indexes the entry of the [object_key], whose object has the [etag] and [last_modified] date.
+    pub fn insert(
+        &self,
+        object_key: String,
+        etag: String,
+        last_modified: i128,
+        key_expr: OwnedKeyExpr,
+        timestamp: Timestamp,
+    ) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.insert(
+                object_key,
+                IndexedEntry {
+                    etag,
+                    last_modified,
+                    key_expr,
+                    timestamp,
+                },
+            );
+        }
+    }
+
+    /// Forgets the entries whose objects are not in the [listed] ones anymore.
+    pub fn retain_listed(&self, listed: &HashSet<&str>) {
+        if let Ok(mut entries) = self.entries.lock() {
+            entries.retain(|object_key, _| listed.contains(object_key.as_str()));
+        }
+    }
+}
+
+/// In-memory LRU cache of the objects retrieved with a get, bounded by an amount of entries and
+/// of bytes, whose entries expire after a time to live.
+///
+/// The cache is invalidated on the writes to a key, and an object being fetched while the cache is
+/// invalidated is not cached, so that a stale object never overrides a newer write.
+pub(crate) struct GetCache {
+    config: GetCacheConfig,
+    inner: Mutex<GetCacheInner>,
+}
+
+#[derive(Default)]
+struct GetCacheInner {
+    entries: HashMap<String, GetCacheEntry>,
+    // Keys of the entries ordered from the least to the most recently used.
+    recency: BTreeMap<u64, String>,
+    // Counter incremented upon each use of an entry, ordering the recency of the entries.
+    tick: u64,
+    // Counter incremented upon each invalidation, used to discard the objects being fetched
+    // meanwhile.
+    generation: u64,
+    bytes: usize,
+}
+
+struct GetCacheEntry {
+    object: Arc<ObjectContent>,
+    inserted_at: Instant,
+    tick: u64,
+}
+
+impl GetCache {
+    /// Creates a new empty instance of [GetCache].
+    pub fn new(config: GetCacheConfig) -> Self {
+        GetCache {
+            config,
+            inner: Mutex::new(GetCacheInner::default()),
+        }
+    }
+
+    /// Returns the cached object associated to the [key] if it is not expired, along with the
+    /// current generation of the cache to be passed to [GetCache::insert] upon a miss.
+    pub fn get(&self, key: &str) -> (Option<Arc<ObjectContent>>, u64) {
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return (None, 0),
+        };
+        let generation = inner.generation;
+        let ttl = self.config.ttl;
+        let tick = inner.tick + 1;
+        let (object, previous_tick) = match inner.entries.get_mut(key) {
+            Some(entry) if entry.inserted_at.elapsed() <= ttl => {
+                let previous_tick = entry.tick;
+                entry.tick = tick;
+                (Some(entry.object.clone()), previous_tick)
+            }
+            Some(_) => {
+                inner.remove(key);
+                return (None, generation);
+            }
+            None => return (None, generation),
+        };
+        inner.tick = tick;
+        inner.recency.remove(&previous_tick);
+        inner.recency.insert(tick, key.to_string());
+        (object, generation)
+    }
+
+    /// Caches the [object] associated to the [key], unless the cache was invalidated since the
+    /// [generation] returned by [GetCache::get], evicting the least recently used entries when
+    /// the bounds of the cache are exceeded.
+    pub fn insert(&self, key: &str, object: Arc<ObjectContent>, generation: u64) {
+        if object.body.len() > self.config.max_bytes {
+            return;
+        }
+        let mut inner = match self.inner.lock() {
+            Ok(inner) => inner,
+            Err(_) => return,
+        };
+        if inner.generation != generation {
+            return;
+        }
+        inner.remove(key);
+        while inner.entries.len() >= self.config.max_entries
+            || inner.bytes + object.body.len() > self.config.max_bytes
+        {
+            match inner.recency.first_key_value() {
+                Some((_, oldest)) => {
+                    let oldest = oldest.to_owned();
+                    inner.remove(&oldest);
+                }
+                None => break,
+            }
+        }
+        inner.tick += 1;
+        let tick = inner.tick;
+        inner.bytes += object.body.len();
+        inner.recency.insert(tick, key.to_string());
+        inner.entries.insert(
+            key.to_string(),
+            GetCacheEntry {
+                object,
+                inserted_at: Instant::now(),
+                tick,
+            },
+        );
+    }
+
+    /// Removes the object associated to the [key] from the cache, discarding the objects being
+    /// fetched meanwhile.
+    pub fn invalidate(&self, key: &str) {
+        if let Ok(mut inner) = self.inner.lock() {
+            inner.generation += 1;
+            inner.remove(key);
+        }
+    }
+
+    /// Removes all the objects from the cache.
+    pub fn clear(&self) {
+        if let Ok(mut inner) = self.inner.lock() {
+            let generation = inner.generation + 1;
+            *inner = GetCacheInner {
+                generation,
+                ..Default::default()
+            };
+        }
+    }
+}
+
+impl GetCacheInner {
+    fn remove(&mut self, key: &str) {
+        if let Some(entry) = self.entries.remove(key) {
+            self.recency.remove(&entry.tick);
+            self.bytes -= entry.object.body.len();
+        }
+    }
+}
+
+/// Tracker of the write operations in flight, so that the closure of the storage can wait for
+/// them to complete.
+#[derive(Clone, Default)]
+pub(crate) struct PendingOperations {
+    inner: Arc<(Mutex<usize>, Condvar)>,
+}
+
+/// Guard of a pending operation, which is considered completed once dropped.
+pub(crate) struct PendingOperation {
+    operations: PendingOperations,
+}
+
+impl PendingOperations {
+    /// Registers a new pending operation, completed when the returned guard is dropped.
+    pub fn start(&self) -> PendingOperation {
+        if let Ok(mut pending) = self.inner.0.lock() {
+            *pending += 1;
+        }
+        PendingOperation {
+            operations: self.clone(),
+        }
+    }
+
+    /// Blocks until all the pending operations are completed or the [timeout] expires, returning
+    /// the amount of operations still pending.
+    pub fn flush(&self, timeout: Duration) -> usize {
+        let (pending, completed) = &*self.inner;
+        let pending = match pending.lock() {
+            Ok(pending) => pending,
+            Err(_) => return 0,
+        };
+        match completed.wait_timeout_while(pending, timeout, |pending| *pending > 0) {
+            Ok((pending, _)) => *pending,
+            Err(_) => 0,
+        }
+    }
+}
+
+impl Drop for PendingOperation {
+    fn drop(&mut self) {
+        let (pending, completed) = &*self.operations.inner;
+        if let Ok(mut pending) = pending.lock() {
+            *pending -= 1;
+            if *pending == 0 {
+                completed.notify_all();
+            }
+        }
+    }
+}
+
+/// Maintenance operation of a storage, triggered by a query with the `s3_operation` parameter.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) enum MaintenanceOperation {
+    /// Deletion of the superseded versions of the objects (`compact`).
+    Compact,
+    /// Audit of the timestamps stored in the metadata of the objects (`verify`).
+    Verify,
+    /// Re-encryption of the objects with the configured SSE-KMS key (`reencrypt`).
+    Reencrypt,
+    /// Download of the objects to the configured local directory (`export`).
+    Export,
+}
+
+impl MaintenanceOperation {
+    pub fn name(&self) -> &'static str {
+        match self {
+            MaintenanceOperation::Compact => "compact",
+            MaintenanceOperation::Verify => "verify",
+            MaintenanceOperation::Reencrypt => "reencrypt",
+            MaintenanceOperation::Export => "export",
+        }
+    }
+}
+
+impl FromStr for MaintenanceOperation {
+    type Err = Box<dyn std::error::Error + Send + Sync>;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "compact" => Ok(MaintenanceOperation::Compact),
+            "verify" => Ok(MaintenanceOperation::Verify),
+            "reencrypt" => Ok(MaintenanceOperation::Reencrypt),
+            "export" => Ok(MaintenanceOperation::Export),
+            _ => Err(zerror!(
+                "Unsupported maintenance operation '{s}', expected \"compact\", \"verify\", \
+                \"reencrypt\" or \"export\"."
+            )
+            .into()),
+        }
+    }
+}
+
+/// Reports of the maintenance operations of a storage, exposed in its admin status: the state
+/// (`running`, `completed` or `failed`) of the last run of each operation, along with its
+/// progress or result.
+#[derive(Clone, Default)]
+pub(crate) struct MaintenanceReports {
+    reports: Arc<Mutex<BTreeMap<&'static str, serde_json::Value>>>,
+}
+
+impl MaintenanceReports {
+    /// Marks the [operation] as running, unless it already is, in which case false is returned.
+    pub fn start(&self, operation: MaintenanceOperation) -> bool {
+        let mut reports = self.reports.lock().unwrap_or_else(|e| e.into_inner());
+        let running = reports
+            .get(operation.name())
+            .map_or(false, |report| report["state"] == "running");
+        if !running {
+            reports.insert(operation.name(), serde_json::json!({ "state": "running" }));
+        }
+        !running
+    }
+
+    /// Records the result of the [operation], whose report is either the fields of its result or
+    /// its error.
+    pub fn complete(&self, operation: MaintenanceOperation, result: &ZResult<serde_json::Value>) {
+        match result {
+            Ok(report) => self.set(operation, "completed", report.to_owned()),
+            Err(e) => self.set(
+                operation,
+                "failed",
+                serde_json::json!({ "error": e.to_string() }),
+            ),
+        }
+    }
+
+    fn set(&self, operation: MaintenanceOperation, state: &str, report: serde_json::Value) {
+        let mut report = match report {
+            serde_json::Value::Object(report) => report,
+            _ => serde_json::Map::new(),
+        };
+        report.insert("state".to_string(), state.into());
+        self.reports
+            .lock()
+            .unwrap_or_else(|e| e.into_inner())
+            .insert(operation.name(), report.into());
+    }
+
+    /// Returns the reports of the operations run so far, if any.
+    pub fn snapshot(&self) -> Option<serde_json::Value> {
+        let reports = self.reports.lock().unwrap_or_else(|e| e.into_inner());
+        (!reports.is_empty()).then(|| {
+            reports
+                .iter()
+                .map(|(name, report)| (name.to_string(), report.to_owned()))
+                .collect::<serde_json::Map<_, _>>()
+                .into()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn closing_waits_for_the_pending_operations() {
+        let pending_operations = PendingOperations::default();
+        let operation = pending_operations.start();
+        let start = Instant::now();
+        let completion = std::thread::spawn(move || {
+            std::thread::sleep(Duration::from_millis(100));
+            drop(operation);
+        });
+        assert_eq!(pending_operations.flush(Duration::from_secs(5)), 0);
+        assert!(start.elapsed() >= Duration::from_millis(100));
+        completion.join().unwrap();
+
+        // The operations still pending after the timeout are reported.
+        let _operation = pending_operations.start();
+        assert_eq!(pending_operations.flush(Duration::from_millis(10)), 1);
+    }
+
+    #[test]
+    fn metadata_values_round_trip_whatever_their_bytes() {
+        for value in ["1.5/ABCD", "é\u{0}\u{7f}\n", " padded ", "=?UTF-8?B?MQ==?="] {
+            let encoded = encode_metadata_value(value.to_string());
+            assert!(
+                encoded.bytes().all(|byte| (b' '..=b'~').contains(&byte)),
+                "{encoded}"
+            );
+            assert_eq!(decode_metadata_value(encoded).unwrap(), value);
+        }
+        // The header-safe values are stored as they are.
+        assert_eq!(encode_metadata_value("1.5/ABCD".to_string()), "1.5/ABCD");
+        assert!(decode_metadata_value("=?UTF-8?B?not base64?=".to_string()).is_err());
+    }
+
+    #[test]
+    fn fips_endpoints_are_resolved_in_the_partition_of_the_region() {
+        for region in [
+            "us-gov-west-1",
+            "us-gov-east-1",
+            "us-east-1",
+            "ca-central-1",
+        ] {
+            assert_eq!(
+                fips_endpoint(region).unwrap(),
+                format!("https://s3-fips.{region}.amazonaws.com")
+            );
+        }
+        for region in [
+            "eu-west-1",
+            "us-iso-east-1",
+            "cn-north-1",
+            "US-GOV-WEST-1",
+            "",
+        ] {
+            assert!(fips_endpoint(region).is_err(), "{region}");
+        }
+    }
+
+    #[test]
+    fn shared_config_endpoints_prefer_the_s3_service_over_the_profile() {
+        let content = "\
+            [default]\n\
+            endpoint_url = http://default:9000\n\
+            \n\
+            [profile minio]\n\
+            endpoint_url = http://profile:9000\n\
+            services = local\n\
+            \n\
+            [profile other]\n\
+            services = missing\n\
+            \n\
+            [services local]\n\
+            dynamodb =\n  endpoint_url = http://dynamodb:8000\n\
+            s3 =\n  endpoint_url = http://s3:9000\n";
+        assert_eq!(
+            shared_config_endpoint(content, "minio").as_deref(),
+            Some("http://s3:9000")
+        );
+        assert_eq!(
+            shared_config_endpoint(content, "default").as_deref(),
+            Some("http://default:9000")
+        );
+        assert_eq!(shared_config_endpoint(content, "other"), None);
+        assert_eq!(shared_config_endpoint(content, "unknown"), None);
+    }
+
+    #[test]
+    fn hash_segments_round_trip_and_spread_the_keys() {
+        let options = KeyOptions {
+            hash_prefix_length: Some(2),
+            ..Default::default()
+        };
+        let mut segments = HashSet::new();
+        for object_key in ["/a/b", "a/b", "/sensor/1", "/sensor/2", "/é/ü", "/"] {
+            let hashed = options.apply_hash_segment(object_key.to_string());
+            let (segment, _) = hashed.split_once('/').unwrap();
+            assert_eq!(segment.len(), 2, "{hashed}");
+            assert_eq!(
+                options.strip_hash_segment(&hashed).as_deref(),
+                Some(object_key),
+                "{hashed}"
+            );
+            segments.insert(segment.to_string());
+        }
+        // The hash of the key with and without leading slash differ.
+        assert_ne!(
+            options.apply_hash_segment("/a/b".to_string()),
+            options.apply_hash_segment("a/b".to_string())
+        );
+        assert!(segments.len() > 1, "{segments:?}");
+        // The objects not written with the same settings don't match their segment.
+        assert_eq!(options.strip_hash_segment("zz/a/b"), None);
+        assert_eq!(options.strip_hash_segment("abc/a/b"), None);
+        assert_eq!(options.strip_hash_segment("a"), None);
+
+        // Sequential keys are spread across the prefixes.
+        let segments: HashSet<_> = (0..256)
+            .map(|i| options.apply_hash_segment(format!("/sensor/{i}"))[..2].to_string())
+            .collect();
+        assert!(segments.len() > 64, "{}", segments.len());
+    }
+
+    #[test]
+    fn timestamp_suffixes_round_trip_and_sort_in_time_ranges() {
+        let id = uhlc::ID::try_from(&[1u8][..]).unwrap();
+        let key = |secs| {
+            let timestamp = Timestamp::new(uhlc::NTP64::from(Duration::from_secs(secs)), id);
+            timestamp_suffixed_key("/a/b", &timestamp)
+        };
+        let keys: Vec<_> = [9, 10, 300, 70000].into_iter().map(key).collect();
+        for suffixed in &keys {
+            assert_eq!(strip_timestamp_suffix(suffixed), Some("/a/b"));
+            assert!(suffixed.len() <= "/a/b".len() + MAX_TIMESTAMP_SUFFIX_LENGTH);
+        }
+        let mut sorted = keys.clone();
+        sorted.sort();
+        assert_eq!(sorted, keys);
+
+        // The versions within a time range are those between the keys of its bounds.
+        let in_range: Vec<_> = keys
+            .iter()
+            .filter(|suffixed| **suffixed >= key(10) && **suffixed <= key(300))
+            .collect();
+        assert_eq!(in_range, [&keys[1], &keys[2]]);
+
+        assert_eq!(strip_timestamp_suffix("/a/b"), None);
+        assert_eq!(strip_timestamp_suffix("/a/b#123-1"), None);
+    }
+
+    #[test]
+    fn redacted_keys_are_hashed_or_truncated_on_char_boundaries() {
+        let key = "tenant-4242/sensors/temperature";
+        assert_eq!(redact_key(key, LogKeys::Plain), key);
+        let hashed = redact_key(key, LogKeys::Hash);
+        assert_eq!(hashed, format!("sha256:{}", &key_hash(key)[..16]));
+        assert!(!hashed.contains("tenant"));
+        assert_eq!(redact_key(key, LogKeys::Truncate), "tenant-4242/sens...");
+        assert_eq!(redact_key("a/b", LogKeys::Truncate), "a/b");
+
+        // The 16th byte is within the 2 bytes of an `é`, which is left out.
+        let key = format!("a{}", "é".repeat(10));
+        assert_eq!(
+            redact_key(&key, LogKeys::Truncate),
+            format!("a{}...", "é".repeat(7))
+        );
+        let key = "日本語のキー/値";
+        assert_eq!(redact_key(key, LogKeys::Truncate), "日本語のキ...");
+    }
+}